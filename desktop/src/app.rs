@@ -1,14 +1,13 @@
-use std::time::Duration;
-
 use eframe::egui::{self, CentralPanel};
 use eframe::{App, CreationContext};
 use egui_dock::{DockArea, DockState, NodeIndex, Style, SurfaceIndex, TabViewer};
-use rodio::buffer::SamplesBuffer;
-use rodio::OutputStream;
-
 use psx::PSXEmulator;
 
+use crate::audio::AudioOutput;
+
 use crate::components::breakpoints::Breakpoints;
+use crate::components::gpu_debugger::GpuDebugger;
+use crate::components::spu_inspector::SpuInspector;
 use crate::components::kernel_logger::KernelLogger;
 use crate::components::{control::*, disassembly::*, tty_logger::*, display::*};
 use crate::input::*;
@@ -16,6 +15,7 @@ use crate::input::*;
 type Tab = String;
 
 pub const BIOS_PATH: &str = "res/SCPH1001.bin";
+pub const WIDESCREEN_GAMES_PATH: &str = "res/widescreen_games.txt";
 
 pub struct FrontendState {
 	psx: PSXEmulator,
@@ -27,13 +27,14 @@ pub struct FrontendState {
 	kernel_logger: KernelLogger,
 	disassembly: Disassembly,
 	breakpoints: Breakpoints,
+	gpu_debugger: GpuDebugger,
+	spu_inspector: SpuInspector,
 
 	input: Input,
 
 	new_breakpoint_open: bool,
 
-	stream_handle: OutputStream,
-	//sink: Sink,
+	audio: AudioOutput,
 }
 
 pub struct Desktop {
@@ -69,7 +70,7 @@ impl App for Desktop {
 		egui::TopBottomPanel::top("Menu Bar").show(ctx, |ui| {
 			egui::menu::bar(ui, |ui| {
 				ui.menu_button("View", |ui| {
-					for tab in &["Disassembly", "TTY Logger", "Kernel Logger", "Breakpoints"] {
+					for tab in &["Disassembly", "TTY Logger", "Kernel Logger", "Breakpoints", "GPU Debugger", "SPU Inspector"] {
 						if ui.button(*tab).clicked() {
 							if let Some(index) = self.tree.find_tab(&tab.to_string()) {
 								self.tree.remove_tab(index);
@@ -84,6 +85,10 @@ impl App for Desktop {
 				ui.separator();
 
 				self.context.input.show_settings(ui, &self.context.psx);
+
+				ui.separator();
+
+				self.context.audio.show_settings(ui);
 			});
 		});
 
@@ -110,13 +115,15 @@ impl TabViewer for FrontendState {
 
 	fn ui(&mut self, ui: &mut egui::Ui, tab: &mut Self::Tab) {
 		match tab.as_str() {
-			"Control" => self.control.show(ui, &mut self.psx, &mut self.tty_logger, &mut self.breakpoints, &mut self.stream_handle),
+			"Control" => self.control.show(ui, &mut self.psx, &mut self.tty_logger, &mut self.breakpoints, &mut self.audio),
 			"Disassembly" => self.disassembly.show(ui, &mut self.psx),
-			"VRAM" => self.vram.show(ui, &self.psx),
-			"Display" => self.display.show(ui, &self.psx),
+			"VRAM" => self.vram.show(ui, &self.psx, self.gpu_debugger.highlight().as_ref()),
+			"Display" => self.display.show(ui, &self.psx, self.gpu_debugger.highlight().as_ref()),
 			"TTY Logger" => self.tty_logger.show(ui, &mut self.psx),
 			"Kernel Logger" => self.kernel_logger.show(ui, &mut self.psx.cpu.kernel_log),
 			"Breakpoints" => self.breakpoints.show(ui, &mut self.psx, &mut self.new_breakpoint_open),
+			"GPU Debugger" => self.gpu_debugger.show(ui, &mut self.psx),
+			"SPU Inspector" => self.spu_inspector.show(ui, &mut self.psx),
 			_ => {
 				ui.label(tab.as_str());
 			}
@@ -132,22 +139,10 @@ impl FrontendState {
 	pub fn new(cc: &CreationContext) -> Self {
 		let bios = std::fs::read(BIOS_PATH).unwrap();
 
-		let stream_handle = rodio::OutputStreamBuilder::open_default_stream().expect("open default audio stream");
-
-		// TODO adjustable volume
-		let sink = rodio::Sink::connect_new(&stream_handle.mixer());
-		sink.set_volume(3.0);																																
-		
-		let audio_callback = Box::new(move |buffer: Vec<f32>| {
-			while sink.len() > 2 {
-				std::thread::sleep(Duration::from_millis(1));
-			}
-
-			sink.append(SamplesBuffer::new(2, 44100, buffer));
-		});
+		let audio = AudioOutput::new();
 
 		#[allow(unused_mut)] 
-		let mut psx = PSXEmulator::new(bios, audio_callback);
+		let mut psx = PSXEmulator::new(bios, audio.callback());
 		Self {
 			psx: psx,
 
@@ -158,19 +153,21 @@ impl FrontendState {
 			kernel_logger: KernelLogger::new(),
 			disassembly: Disassembly::new(),
 			breakpoints: Breakpoints::new(),
+			gpu_debugger: GpuDebugger::new(cc),
+			spu_inspector: SpuInspector::new(),
 
 			input: Input::new(),
 
 			new_breakpoint_open: false,
 
-			stream_handle: stream_handle,
+			audio: audio,
 		}
 
 	}
 
 	fn update(&mut self, ctx: &egui::Context) {
 		self.input.handle_events();
-		self.psx.update_input(self.input.get_input(ctx), self.input.analog_enabled);
+		self.input.update_psx(ctx, &mut self.psx, self.display.pointer());
 
 		if !self.control.paused && !self.psx.breakpoint_hit {
 			self.psx.run_frame();
@@ -0,0 +1,336 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use eframe::egui::{self, Ui};
+use log::*;
+use rodio::{OutputStream, OutputStreamBuilder, Sink, Source};
+
+// the spu output goes through a queue to the audio thread, where it's resampled to the output
+// rate. the resampling ratio is nudged to keep the queue at the configured latency, so small
+// differences between the emulated and host clocks don't end in under/overruns. the emulator
+// only waits when it's so far ahead that the queue reaches its hard cap
+
+const SPU_SAMPLE_RATE: u32 = 44100;
+
+// input frames under the resampling kernel and the number of precomputed kernel phases
+const TAPS: usize = 16;
+const PHASES: usize = 256;
+
+// furthest the playback rate is bent to keep the queue at the target, 0.5% isn't audible
+const MAX_RATE_ADJUST: f64 = 0.005;
+
+// frames taken from the queue at once by the audio thread
+const PULL_LEN: usize = 256;
+
+const DEFAULT_LATENCY_MS: u32 = 60;
+
+// queue length the emulator waits at, in multiples of the target
+const MAX_LEN_FACTOR: usize = 3;
+
+// longest wait for the audio thread, so a stopped sink can't hang the emulator
+const MAX_WAIT: Duration = Duration::from_millis(100);
+
+struct AudioQueue {
+	frames: VecDeque<[f32; 2]>,
+	target_len: usize,
+
+	// shown in the settings
+	rate_adjust: f64,
+	underruns: u64,
+}
+
+impl AudioQueue {
+	fn set_latency(&mut self, latency_ms: u32) {
+		self.target_len = SPU_SAMPLE_RATE as usize * latency_ms as usize / 1000;
+	}
+
+	fn max_len(&self) -> usize {
+		self.target_len * MAX_LEN_FACTOR
+	}
+}
+
+pub struct AudioOutput {
+	stream: OutputStream,
+	sink: Sink,
+	queue: Arc<Mutex<AudioQueue>>,
+	// signalled by the audio thread whenever it takes frames from the queue
+	drained: Arc<Condvar>,
+
+	latency_ms: u32,
+	// resample to the rate of the device instead of leaving it to the mixer
+	device_rate: bool,
+}
+
+impl AudioOutput {
+	pub fn new() -> Self {
+		let stream = OutputStreamBuilder::open_default_stream().expect("open default audio stream");
+
+		let mut queue = AudioQueue {
+			frames: VecDeque::new(),
+			target_len: 0,
+
+			rate_adjust: 0.0,
+			underruns: 0,
+		};
+
+		queue.set_latency(DEFAULT_LATENCY_MS);
+
+		let mut audio = Self {
+			sink: Sink::connect_new(stream.mixer()),
+			stream,
+			queue: Arc::new(Mutex::new(queue)),
+			drained: Arc::new(Condvar::new()),
+
+			latency_ms: DEFAULT_LATENCY_MS,
+			device_rate: true,
+		};
+
+		audio.restart();
+		audio
+	}
+
+	// callback for the emulator, drift is left to the rate control and it only blocks when the
+	// queue is over its hard cap
+	pub fn callback(&self) -> Box<dyn Fn(Vec<f32>)> {
+		let queue = self.queue.clone();
+		let drained = self.drained.clone();
+
+		Box::new(move |buffer: Vec<f32>| {
+			let mut queue = queue.lock().unwrap();
+
+			while queue.frames.len() >= queue.max_len() {
+				let (guard, timeout) = drained.wait_timeout(queue, MAX_WAIT).unwrap();
+				queue = guard;
+
+				if timeout.timed_out() {
+					break;
+				}
+			}
+
+			queue.frames.extend(buffer.chunks_exact(2).map(|frame| [frame[0], frame[1]]));
+		})
+	}
+
+	pub fn show_settings(&mut self, ui: &mut Ui) {
+		ui.menu_button("Audio", |ui| {
+			if ui.add(egui::Slider::new(&mut self.latency_ms, 20..=250).text("Latency (ms)")).changed() {
+				self.queue.lock().unwrap().set_latency(self.latency_ms);
+			}
+
+			let output_rate = self.stream.config().sample_rate();
+
+			if ui.checkbox(&mut self.device_rate, format!("Output at device rate ({output_rate}hz)")).changed() {
+				self.restart();
+			}
+
+			let queue = self.queue.lock().unwrap();
+
+			ui.label(format!(
+				"Buffered: {}ms  Rate: {:+.3}%  Underruns: {}",
+				queue.frames.len() * 1000 / SPU_SAMPLE_RATE as usize,
+				queue.rate_adjust * 100.0,
+				queue.underruns
+			));
+		});
+	}
+
+	// replaces the source playing from the queue, the queue itself is kept so the emulator's
+	// callback stays valid
+	fn restart(&mut self) {
+		let output_rate = match self.device_rate {
+			true => self.stream.config().sample_rate(),
+			false => SPU_SAMPLE_RATE,
+		};
+
+		debug!("audio output at {output_rate}hz");
+
+		self.sink.stop();
+		self.sink = Sink::connect_new(self.stream.mixer());
+
+		// TODO adjustable volume
+		self.sink.set_volume(3.0);
+		self.sink.append(ResampledSource::new(self.queue.clone(), self.drained.clone(), output_rate));
+	}
+}
+
+// windowed sinc resampler pulling from the queue, runs on the audio thread
+struct ResampledSource {
+	queue: Arc<Mutex<AudioQueue>>,
+	drained: Arc<Condvar>,
+	output_rate: u32,
+
+	// PHASES + 1 rows of TAPS weights, the last row is for interpolating up to phase 1
+	kernel: Vec<f32>,
+	// the TAPS input frames around the output position, which sits between the middle two
+	window: VecDeque<[f32; 2]>,
+	// position between the middle two frames
+	phase: f64,
+	step: f64,
+
+	pending: VecDeque<[f32; 2]>,
+	last_frame: [f32; 2],
+	// an underrun is only counted once until the queue refills
+	starved: bool,
+	// right channel of the last frame, samples are interleaved
+	right: Option<f32>,
+}
+
+impl ResampledSource {
+	fn new(queue: Arc<Mutex<AudioQueue>>, drained: Arc<Condvar>, output_rate: u32) -> Self {
+		let ratio = f64::from(SPU_SAMPLE_RATE) / f64::from(output_rate);
+
+		Self {
+			queue,
+			drained,
+			output_rate,
+
+			kernel: build_kernel(ratio),
+			window: VecDeque::from(vec![[0.0; 2]; TAPS]),
+			phase: 0.0,
+			step: ratio,
+
+			pending: VecDeque::with_capacity(PULL_LEN),
+			last_frame: [0.0; 2],
+			starved: false,
+			right: None,
+		}
+	}
+
+	fn next_frame(&mut self) -> [f32; 2] {
+		let row = self.phase * PHASES as f64;
+		let index = (row as usize).min(PHASES - 1);
+		let t = (row - index as f64) as f32;
+
+		let (row_a, row_b) = (&self.kernel[index * TAPS..][..TAPS], &self.kernel[(index + 1) * TAPS..][..TAPS]);
+
+		let mut out = [0.0; 2];
+
+		for (k, frame) in self.window.iter().enumerate() {
+			let weight = row_a[k] + (row_b[k] - row_a[k]) * t;
+
+			out[0] += frame[0] * weight;
+			out[1] += frame[1] * weight;
+		}
+
+		self.phase += self.step;
+
+		while self.phase >= 1.0 {
+			self.phase -= 1.0;
+
+			let frame = self.next_input();
+
+			self.window.pop_front();
+			self.window.push_back(frame);
+		}
+
+		out
+	}
+
+	fn next_input(&mut self) -> [f32; 2] {
+		if self.pending.is_empty() {
+			self.pull();
+		}
+
+		match self.pending.pop_front() {
+			Some(frame) => {
+				self.last_frame = frame;
+				frame
+			},
+			// ran dry, fade out instead of dropping straight to silence
+			None => {
+				self.last_frame = self.last_frame.map(|sample| sample * 0.995);
+				self.last_frame
+			}
+		}
+	}
+
+	fn pull(&mut self) {
+		let mut queue = self.queue.lock().unwrap();
+
+		if queue.frames.is_empty() {
+			if !self.starved {
+				queue.underruns += 1;
+				self.starved = true;
+			}
+
+			return;
+		}
+
+		self.starved = false;
+
+		// consume faster when the queue is above the target and slower when below
+		let error = (queue.frames.len() as f64 - queue.target_len as f64) / queue.target_len.max(1) as f64;
+		let adjust = error.clamp(-1.0, 1.0) * MAX_RATE_ADJUST;
+
+		self.step = f64::from(SPU_SAMPLE_RATE) / f64::from(self.output_rate) * (1.0 + adjust);
+		queue.rate_adjust = adjust;
+
+		let len = queue.frames.len().min(PULL_LEN);
+		self.pending.extend(queue.frames.drain(..len));
+
+		self.drained.notify_one();
+	}
+}
+
+impl Iterator for ResampledSource {
+	type Item = f32;
+
+	fn next(&mut self) -> Option<f32> {
+		if let Some(right) = self.right.take() {
+			return Some(right);
+		}
+
+		let [left, right] = self.next_frame();
+		self.right = Some(right);
+
+		Some(left)
+	}
+}
+
+impl Source for ResampledSource {
+	fn current_span_len(&self) -> Option<usize> {
+		None
+	}
+
+	fn channels(&self) -> u16 {
+		2
+	}
+
+	fn sample_rate(&self) -> u32 {
+		self.output_rate
+	}
+
+	fn total_duration(&self) -> Option<Duration> {
+		None
+	}
+}
+
+// blackman windowed sinc, cut off below the output nyquist when downsampling
+fn build_kernel(ratio: f64) -> Vec<f32> {
+	let cutoff = (1.0 / ratio).min(1.0) * 0.97;
+	let centre = (TAPS / 2 - 1) as f64;
+
+	let mut kernel = Vec::with_capacity((PHASES + 1) * TAPS);
+
+	for phase in 0..=PHASES {
+		let offset = phase as f64 / PHASES as f64;
+
+		let row: Vec<f64> = (0..TAPS).map(|k| {
+			let x = k as f64 - centre - offset;
+			let sinc = if x == 0.0 { 1.0 } else { (PI * cutoff * x).sin() / (PI * cutoff * x) };
+
+			let n = (x + TAPS as f64 / 2.0) / TAPS as f64;
+			let window = 0.42 - 0.5 * (2.0 * PI * n).cos() + 0.08 * (4.0 * PI * n).cos();
+
+			sinc * window
+		}).collect();
+
+		// unity gain at dc for every phase
+		let sum: f64 = row.iter().sum();
+		kernel.extend(row.iter().map(|weight| (weight / sum) as f32));
+	}
+
+	kernel
+}
@@ -0,0 +1,39 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use rcue::parser::parse_from_file;
+
+use psx::cdrom::disc::Disc;
+
+// shared by the command line tools
+
+// loads every track of a cue sheet and any patches next to it, exits with a message if anything is missing
+pub fn load_disc(cue_path: &Path) -> Disc {
+	let cue = parse_from_file(&cue_path.to_string_lossy(), false).unwrap_or_else(|err| {
+		eprintln!("unable to parse {}: {err:?}", cue_path.display());
+		std::process::exit(1);
+	});
+
+	let cue_dir = cue_path.parent().unwrap_or(Path::new("."));
+
+	let tracks = cue.files.into_iter().map(|track| {
+		let mut data = Vec::new();
+
+		File::open(cue_dir.join(&track.file))
+			.and_then(|mut file| file.read_to_end(&mut data))
+			.unwrap_or_else(|err| {
+				eprintln!("unable to read track {}: {err}", track.file);
+				std::process::exit(1);
+			});
+
+		data
+	}).collect();
+
+	let mut disc = Disc::new();
+	disc.add_tracks(tracks);
+	disc.load_subchannel_files(cue_path);
+	disc.load_patch_files(cue_path);
+
+	disc
+}
@@ -0,0 +1,72 @@
+use std::fs;
+use std::path::PathBuf;
+
+use env_logger::*;
+use log::*;
+
+use psx::gpu_dump::{GpuDump, GpuDumpPlayer};
+
+// replays a GPU dump without the rest of the console and prints a hash of vram after every
+// frame, comparing the output of two builds shows which frames a rasterizer change affects.
+// with an output directory the vram of every frame is also written as a ppm image
+//
+// usage: gpu_replay <dump> [output dir]
+
+fn main() {
+	let mut builder = Builder::from_env(Env::default().default_filter_or("psx=warn"));
+	builder.target(Target::Stdout);
+	builder.init();
+
+	let mut args = std::env::args().skip(1);
+
+	let Some(dump_path) = args.next() else {
+		eprintln!("usage: gpu_replay <dump> [output dir]");
+		std::process::exit(1);
+	};
+
+	let out_dir = args.next().map(PathBuf::from);
+
+	let dump = GpuDump::load(&dump_path).unwrap_or_else(|err| {
+		eprintln!("unable to load {dump_path}: {err}");
+		std::process::exit(1);
+	});
+
+	info!("{} frames, {} events", dump.frames(), dump.events.len());
+
+	if let Some(dir) = &out_dir {
+		fs::create_dir_all(dir).expect("unable to create output directory");
+	}
+
+	let mut player = GpuDumpPlayer::new(dump);
+
+	while player.step_frame() {
+		let frame = player.get_frame();
+
+		println!("frame {frame}: {:016x}", hash_vram(player.get_vram()));
+
+		if let Some(dir) = &out_dir {
+			fs::write(dir.join(format!("frame_{frame:04}.ppm")), vram_to_ppm(player.get_vram()))
+				.expect("unable to write frame");
+		}
+	}
+}
+
+// FNV-1a
+fn hash_vram(vram: &[u16]) -> u64 {
+	vram.iter()
+		.flat_map(|pixel| pixel.to_le_bytes())
+		.fold(0xCBF29CE484222325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x100000001B3))
+}
+
+// the whole 1024x512 vram as 15 bit colour
+fn vram_to_ppm(vram: &[u16]) -> Vec<u8> {
+	let mut ppm = b"P6\n1024 512\n255\n".to_vec();
+
+	for pixel in vram {
+		ppm.push(((pixel & 0x1F) << 3) as u8);
+		ppm.push((((pixel >> 5) & 0x1F) << 3) as u8);
+		ppm.push((((pixel >> 10) & 0x1F) << 3) as u8);
+	}
+
+	ppm
+}
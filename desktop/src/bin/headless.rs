@@ -0,0 +1,152 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use env_logger::*;
+use log::*;
+
+use psx::{DeviceType, PSXEmulator};
+use psx::capture::{AvCapture, VideoFormat};
+use psx::serial::open_backend;
+
+// runs a game without a window or audio device and captures the output, for recording gameplay
+// and checking a/v sync. the wav and video always cover the same frames
+//
+// usage: headless [options] <game.cue | program.exe>
+//   --bios <path>     bios image, res/SCPH1001.bin by default
+//   --frames <n>      frames to run, 600 by default
+//   --wav <path>      write the spu output as 16 bit stereo wav
+//   --video <path>    write the display as y4m, or raw rgb24 with --raw
+//   --raw             raw rgb24 instead of y4m
+//   --patch <path>    ppf, ips or xdelta patch applied to the disc, can be given more than once
+//   --serial <spec>   serial port backend, e.g. loopback, tcp-listen:127.0.0.1:5000, tcp:127.0.0.1:5000,
+//                     unix-listen:<path>, unix:<path>, console or console:<device>
+//   --gpu-threads <n> threads for rasterizing large primitives, 1 by default
+//   --multitap <port> multitap with a controller in each slot on port 1 or 2, can be given for both
+//   --accurate-gpustat GPUSTAT from the gpu state instead of the fixed value
+
+const USAGE: &str = "usage: headless [--bios <path>] [--frames <n>] [--wav <path>] [--video <path>] [--raw] [--patch <path>]... [--serial <spec>] [--gpu-threads <n>] [--multitap <port>]... [--accurate-gpustat] <game.cue | program.exe>";
+
+const DEFAULT_BIOS_PATH: &str = "res/SCPH1001.bin";
+const DEFAULT_FRAMES: u64 = 600;
+
+fn main() {
+	let mut builder = Builder::from_env(Env::default().default_filter_or("psx=warn,headless=info"));
+	builder.target(Target::Stdout);
+	builder.init();
+
+	let mut bios_path = PathBuf::from(DEFAULT_BIOS_PATH);
+	let mut frames = DEFAULT_FRAMES;
+	let mut wav_path = None;
+	let mut video_path = None;
+	let mut video_format = VideoFormat::Y4m;
+	let mut patch_paths = Vec::new();
+	let mut serial = None;
+	let mut gpu_threads = 1;
+	let mut multitap_ports = Vec::new();
+	let mut accurate_gpustat = false;
+	let mut game_path = None;
+
+	let mut args = std::env::args().skip(1);
+
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--bios" => bios_path = PathBuf::from(next_arg(&mut args)),
+			"--frames" => frames = next_arg(&mut args).parse().unwrap_or_else(|_| exit_usage()),
+			"--wav" => wav_path = Some(PathBuf::from(next_arg(&mut args))),
+			"--video" => video_path = Some(PathBuf::from(next_arg(&mut args))),
+			"--raw" => video_format = VideoFormat::RawRgb24,
+			"--patch" => patch_paths.push(PathBuf::from(next_arg(&mut args))),
+			"--serial" => serial = Some(next_arg(&mut args)),
+			"--gpu-threads" => gpu_threads = next_arg(&mut args).parse().unwrap_or_else(|_| exit_usage()),
+			"--multitap" => multitap_ports.push(match next_arg(&mut args).as_str() {
+				"1" => 0,
+				"2" => 1,
+				_ => exit_usage(),
+			}),
+			"--accurate-gpustat" => accurate_gpustat = true,
+			_ if arg.starts_with("--") => exit_usage(),
+			_ => game_path = Some(PathBuf::from(arg)),
+		}
+	}
+
+	let Some(game_path) = game_path else {
+		exit_usage();
+	};
+
+	let bios = fs::read(&bios_path).unwrap_or_else(|err| {
+		eprintln!("unable to read bios {}: {err}", bios_path.display());
+		std::process::exit(1);
+	});
+
+	let mut psx = PSXEmulator::new(bios, Box::new(|_| {}));
+
+	psx.set_gpu_threads(gpu_threads);
+	psx.set_accurate_gpustat(accurate_gpustat);
+
+	for port in multitap_ports {
+		psx.set_multitap(port, true);
+
+		for slot in 0..4 {
+			psx.set_device(port, slot, DeviceType::Controller);
+		}
+	}
+
+	if let Some(spec) = serial {
+		psx.set_serial_backend(Some(open_backend(&spec).unwrap_or_else(|err| {
+			eprintln!("unable to open serial backend {spec}: {err}");
+			std::process::exit(1);
+		})));
+	}
+
+	let mut capture = AvCapture::new(wav_path.as_deref(), video_path.as_deref().map(|path| (path, video_format)))
+		.unwrap_or_else(|err| {
+			eprintln!("unable to create capture files: {err}");
+			std::process::exit(1);
+		});
+
+	capture.set_frame_limit(Some(frames));
+
+	let is_exe = game_path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("exe"));
+
+	if is_exe {
+		psx.sideload_exe(fs::read(&game_path).expect("unable to read exe"));
+	} else {
+		let mut disc = common::load_disc(&game_path);
+
+		for path in patch_paths {
+			if let Err(err) = disc.apply_patch_file(&path) {
+				eprintln!("unable to apply patch {}: {err}", path.display());
+				std::process::exit(1);
+			}
+		}
+
+		psx.load_disc(disc);
+	}
+
+	// started after the exe is sideloaded so the capture doesn't include the bios boot
+	psx.start_capture(capture).expect("unable to start capture");
+
+	while psx.get_capture().is_some_and(|capture| !capture.is_finished()) {
+		psx.run_frame();
+	}
+
+	let captured = psx.get_capture().map_or(0, |capture| capture.frames());
+
+	if let Err(err) = psx.stop_capture() {
+		eprintln!("capture failed: {err}");
+		std::process::exit(1);
+	}
+
+	info!("captured {captured} frames");
+}
+
+fn next_arg(args: &mut impl Iterator<Item = String>) -> String {
+	args.next().unwrap_or_else(|| exit_usage())
+}
+
+fn exit_usage() -> ! {
+	eprintln!("{USAGE}");
+	std::process::exit(1);
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use env_logger::*;
+use log::*;
+
+use psx::sample_export::find_disc_samples;
+
+// exports every VAG and VAB sample found on a disc as a wav, with loop points for looped samples,
+// to compare against what the spu plays
+//
+// usage: sample_export <game.cue> <output dir>
+
+fn main() {
+	let mut builder = Builder::from_env(Env::default().default_filter_or("psx=warn,sample_export=info"));
+	builder.target(Target::Stdout);
+	builder.init();
+
+	let mut args = std::env::args().skip(1);
+
+	let (Some(cue_path), Some(out_dir)) = (args.next(), args.next()) else {
+		eprintln!("usage: sample_export <game.cue> <output dir>");
+		std::process::exit(1);
+	};
+
+	let out_dir = PathBuf::from(out_dir);
+	fs::create_dir_all(&out_dir).expect("unable to create output directory");
+
+	let disc = common::load_disc(&PathBuf::from(cue_path));
+	let samples = find_disc_samples(&disc);
+
+	for sample in samples.iter() {
+		let path = out_dir.join(format!("{}.wav", sample.name));
+
+		if let Err(err) = sample.save_wav(&path) {
+			error!("unable to write {}: {err}", path.display());
+		}
+	}
+
+	info!("exported {} samples", samples.len());
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use env_logger::*;
+use log::*;
+
+use psx::capture::{VideoFormat, VideoWriter, WavWriter};
+use psx::str_decoder::{disc_str_sectors, frame_rate, str_file_sectors, StrDecoder};
+
+// decodes an STR movie through the emulated mdec and xa decoder, for checking the decoders
+// against a known-good player
+//
+// usage: str_decode [options] <movie.str | game.cue>
+//   --file <path>       movie in the disc filesystem when a cue is given, e.g. MOVIE/OPEN.STR
+//   --png <dir>         write every frame as a png
+//   --y4m <path>        write the frames as y4m
+//   --wav <path>        write the audio as 44.1khz 16 bit stereo wav
+//   --channel <f>:<c>   xa file and channel of the audio, the first one found by default
+
+const USAGE: &str = "usage: str_decode [--file <path on disc>] [--png <dir>] [--y4m <path>] [--wav <path>] [--channel <file>:<channel>] <movie.str | game.cue>";
+
+fn main() {
+	let mut builder = Builder::from_env(Env::default().default_filter_or("psx=warn,str_decode=info"));
+	builder.target(Target::Stdout);
+	builder.init();
+
+	let mut disc_file = None;
+	let mut png_dir = None;
+	let mut y4m_path = None;
+	let mut wav_path = None;
+	let mut channel = None;
+	let mut input_path = None;
+
+	let mut args = std::env::args().skip(1);
+
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--file" => disc_file = Some(next_arg(&mut args)),
+			"--png" => png_dir = Some(PathBuf::from(next_arg(&mut args))),
+			"--y4m" => y4m_path = Some(PathBuf::from(next_arg(&mut args))),
+			"--wav" => wav_path = Some(PathBuf::from(next_arg(&mut args))),
+			"--channel" => channel = Some(parse_channel(&next_arg(&mut args))),
+			_ if arg.starts_with("--") => exit_usage(),
+			_ => input_path = Some(PathBuf::from(arg)),
+		}
+	}
+
+	let Some(input_path) = input_path else {
+		exit_usage();
+	};
+
+	let is_cue = input_path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("cue"));
+
+	let sectors = if is_cue {
+		let Some(disc_file) = disc_file else {
+			eprintln!("--file is needed to find the movie on a disc");
+			std::process::exit(1);
+		};
+
+		let disc = common::load_disc(&input_path);
+
+		disc_str_sectors(&disc, &disc_file).unwrap_or_else(|| {
+			eprintln!("{disc_file} isn't on the disc");
+			std::process::exit(1);
+		})
+	} else {
+		str_file_sectors(&fs::read(&input_path).unwrap_or_else(|err| {
+			eprintln!("unable to read {}: {err}", input_path.display());
+			std::process::exit(1);
+		}))
+	};
+
+	if let Some(png_dir) = &png_dir {
+		fs::create_dir_all(png_dir).expect("unable to create png directory");
+	}
+
+	let mut y4m = y4m_path.map(|path| VideoWriter::create(&path, VideoFormat::Y4m, frame_rate(&sectors)).unwrap_or_else(|err| {
+		eprintln!("unable to create {}: {err}", path.display());
+		std::process::exit(1);
+	}));
+
+	let mut wav = wav_path.map(|path| WavWriter::create(&path).unwrap_or_else(|err| {
+		eprintln!("unable to create {}: {err}", path.display());
+		std::process::exit(1);
+	}));
+
+	let mut decoder = StrDecoder::new();
+
+	if let Some((file, channel)) = channel {
+		decoder.set_audio_channel(file, channel);
+	}
+
+	let mut frames = 0;
+	let mut samples = 0;
+
+	for sector in sectors.iter() {
+		if let Some(frame) = decoder.push_sector(sector) {
+			debug!("frame {} {}x{} at sector {}, {} mdec cycles", frame.number, frame.width, frame.height, frame.sector, frame.decode_cycles);
+
+			if let Some(png_dir) = &png_dir {
+				let path = png_dir.join(format!("{:05}.png", frame.number));
+
+				if let Err(err) = write_png(&path, frame.width as u32, frame.height as u32, &frame.rgb) {
+					error!("unable to write {}: {err}", path.display());
+				}
+			}
+
+			if let Some(y4m) = &mut y4m {
+				y4m.write_frame(frame.width, frame.height, &frame.rgb).expect("unable to write y4m frame");
+			}
+
+			frames += 1;
+		}
+
+		let audio = decoder.take_audio();
+		samples += audio.len();
+
+		if let Some(wav) = &mut wav {
+			for sample in audio {
+				wav.write_sample(sample).expect("unable to write wav sample");
+			}
+		}
+	}
+
+	if let Some(y4m) = y4m {
+		y4m.finish().expect("unable to finish y4m");
+	}
+
+	if let Some(wav) = wav {
+		wav.finish().expect("unable to finish wav");
+	}
+
+	let (num, den) = frame_rate(&sectors);
+
+	info!("decoded {frames} frames at {:.3}fps and {:.2}s of audio", num as f64 / den as f64, samples as f64 / 44100.0);
+}
+
+fn parse_channel(arg: &str) -> (u8, u8) {
+	arg.split_once(':')
+		.and_then(|(file, channel)| Some((file.parse().ok()?, channel.parse().ok()?)))
+		.unwrap_or_else(|| exit_usage())
+}
+
+fn write_png(path: &Path, width: u32, height: u32, rgb: &[u8]) -> Result<(), png::EncodingError> {
+	let file = std::io::BufWriter::new(fs::File::create(path)?);
+
+	let mut encoder = png::Encoder::new(file, width, height);
+	encoder.set_color(png::ColorType::Rgb);
+	encoder.set_depth(png::BitDepth::Eight);
+
+	encoder.write_header()?.write_image_data(rgb)
+}
+
+fn next_arg(args: &mut impl Iterator<Item = String>) -> String {
+	args.next().unwrap_or_else(|| exit_usage())
+}
+
+fn exit_usage() -> ! {
+	eprintln!("{USAGE}");
+	std::process::exit(1);
+}
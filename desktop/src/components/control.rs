@@ -1,19 +1,19 @@
+use std::collections::BTreeSet;
 use std::fs::{self, File};
 use std::io::Read;
-use std::path::PathBuf;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
 
-use eframe::egui::Ui;
+use eframe::egui::{ComboBox, DragValue, TextEdit, Ui};
 use rfd::FileDialog;
 use rcue::parser::parse_from_file;
-use rodio::buffer::SamplesBuffer;
-use rodio::OutputStream;
 use log::*;
 
-use psx::PSXEmulator;
+use psx::{GpuModel, PSXEmulator};
+use psx::serial::open_backend;
 use psx::cdrom::disc::Disc;
 
-use crate::app::BIOS_PATH;
+use crate::app::{BIOS_PATH, WIDESCREEN_GAMES_PATH};
+use crate::audio::AudioOutput;
 use crate::components::breakpoints::Breakpoints;
 use crate::components::tty_logger::TTYLogger;
 
@@ -21,6 +21,22 @@ pub struct Control {
 	pub paused: bool,
 	pub step: bool,
 	muted: bool,
+	resolution_scale: u32,
+	gpu_model: GpuModel,
+	gpu_threads: usize,
+	accurate_gpustat: bool,
+	pgxp: bool,
+	texture_cache: bool,
+	fast_cd: bool,
+	widescreen: bool,
+
+	// serial port backend, see psx::serial::open_backend
+	serial_spec: String,
+	serial_connected: bool,
+
+	// game id of the loaded disc and the games widescreen is enabled for
+	game_id: Option<String>,
+	widescreen_games: BTreeSet<String>,
 }
 
 impl Control {
@@ -29,10 +45,26 @@ impl Control {
 			paused: true,
 			step: false,
 			muted: false,
+			resolution_scale: 1,
+			gpu_model: GpuModel::V0,
+			gpu_threads: 1,
+			accurate_gpustat: false,
+			pgxp: false,
+			texture_cache: true,
+			fast_cd: false,
+			widescreen: false,
+
+			serial_spec: "tcp-listen:127.0.0.1:5000".to_string(),
+			serial_connected: false,
+
+			game_id: None,
+			widescreen_games: fs::read_to_string(WIDESCREEN_GAMES_PATH)
+				.map(|list| list.lines().map(|line| line.trim().to_string()).filter(|id| !id.is_empty()).collect())
+				.unwrap_or_default(),
 		}
 	}
 
-	pub fn show(&mut self, ui: &mut Ui, psx: &mut PSXEmulator, tty: &mut TTYLogger, breakpoints: &mut Breakpoints, stream_handle: &mut OutputStream) {
+	pub fn show(&mut self, ui: &mut Ui, psx: &mut PSXEmulator, tty: &mut TTYLogger, breakpoints: &mut Breakpoints, audio: &mut AudioOutput) {
 		ui.strong("Control");
 
 		ui.horizontal(|ui| {
@@ -56,18 +88,101 @@ impl Control {
 				let exe_path = self.select_file(("EXE File", &["exe", "ps-exe"]));
 
 				if let Some(exe) = exe_path {
-					self.reset_emu(psx, tty, breakpoints, stream_handle);
+					self.reset_emu(psx, tty, breakpoints, audio);
 					psx.sideload_exe(fs::read(exe).unwrap());
 				}
 			}
 
 			if ui.button("Reset").clicked() {
-				self.reset_emu(psx, tty, breakpoints, stream_handle);
+				self.reset_emu(psx, tty, breakpoints, audio);
 			}
 
 			if ui.checkbox(&mut self.muted, "Mute").changed() {
 				psx.bus.spu.emu_mute = self.muted;
 			}
+
+			if ui.button(if psx.is_gpu_recording() { "Stop GPU Dump" } else { "Record GPU Dump" }).clicked() {
+				if psx.is_gpu_recording() {
+					self.save_gpu_dump(psx);
+				} else {
+					psx.start_gpu_recording();
+				}
+			}
+		});
+
+		ui.horizontal(|ui| {
+			let old_scale = self.resolution_scale;
+
+			ComboBox::from_label("Resolution")
+				.selected_text(format!("{}x", self.resolution_scale))
+				.show_ui(ui, |ui| {
+					for scale in [1, 2, 4, 8] {
+						ui.selectable_value(&mut self.resolution_scale, scale, format!("{scale}x"));
+					}
+				});
+
+			if self.resolution_scale != old_scale {
+				psx.set_resolution_scale(self.resolution_scale);
+			}
+
+			let old_model = self.gpu_model;
+
+			ComboBox::from_label("GPU")
+				.selected_text(format!("{:?}", self.gpu_model))
+				.show_ui(ui, |ui| {
+					ui.selectable_value(&mut self.gpu_model, GpuModel::V0, "V0 (160-pin)");
+					ui.selectable_value(&mut self.gpu_model, GpuModel::V2, "V2 (208-pin)");
+				});
+
+			if self.gpu_model != old_model {
+				psx.set_gpu_model(self.gpu_model);
+			}
+
+			let max_threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+
+			if ui.add(DragValue::new(&mut self.gpu_threads).range(1..=max_threads).prefix("GPU Threads: ")).changed() {
+				psx.set_gpu_threads(self.gpu_threads);
+			}
+
+			if ui.checkbox(&mut self.accurate_gpustat, "Accurate GPUSTAT").changed() {
+				psx.set_accurate_gpustat(self.accurate_gpustat);
+			}
+
+			if ui.checkbox(&mut self.pgxp, "PGXP").changed() {
+				psx.set_pgxp_enabled(self.pgxp);
+			}
+
+			if ui.checkbox(&mut self.texture_cache, "Texture Cache").changed() {
+				psx.set_texture_cache_enabled(self.texture_cache);
+			}
+
+			if ui.checkbox(&mut self.fast_cd, "Fast CD").changed() {
+				psx.set_fast_cd(self.fast_cd);
+			}
+
+			if ui.checkbox(&mut self.widescreen, "Widescreen").changed() {
+				psx.set_widescreen(self.widescreen);
+				self.save_widescreen_setting();
+			}
+		});
+
+		ui.horizontal(|ui| {
+			ui.add_enabled(!self.serial_connected, TextEdit::singleline(&mut self.serial_spec).desired_width(200.0));
+
+			if ui.button(if self.serial_connected { "Unplug Serial" } else { "Plug Serial" }).clicked() {
+				if self.serial_connected {
+					psx.set_serial_backend(None);
+					self.serial_connected = false;
+				} else {
+					match open_backend(&self.serial_spec) {
+						Ok(backend) => {
+							psx.set_serial_backend(Some(backend));
+							self.serial_connected = true;
+						},
+						Err(err) => error!("unable to open serial backend {}: {err}", self.serial_spec),
+					}
+				}
+			}
 		});
 
 	}
@@ -83,7 +198,26 @@ impl Control {
 
 	}
 
-	pub fn load_disc(&self, cue_path: &str, psx: &mut PSXEmulator) {
+	fn save_gpu_dump(&mut self, psx: &mut PSXEmulator) {
+		let Some(dump) = psx.stop_gpu_recording() else {
+			warn!("no GPU commands were recorded");
+			return;
+		};
+
+		let dump_path = FileDialog::new()
+			.add_filter("GPU Dump", &["gpudump"])
+			.set_directory(std::env::current_dir().unwrap())
+			.save_file();
+
+		if let Some(path) = dump_path {
+			match dump.save(&path) {
+				Ok(()) => info!("saved {} frames of GPU commands to {}", dump.frames(), path.display()),
+				Err(err) => error!("unable to save GPU dump: {err}"),
+			}
+		}
+	}
+
+	pub fn load_disc(&mut self, cue_path: &str, psx: &mut PSXEmulator) {
 		let cue = parse_from_file(cue_path, false).unwrap();
 
 		let mut cue_dir = PathBuf::from(cue_path);
@@ -108,27 +242,54 @@ impl Control {
 		}
 
 		disc.add_tracks(tracks);
+		disc.load_subchannel_files(Path::new(cue_path));
+		disc.load_patch_files(Path::new(cue_path));
+
+		// widescreen is remembered per game
+		self.game_id = disc.get_game_id();
+		self.widescreen = self.game_id.as_ref().is_some_and(|id| self.widescreen_games.contains(id));
+		psx.set_widescreen(self.widescreen);
+
+		debug!("game id: {:?} widescreen: {}", self.game_id, self.widescreen);
 
 		debug!("Loaded disc: {}", cue_path);
 		psx.load_disc(disc);
 	}
 
-	pub fn reset_emu(&mut self, psx: &mut PSXEmulator, tty: &mut TTYLogger, breakpoints: &mut Breakpoints, stream_handle: &mut OutputStream) {
-		let sink = rodio::Sink::connect_new(&stream_handle.mixer());
-		sink.set_volume(3.0);
+	fn save_widescreen_setting(&mut self) {
+		let Some(game_id) = self.game_id.clone() else {
+			return;
+		};
 
-		let audio_callback = Box::new(move |buffer: Vec<f32>| {
-			while sink.len() > 2 {
-				std::thread::sleep(Duration::from_millis(1));
-			}
+		if self.widescreen {
+			self.widescreen_games.insert(game_id);
+		} else {
+			self.widescreen_games.remove(&game_id);
+		}
 
-			sink.append(SamplesBuffer::new(2, 44100, buffer));
-		});
+		let list: String = self.widescreen_games.iter().map(|id| format!("{id}\n")).collect();
 
+		if let Err(err) = fs::write(WIDESCREEN_GAMES_PATH, list) {
+			warn!("unable to save widescreen games: {err}");
+		}
+	}
+
+	pub fn reset_emu(&mut self, psx: &mut PSXEmulator, tty: &mut TTYLogger, breakpoints: &mut Breakpoints, audio: &mut AudioOutput) {
 		let bios = fs::read(BIOS_PATH).unwrap();
-		*psx = PSXEmulator::new(bios, audio_callback);
+		let serial = psx.take_serial_backend();
+
+		*psx = PSXEmulator::new(bios, audio.callback());
+		psx.set_serial_backend(serial);
 
 		psx.bus.spu.emu_mute = self.muted;
+		psx.set_resolution_scale(self.resolution_scale);
+		psx.set_gpu_model(self.gpu_model);
+		psx.set_gpu_threads(self.gpu_threads);
+		psx.set_accurate_gpustat(self.accurate_gpustat);
+		psx.set_pgxp_enabled(self.pgxp);
+		psx.set_texture_cache_enabled(self.texture_cache);
+		psx.set_fast_cd(self.fast_cd);
+		psx.set_widescreen(self.widescreen);
 
 		tty.out_buf.clear();
 		breakpoints.breakpoints.clear();
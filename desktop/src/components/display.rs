@@ -1,5 +1,7 @@
 use eframe::{CreationContext, egui::{load::SizedTexture, *}};
 
+use crate::components::gpu_debugger::{paint_highlight, DrawHighlight};
+
 const VRAM_WIDTH: usize = 1024;
 const VRAM_HEIGHT: usize = 512;
 
@@ -8,7 +10,10 @@ fn convert_5bit_to_8bit(color: u16) -> u8 {
 }
 
 pub struct DisplayViwer {
-	display_tex: TextureHandle
+	display_tex: TextureHandle,
+
+	// pixel of the displayed picture under the mouse, aims the light gun
+	pointer: Option<(f32, f32)>,
 }
 
 impl DisplayViwer {
@@ -18,18 +23,28 @@ impl DisplayViwer {
 				"Display Viewer",
 				ColorImage::new([VRAM_WIDTH, VRAM_HEIGHT], Color32::BLACK),
 				TextureOptions::NEAREST
-			)
+			),
+
+			pointer: None,
 		}
 	}
 
-	pub fn show(&mut self, ui: &mut Ui, psx: &psx::PSXEmulator) {
+	pub fn pointer(&self) -> Option<(f32, f32)> {
+		self.pointer
+	}
+
+	pub fn show(&mut self, ui: &mut Ui, psx: &psx::PSXEmulator, highlight: Option<&DrawHighlight>) {
 
-		let (width, height) = psx.get_display_res();
+		let (display_width, display_height) = psx.get_display_res();
 		let (start_x, start_y) = psx.get_display_start();
 
+		// 24-bit output (MDEC frames) and vram rebuilt by the gpu debugger are shown at native resolution
+		let scale = if psx.is_display_24bit() || highlight.is_some() { 1 } else { psx.get_resolution_scale() as usize };
+		let (width, height) = (display_width * scale, display_height * scale);
+
 		let mut display_buf = vec![Color32::default(); width * height];
 
-		let vram = psx.get_vram();
+		let vram = highlight.map_or(&psx.get_vram()[..], |highlight| highlight.vram);
 
 		if psx.is_display_24bit() {			
 			for y in start_y..(start_y + height) {
@@ -57,6 +72,23 @@ impl DisplayViwer {
 
 				}
 			}
+		} else if scale > 1 {
+			let hires_vram = psx.get_hires_vram();
+			let (vram_width, vram_height) = (1024 * scale, 512 * scale);
+
+			for y in 0..height {
+				for x in 0..width {
+					let vram_y = (start_y * scale + y) & (vram_height - 1);
+					let vram_x = (start_x * scale + x) & (vram_width - 1);
+					let pixel = hires_vram[vram_width * vram_y + vram_x];
+
+					display_buf[x + width * y] = Color32::from_rgb(
+						convert_5bit_to_8bit(pixel & 0x1F),
+						convert_5bit_to_8bit((pixel >> 5) & 0x1F),
+						convert_5bit_to_8bit((pixel >> 10) & 0x1F),
+					)
+				}
+			}
 		} else {
 			for y in start_y..(start_y + height) {
 				for x in start_x..(start_x + width) {
@@ -78,23 +110,80 @@ impl DisplayViwer {
 			pixels: display_buf,
 		};
 
-		self.display_tex.set(colour_image, TextureOptions::NEAREST);
+		let filter = if scale > 1 { TextureOptions::LINEAR } else { TextureOptions::NEAREST };
+		self.display_tex.set(colour_image, filter);
+
+		let display_size = if psx.is_widescreen() {
+			Vec2::new(480.0 * 16.0 / 9.0, 480.0)
+		} else {
+			Vec2::new(640.0, 480.0)
+		};
 
 		let image = Image::from_texture(SizedTexture::new(
 			&self.display_tex, 
-			Vec2::new(640.0, 480.0)
+			display_size
 		))
 		.shrink_to_fit();
 
 		ui.centered_and_justified(|ui| {
-			ui.add(image);
+			let response = ui.add(image);
+			let rect = response.rect;
+
+			self.pointer = response.hover_pos().map(|pos| (
+				(pos.x - rect.min.x) * display_width as f32 / rect.width(),
+				(pos.y - rect.min.y) * display_height as f32 / rect.height(),
+			));
+
+			if let Some(highlight) = highlight {
+				let to_screen = |x: f32, y: f32| rect.min + Vec2::new(
+					(x - start_x as f32) * rect.width() / display_width as f32,
+					(y - start_y as f32) * rect.height() / display_height as f32,
+				);
+
+				paint_highlight(&ui.painter_at(rect), highlight.draw, to_screen, false);
+			}
 		});
 
 	}
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum VramViewMode {
+	Rgb15,
+	Clut4,
+	Clut8,
+	Rgb24,
+}
+
+impl VramViewMode {
+	// decoded pixels per vram halfword
+	fn pixels_per_halfword(&self) -> f32 {
+		match self {
+			Self::Rgb15 => 1.0,
+			Self::Clut4 => 4.0,
+			Self::Clut8 => 2.0,
+			Self::Rgb24 => 2.0 / 3.0,
+		}
+	}
+
+	fn image_width(&self) -> usize {
+		(VRAM_WIDTH as f32 * self.pixels_per_halfword()) as usize
+	}
+}
+
 pub struct VramViewer {
-	vram_tex: TextureHandle
+	vram_tex: TextureHandle,
+
+	mode: VramViewMode,
+	clut: (u32, u32),
+	zoom: f32,
+	show_overlays: bool,
+
+	// export region in vram halfwords, (min, max) inclusive
+	selection: Option<(Pos2, Pos2)>,
+	drag_start: Option<Pos2>,
+	// decoded pixels of the last frame shown, used for exporting
+	decoded: Vec<Color32>,
 }
 
 impl VramViewer {
@@ -105,44 +194,222 @@ impl VramViewer {
 				"VRAM Viewer",
 				ColorImage::new([VRAM_WIDTH, VRAM_HEIGHT], Color32::BLACK),
 				TextureOptions::NEAREST
-			)
+			),
+
+			mode: VramViewMode::Rgb15,
+			clut: (0, 0),
+			zoom: 1.0,
+			show_overlays: true,
+
+			selection: None,
+			drag_start: None,
+			decoded: Vec::new(),
 		}
 	}
 
-	pub fn show(&mut self, ui: &mut Ui, psx: &psx::PSXEmulator) {
+	pub fn show(&mut self, ui: &mut Ui, psx: &psx::PSXEmulator, highlight: Option<&DrawHighlight>) {
+
+		let vram = highlight.map_or(&psx.get_vram()[..], |highlight| highlight.vram);
+
+		self.show_settings(ui);
+
+		let width = self.mode.image_width();
+		self.decoded = decode_vram(vram, self.mode, self.clut);
+
+		let colour_image = ColorImage {
+			size: [width, VRAM_HEIGHT],
+			pixels: self.decoded.clone(),
+		};
+
+		self.vram_tex.set(colour_image, TextureOptions::NEAREST);
+
+		let image = Image::from_texture(SizedTexture::new(
+			&self.vram_tex,
+			Vec2::new(VRAM_WIDTH as f32 * self.zoom, VRAM_HEIGHT as f32 * self.zoom)
+		))
+		.sense(Sense::click_and_drag());
+
+		let mut hovered = None;
+
+		ScrollArea::both().id_salt("VRAM Scroll").show(ui, |ui| {
+			let response = ui.add(image);
+			let rect = response.rect;
+
+			// everything is placed in vram halfword coordinates, whatever the view mode
+			let to_screen = |x: f32, y: f32| rect.min + Vec2::new(x * rect.width() / VRAM_WIDTH as f32, y * rect.height() / VRAM_HEIGHT as f32);
+			let to_vram = |pos: Pos2| {
+				let offset = (pos - rect.min) / self.zoom;
+				Pos2::new(offset.x.clamp(0.0, (VRAM_WIDTH - 1) as f32).floor(), offset.y.clamp(0.0, (VRAM_HEIGHT - 1) as f32).floor())
+			};
+
+			hovered = response.hover_pos().map(to_vram);
 
-		let vram = psx.get_vram();
-		let mut display_buf = vec![Color32::default(); VRAM_WIDTH * VRAM_HEIGHT];
+			// dragging selects the export region
+			if response.drag_started() {
+				self.drag_start = response.interact_pointer_pos().map(to_vram);
+			}
+
+			if response.dragged() {
+				if let (Some(start), Some(pos)) = (self.drag_start, response.interact_pointer_pos()) {
+					let end = to_vram(pos);
+					self.selection = Some((start.min(end), start.max(end)));
+				}
+			}
+
+			let painter = ui.painter_at(rect);
+
+			if self.show_overlays {
+				let outline = |min: (usize, usize), max: (usize, usize), colour: Color32| {
+					let area = Rect::from_min_max(to_screen(min.0 as f32, min.1 as f32), to_screen(max.0 as f32 + 1.0, max.1 as f32 + 1.0));
+					painter.rect_stroke(area, 0.0, Stroke::new(1.0, colour), StrokeKind::Inside);
+				};
 
-		for y in 0..512 {
-			for x in 0..1024 {
-				let vram_addr = 1024 * y + x;
-				let pixel = vram[vram_addr];
+				let (draw_min, draw_max) = psx.get_draw_area();
+				outline(draw_min, draw_max, Color32::GREEN);
 
-				if pixel != 0 {
-					//println!("pixel: 0x{pixel:X}");
+				let (start_x, start_y) = psx.get_display_start();
+				let (display_width, display_height) = psx.get_display_res();
+				let display_width = if psx.is_display_24bit() { display_width * 3 / 2 } else { display_width };
+				outline((start_x, start_y), (start_x + display_width - 1, start_y + display_height - 1), Color32::YELLOW);
+
+				let (page_x, page_y, depth) = psx.get_texture_page();
+				outline((page_x, page_y), (page_x + 256 * depth / 16 - 1, page_y + 255), Color32::LIGHT_BLUE);
+			}
+
+			if let Some((min, max)) = self.selection {
+				let area = Rect::from_min_max(to_screen(min.x, min.y), to_screen(max.x + 1.0, max.y + 1.0));
+				painter.rect_stroke(area, 0.0, Stroke::new(1.0, Color32::WHITE), StrokeKind::Outside);
+			}
+
+			if let Some(highlight) = highlight {
+				paint_highlight(&painter, highlight.draw, to_screen, true);
+			}
+		});
+
+		ui.horizontal(|ui| {
+			match hovered {
+				Some(pos) => {
+					let (x, y) = (pos.x as usize, pos.y as usize);
+					let raw = vram[VRAM_WIDTH * y + x];
+
+					ui.monospace(format!("({x}, {y}) 0x{raw:04X} mask: {}", raw >> 15));
+				},
+				None => { ui.monospace("-"); },
+			}
+
+			if let Some((min, max)) = self.selection {
+				ui.separator();
+				ui.monospace(format!("selection ({}, {}) {}x{}", min.x, min.y, max.x - min.x + 1.0, max.y - min.y + 1.0));
+
+				if ui.button("Export PNG").clicked() {
+					self.export_selection(min, max);
 				}
 
-				display_buf[x + VRAM_WIDTH * y] = Color32::from_rgb(
-					convert_5bit_to_8bit((pixel >> 0) & 0x1F),
-					convert_5bit_to_8bit((pixel >> 5) & 0x1F),
-					convert_5bit_to_8bit((pixel >> 10) & 0x1F),
-				)
+				if ui.button("Clear").clicked() {
+					self.selection = None;
+				}
+			}
+		});
+
+	}
 
+	fn show_settings(&mut self, ui: &mut Ui) {
+		ui.horizontal(|ui| {
+			ComboBox::from_label("Mode")
+				.selected_text(format!("{:?}", self.mode))
+				.show_ui(ui, |ui| {
+					ui.selectable_value(&mut self.mode, VramViewMode::Rgb15, "15-bit");
+					ui.selectable_value(&mut self.mode, VramViewMode::Clut4, "4-bit CLUT");
+					ui.selectable_value(&mut self.mode, VramViewMode::Clut8, "8-bit CLUT");
+					ui.selectable_value(&mut self.mode, VramViewMode::Rgb24, "24-bit");
+				});
+
+			if matches!(self.mode, VramViewMode::Clut4 | VramViewMode::Clut8) {
+				ui.label("CLUT");
+				ui.add(DragValue::new(&mut self.clut.0).range(0..=1023).speed(16.0));
+				ui.add(DragValue::new(&mut self.clut.1).range(0..=511));
 			}
-		}
 
-		let colour_image = ColorImage {
-			size: [VRAM_WIDTH, VRAM_HEIGHT],
-			pixels: display_buf,
+			ComboBox::from_label("Zoom")
+				.selected_text(format!("{}x", self.zoom))
+				.show_ui(ui, |ui| {
+					for zoom in [1.0, 2.0, 4.0] {
+						ui.selectable_value(&mut self.zoom, zoom, format!("{zoom}x"));
+					}
+				});
+
+			ui.checkbox(&mut self.show_overlays, "Overlays");
+		});
+	}
+
+	// writes the selected region as shown in the current mode
+	fn export_selection(&self, min: Pos2, max: Pos2) {
+		let Some(path) = rfd::FileDialog::new()
+			.add_filter("PNG Image", &["png"])
+			.set_directory(std::env::current_dir().unwrap())
+			.save_file()
+		else {
+			return;
 		};
 
-		self.vram_tex.set(colour_image, TextureOptions::NEAREST);
+		let scale = self.mode.pixels_per_halfword();
+		let image_width = self.mode.image_width();
+
+		let (start_x, end_x) = ((min.x * scale) as usize, (((max.x + 1.0) * scale) as usize).clamp(1, image_width));
+		let (start_y, end_y) = (min.y as usize, max.y as usize + 1);
+		let (width, height) = (end_x.saturating_sub(start_x).max(1), end_y - start_y);
+
+		let mut rgb = Vec::with_capacity(width * height * 3);
+
+		for y in start_y..end_y {
+			for x in start_x..start_x + width {
+				let pixel = self.decoded[image_width * y + x.min(image_width - 1)];
+				rgb.extend_from_slice(&[pixel.r(), pixel.g(), pixel.b()]);
+			}
+		}
 
-		let image = Image::new(&self.vram_tex);
+		if let Err(err) = write_png(&path, width as u32, height as u32, &rgb) {
+			log::error!("unable to export VRAM region: {err}");
+		}
+	}
 
-		ui.add(image);
+}
 
+fn decode_vram(vram: &[u16], mode: VramViewMode, clut: (u32, u32)) -> Vec<Color32> {
+	let rgb555 = |pixel: u16| Color32::from_rgb(
+		convert_5bit_to_8bit(pixel & 0x1F),
+		convert_5bit_to_8bit((pixel >> 5) & 0x1F),
+		convert_5bit_to_8bit((pixel >> 10) & 0x1F),
+	);
+
+	let clut_entry = |index: u16| vram[VRAM_WIDTH * clut.1 as usize + ((clut.0 as usize + index as usize) & (VRAM_WIDTH - 1))];
+
+	let width = mode.image_width();
+	let mut pixels = Vec::with_capacity(width * VRAM_HEIGHT);
+
+	for y in 0..VRAM_HEIGHT {
+		let row = &vram[VRAM_WIDTH * y..VRAM_WIDTH * (y + 1)];
+		let byte = |i: usize| (row[i / 2] >> ((i % 2) * 8)) as u8;
+
+		for x in 0..width {
+			pixels.push(match mode {
+				VramViewMode::Rgb15 => rgb555(row[x]),
+				VramViewMode::Clut4 => rgb555(clut_entry((row[x / 4] >> ((x % 4) * 4)) & 0xF)),
+				VramViewMode::Clut8 => rgb555(clut_entry((row[x / 2] >> ((x % 2) * 8)) & 0xFF)),
+				VramViewMode::Rgb24 => Color32::from_rgb(byte(x * 3), byte(x * 3 + 1), byte(x * 3 + 2)),
+			});
+		}
 	}
 
-}
\ No newline at end of file
+	pixels
+}
+
+fn write_png(path: &std::path::Path, width: u32, height: u32, rgb: &[u8]) -> Result<(), png::EncodingError> {
+	let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+
+	let mut encoder = png::Encoder::new(file, width, height);
+	encoder.set_color(png::ColorType::Rgb);
+	encoder.set_depth(png::BitDepth::Eight);
+
+	encoder.write_header()?.write_image_data(rgb)
+}
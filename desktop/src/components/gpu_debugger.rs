@@ -0,0 +1,315 @@
+use eframe::egui::{self, load::SizedTexture, Color32, ColorImage, Image, Painter, Pos2, Rect, Sense, Shape, Stroke, TextureHandle, TextureOptions, Vec2};
+use eframe::CreationContext;
+use egui_extras::{Column, TableBuilder};
+
+use psx::PSXEmulator;
+use psx::gpu_debug::{decode_texture_page, DrawCall, DrawCallKind, FrameCapture};
+
+const HIGHLIGHT_COLOUR: Color32 = Color32::from_rgb(255, 0, 255);
+const TEXTURE_COLOUR: Color32 = Color32::from_rgb(0, 255, 255);
+
+// what the vram and display viewers show while a draw is selected
+pub struct DrawHighlight<'a> {
+	pub vram: &'a [u16],
+	pub draw: &'a DrawCall,
+}
+
+pub struct GpuDebugger {
+	enabled: bool,
+
+	// the frame is frozen once a draw is selected so the list doesn't change under it
+	frame: Option<FrameCapture>,
+	selected: Option<usize>,
+	// vram rebuilt up to and including the selected draw
+	step_vram: Vec<u16>,
+
+	texture_tex: TextureHandle,
+}
+
+impl GpuDebugger {
+	pub fn new(cc: &CreationContext) -> Self {
+		Self {
+			enabled: false,
+
+			frame: None,
+			selected: None,
+			step_vram: Vec::new(),
+
+			texture_tex: cc.egui_ctx.load_texture(
+				"GPU Debugger Texture",
+				ColorImage::new([256, 256], Color32::BLACK),
+				TextureOptions::NEAREST
+			),
+		}
+	}
+
+	pub fn show(&mut self, ui: &mut egui::Ui, psx: &mut PSXEmulator) {
+		ui.horizontal(|ui| {
+			if ui.checkbox(&mut self.enabled, "Capture Draws").changed() {
+				psx.set_gpu_debug_capture(self.enabled);
+
+				if !self.enabled {
+					self.release();
+				}
+			}
+
+			if self.frame.is_some() && ui.button("Release Frame").clicked() {
+				self.release();
+			}
+		});
+
+		let Some(frame) = self.frame.as_ref().or(psx.get_last_frame_capture()) else {
+			ui.label("No frame captured");
+			return;
+		};
+
+		let draw_count = frame.draws.len();
+		let mut new_selection = None;
+
+		ui.horizontal(|ui| {
+			ui.label(format!("{draw_count} draws"));
+
+			if ui.button("Prev").clicked() && draw_count > 0 {
+				new_selection = Some(self.selected.map_or(0, |i| i.saturating_sub(1)));
+			}
+
+			if ui.button("Next").clicked() && draw_count > 0 {
+				new_selection = Some(self.selected.map_or(0, |i| (i + 1).min(draw_count - 1)));
+			}
+		});
+
+		ui.separator();
+
+		let list_height = ui.available_height() * 0.5;
+
+		TableBuilder::new(ui)
+			.id_salt("GPU Draws")
+			.column(Column::auto().at_least(40.0))
+			.column(Column::auto().at_least(80.0))
+			.column(Column::remainder())
+			.striped(true)
+			.sense(Sense::click())
+			.max_scroll_height(list_height)
+			.header(20.0, |mut header| {
+				header.col(|ui| { ui.label("#"); });
+				header.col(|ui| { ui.label("Command"); });
+				header.col(|ui| { ui.label("Area"); });
+			})
+			.body(|body| {
+				body.rows(18.0, draw_count, |mut row| {
+					let i = row.index();
+					let draw = &frame.draws[i];
+
+					row.set_selected(self.selected == Some(i));
+
+					row.col(|ui| { ui.monospace(format!("{i}")); });
+					row.col(|ui| { ui.label(draw_name(draw)); });
+					row.col(|ui| {
+						let (min_x, min_y, max_x, max_y) = draw.bounds();
+						ui.monospace(format!("({min_x}, {min_y}) - ({max_x}, {max_y})"));
+					});
+
+					if row.response().clicked() {
+						new_selection = Some(i);
+					}
+				});
+			});
+
+		if let Some(index) = new_selection {
+			self.select(index, psx);
+		}
+
+		let (Some(frame), Some(index)) = (&self.frame, self.selected) else {
+			return;
+		};
+
+		let draw = &frame.draws[index];
+
+		ui.separator();
+
+		egui::ScrollArea::vertical().id_salt("GPU Draw Details").show(ui, |ui| {
+			show_draw_details(ui, draw);
+
+			if draw.textured {
+				ui.separator();
+				ui.label("Texture page (sampled area outlined)");
+
+				let response = ui.add(Image::from_texture(SizedTexture::new(&self.texture_tex, Vec2::new(256.0, 256.0))));
+
+				let uvs: Vec<Pos2> = draw.vertices.iter()
+					.map(|v| response.rect.min + Vec2::new(v.u as f32, v.v as f32))
+					.collect();
+
+				paint_outline(ui.painter(), draw.kind, &uvs, HIGHLIGHT_COLOUR);
+			}
+		});
+	}
+
+	pub fn highlight(&self) -> Option<DrawHighlight<'_>> {
+		let draw = &self.frame.as_ref()?.draws[self.selected?];
+
+		Some(DrawHighlight { vram: &self.step_vram, draw })
+	}
+
+	fn select(&mut self, index: usize, psx: &PSXEmulator) {
+		if self.frame.is_none() {
+			self.frame = psx.get_last_frame_capture().cloned();
+		}
+
+		let Some(frame) = &self.frame else {
+			return;
+		};
+
+		let draw = &frame.draws[index];
+
+		self.step_vram = frame.vram_after(index);
+		self.selected = Some(index);
+
+		if draw.textured {
+			let pixels = decode_texture_page(&self.step_vram, draw).into_iter().map(rgb555_to_colour32).collect();
+			self.texture_tex.set(ColorImage { size: [256, 256], pixels }, TextureOptions::NEAREST);
+		}
+	}
+
+	fn release(&mut self) {
+		self.frame = None;
+		self.selected = None;
+		self.step_vram = Vec::new();
+	}
+}
+
+// outlines the selected draw and the texture page / clut it reads, positions are in vram coordinates
+pub fn paint_highlight(painter: &Painter, draw: &DrawCall, to_screen: impl Fn(f32, f32) -> Pos2, show_texture: bool) {
+	let points: Vec<Pos2> = draw.vertices.iter().map(|v| to_screen(v.x as f32, v.y as f32)).collect();
+	paint_outline(painter, draw.kind, &points, HIGHLIGHT_COLOUR);
+
+	if show_texture && draw.textured {
+		let (page_x, page_y) = (draw.tex_page.0 as f32, draw.tex_page.1 as f32);
+		let page_width = 256.0 * draw.tex_depth as f32 / 16.0;
+
+		let page = Rect::from_two_pos(to_screen(page_x, page_y), to_screen(page_x + page_width, page_y + 256.0));
+		painter.rect_stroke(page, 0.0, Stroke::new(1.0, TEXTURE_COLOUR), egui::StrokeKind::Outside);
+
+		let clut_width = match draw.tex_depth {
+			4 => 16.0,
+			8 => 256.0,
+			_ => 0.0,
+		};
+
+		if clut_width > 0.0 {
+			let (clut_x, clut_y) = (draw.clut.0 as f32, draw.clut.1 as f32);
+			let clut = Rect::from_two_pos(to_screen(clut_x, clut_y), to_screen(clut_x + clut_width, clut_y + 1.0));
+
+			painter.rect_stroke(clut, 0.0, Stroke::new(1.0, TEXTURE_COLOUR), egui::StrokeKind::Outside);
+		}
+	}
+}
+
+fn paint_outline(painter: &Painter, kind: DrawCallKind, points: &[Pos2], colour: Color32) {
+	let stroke = Stroke::new(1.0, colour);
+
+	match kind {
+		// quads are drawn as the triangles 0 1 2 and 1 2 3
+		DrawCallKind::Polygon => {
+			for triangle in points.windows(3) {
+				painter.add(Shape::closed_line(triangle.to_vec(), stroke));
+			}
+		},
+		DrawCallKind::Line | DrawCallKind::Polyline => {
+			painter.add(Shape::line(points.to_vec(), stroke));
+		},
+		_ => {
+			painter.add(Shape::closed_line(points.to_vec(), stroke));
+		}
+	}
+}
+
+fn show_draw_details(ui: &mut egui::Ui, draw: &DrawCall) {
+	egui::Grid::new("GPU Draw Details Grid").striped(true).show(ui, |ui| {
+		ui.label("Command");
+		ui.label(draw_name(draw));
+		ui.end_row();
+
+		if draw.textured {
+			ui.label("Texture page");
+			ui.monospace(format!("({}, {}) {}bit{}", draw.tex_page.0, draw.tex_page.1, draw.tex_depth, if draw.raw_texture { " raw" } else { "" }));
+			ui.end_row();
+
+			if draw.tex_depth != 16 {
+				ui.label("CLUT");
+				ui.monospace(format!("({}, {})", draw.clut.0, draw.clut.1));
+				ui.end_row();
+			}
+		}
+
+		ui.label("Semi-transparency");
+		ui.label(if draw.semi_transparent {
+			["B/2 + F/2", "B + F", "B - F", "B + F/4"][draw.semi_transparency_mode as usize]
+		} else {
+			"Off"
+		});
+		ui.end_row();
+
+		ui.label("Mask bit");
+		ui.label(format!("set: {} check: {}", draw.force_mask_bit, draw.check_mask_bit));
+		ui.end_row();
+	});
+
+	ui.separator();
+
+	egui::Grid::new("GPU Draw Vertices").striped(true).show(ui, |ui| {
+		ui.label("Vertex");
+		ui.label("Position");
+		ui.label("Colour");
+
+		if draw.textured {
+			ui.label("UV");
+		}
+
+		ui.end_row();
+
+		for (i, v) in draw.vertices.iter().enumerate() {
+			let (r, g, b) = v.colour;
+
+			ui.monospace(format!("{i}"));
+			ui.monospace(format!("({}, {})", v.x, v.y));
+
+			ui.horizontal(|ui| {
+				let (rect, _) = ui.allocate_exact_size(Vec2::splat(12.0), Sense::hover());
+				ui.painter().rect_filled(rect, 0.0, Color32::from_rgb(r, g, b));
+				ui.monospace(format!("#{r:02X}{g:02X}{b:02X}"));
+			});
+
+			if draw.textured {
+				ui.monospace(format!("({}, {})", v.u, v.v));
+			}
+
+			ui.end_row();
+		}
+	});
+}
+
+fn draw_name(draw: &DrawCall) -> String {
+	match draw.kind {
+		DrawCallKind::Polygon => format!(
+			"{} {}",
+			if draw.vertices.len() == 4 { "Quad" } else { "Triangle" },
+			if draw.textured { "textured" } else { "untextured" }
+		),
+		DrawCallKind::Rect => format!("Rect{}", if draw.textured { " textured" } else { "" }),
+		DrawCallKind::Line => "Line".to_string(),
+		DrawCallKind::Polyline => format!("Polyline ({} points)", draw.vertices.len()),
+		DrawCallKind::QuickFill => "Quick Fill".to_string(),
+		DrawCallKind::VramCopy => "VRAM to VRAM".to_string(),
+		DrawCallKind::CpuToVram => "CPU to VRAM".to_string(),
+		DrawCallKind::VramToCpu => "VRAM to CPU".to_string(),
+	}
+}
+
+fn rgb555_to_colour32(pixel: u16) -> Color32 {
+	Color32::from_rgb(
+		((pixel & 0x1F) << 3) as u8,
+		(((pixel >> 5) & 0x1F) << 3) as u8,
+		(((pixel >> 10) & 0x1F) << 3) as u8,
+	)
+}
@@ -3,4 +3,6 @@ pub mod display;
 pub mod tty_logger;
 pub mod disassembly;
 pub mod kernel_logger;
-pub mod breakpoints;
\ No newline at end of file
+pub mod breakpoints;
+pub mod gpu_debugger;
+pub mod spu_inspector;
\ No newline at end of file
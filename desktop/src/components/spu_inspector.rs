@@ -0,0 +1,170 @@
+use eframe::egui::{self, Color32, Pos2, Rect, Sense, Shape, Stroke, Vec2};
+use egui_extras::{Column, TableBuilder};
+use log::*;
+
+use psx::{AdsrPhase, PSXEmulator, VoiceInfo};
+
+const WAVEFORM_COLOUR: Color32 = Color32::from_rgb(0, 200, 120);
+const LEVEL_COLOUR: Color32 = Color32::from_rgb(80, 140, 220);
+
+pub struct SpuInspector {
+	show_waveforms: bool,
+}
+
+impl SpuInspector {
+	pub fn new() -> Self {
+		Self {
+			show_waveforms: false,
+		}
+	}
+
+	pub fn show(&mut self, ui: &mut egui::Ui, psx: &mut PSXEmulator) {
+		let spu = &mut psx.bus.spu;
+
+		ui.horizontal(|ui| {
+			if ui.checkbox(&mut self.show_waveforms, "Waveforms").changed() {
+				spu.set_waveform_capture(self.show_waveforms);
+			}
+
+			let mut reverb = spu.is_reverb_output_enabled();
+			if ui.checkbox(&mut reverb, "Reverb").changed() {
+				spu.set_reverb_output_enabled(reverb);
+			}
+
+			if ui.button("Clear Mute/Solo").clicked() {
+				for voice in 0..24 {
+					spu.set_voice_muted(voice, false);
+					spu.set_voice_solo(voice, false);
+				}
+			}
+
+			if ui.button("Export Samples").clicked() {
+				if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+					let samples = spu.extract_samples();
+
+					for sample in samples.iter() {
+						let path = dir.join(format!("{}.wav", sample.name));
+
+						if let Err(err) = sample.save_wav(&path) {
+							error!("unable to write {}: {err}", path.display());
+						}
+					}
+
+					info!("exported {} samples from sound ram", samples.len());
+				}
+			}
+		});
+
+		ui.separator();
+
+		let row_height = if self.show_waveforms { 32.0 } else { 18.0 };
+
+		TableBuilder::new(ui)
+			.id_salt("SPU Voices")
+			.column(Column::auto().at_least(20.0))
+			.column(Column::auto())
+			.column(Column::auto())
+			.column(Column::auto().at_least(60.0))
+			.column(Column::auto().at_least(80.0))
+			.column(Column::auto().at_least(90.0))
+			.column(Column::auto().at_least(150.0))
+			.column(Column::auto().at_least(70.0))
+			.column(Column::remainder().at_least(100.0))
+			.striped(true)
+			.header(20.0, |mut header| {
+				header.col(|ui| { ui.label("#"); });
+				header.col(|ui| { ui.label("M"); });
+				header.col(|ui| { ui.label("S"); });
+				header.col(|ui| { ui.label("Phase"); });
+				header.col(|ui| { ui.label("Level"); });
+				header.col(|ui| { ui.label("Pitch"); });
+				header.col(|ui| { ui.label("Start / Repeat / Current"); });
+				header.col(|ui| { ui.label("Flags"); });
+				header.col(|ui| { ui.label("Waveform"); });
+			})
+			.body(|body| {
+				body.rows(row_height, 24, |mut row| {
+					let voice = row.index();
+					let info = spu.get_voice_info(voice);
+
+					row.col(|ui| { ui.monospace(format!("{voice}")); });
+					row.col(|ui| {
+						let mut muted = spu.is_voice_muted(voice);
+						if ui.checkbox(&mut muted, "").changed() {
+							spu.set_voice_muted(voice, muted);
+						}
+					});
+					row.col(|ui| {
+						let mut solo = spu.is_voice_solo(voice);
+						if ui.checkbox(&mut solo, "").changed() {
+							spu.set_voice_solo(voice, solo);
+						}
+					});
+					row.col(|ui| { ui.label(phase_name(info.adsr_phase)); });
+					row.col(|ui| { level_bar(ui, info.adsr_level); });
+					row.col(|ui| {
+						// 0x1000 is 44100hz
+						let hz = u32::from(info.sample_rate) * 44100 / 0x1000;
+						ui.monospace(format!("{:04X} {hz}hz", info.sample_rate));
+					});
+					row.col(|ui| {
+						ui.monospace(format!("{:05X} {:05X} {:05X}", info.start_addr, info.repeat_addr, info.current_addr));
+					});
+					row.col(|ui| { ui.monospace(flags(&info)); });
+					row.col(|ui| {
+						if let Some(waveform) = spu.get_voice_waveform(voice) {
+							paint_waveform(ui, &waveform);
+						}
+					});
+				});
+			});
+	}
+}
+
+impl Default for SpuInspector {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+fn phase_name(phase: AdsrPhase) -> &'static str {
+	match phase {
+		AdsrPhase::Attack => "Attack",
+		AdsrPhase::Decay => "Decay",
+		AdsrPhase::Sustain => "Sustain",
+		AdsrPhase::Release => "Release",
+	}
+}
+
+// K: keyed on, E: ENDX, N: noise, R: reverb, P: pitch modulation
+fn flags(info: &VoiceInfo) -> String {
+	[(info.key_on, 'K'), (info.end_x, 'E'), (info.noise, 'N'), (info.reverb, 'R'), (info.pitch_modulation, 'P')]
+		.iter()
+		.map(|&(set, c)| if set { c } else { '-' })
+		.collect()
+}
+
+fn level_bar(ui: &mut egui::Ui, level: i16) {
+	let (rect, response) = ui.allocate_exact_size(Vec2::new(80.0, 10.0), Sense::hover());
+	let fill = f32::from(level.max(0)) / f32::from(i16::MAX);
+
+	ui.painter().rect_stroke(rect, 0.0, Stroke::new(1.0, Color32::GRAY), egui::StrokeKind::Inside);
+	ui.painter().rect_filled(Rect::from_min_size(rect.min, Vec2::new(rect.width() * fill, rect.height())), 0.0, LEVEL_COLOUR);
+
+	response.on_hover_text(format!("{level:04X}"));
+}
+
+fn paint_waveform(ui: &mut egui::Ui, waveform: &[i16]) {
+	let size = Vec2::new(ui.available_width().max(100.0), ui.available_height());
+	let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
+
+	let step = rect.width() / waveform.len() as f32;
+	let points: Vec<Pos2> = waveform.iter().enumerate()
+		.map(|(i, &sample)| Pos2::new(
+			rect.left() + i as f32 * step,
+			rect.center().y - f32::from(sample) / 32768.0 * rect.height() / 2.0,
+		))
+		.collect();
+
+	ui.painter().add(Shape::line(points, Stroke::new(1.0, WAVEFORM_COLOUR)));
+}
@@ -3,7 +3,7 @@ use gilrs::*;
 use gilrs::ff::{BaseEffect, Effect, EffectBuilder, Replay, Ticks};
 use log::*;
 
-use psx::{PSXEmulator, sio0::InputState};
+use psx::{DeviceType, GunConInput, MouseInput, NeGconInput, PSXEmulator, sio0::InputState};
 
 // hardcoding this because it seems to be a non-existent controller which always connects first on windows
 const EXCLUDED_CONTROLLER: &str = "HID-compliant game controller";
@@ -23,30 +23,144 @@ const BTN_R2: 		Key = Key::Num3;
 const BTN_START: 	Key = Key::Enter;
 const BTN_SELECT: 	Key = Key::Backslash;
 
+// fires the guncon with the aim off the picture, which is how most gun games reload
+const GUNCON_SHOOT_OFFSCREEN: Key = Key::Space;
+
+const PORTS: usize = 2;
+// slots A-D of a multitap, only slot A without one
+const SLOTS: usize = 4;
+
+const DEVICE_TYPES: [(DeviceType, &str); 5] = [
+	(DeviceType::None, "None"),
+	(DeviceType::Controller, "Controller"),
+	(DeviceType::Mouse, "Mouse"),
+	(DeviceType::NeGcon, "NeGcon"),
+	(DeviceType::GunCon, "GunCon"),
+];
+
 pub struct Input {
-	active_gamepad: Option<GamepadId>,
+	// indexed by port and slot, port 1 slot A falls back to the keyboard
+	active_gamepads: [[Option<GamepadId>; SLOTS]; PORTS],
 	gilrs: Gilrs,
 
-	pub analog_enabled: bool,
+	devices: [[DeviceType; SLOTS]; PORTS],
+	multitaps: [bool; PORTS],
 
-	current_effect: Option<Effect>
+	pub analog_enabled: [[bool; SLOTS]; PORTS],
+
+	current_effects: [[Option<Effect>; SLOTS]; PORTS],
 }
 
 impl Input {
 	pub fn new() -> Self {
+		let mut devices = [[DeviceType::None; SLOTS]; PORTS];
+		devices[0][0] = DeviceType::Controller;
+
 		Self {
-			active_gamepad: None,
+			active_gamepads: [[None; SLOTS]; PORTS],
 			gilrs: Gilrs::new().unwrap(),
+
+			devices,
+			multitaps: [false; PORTS],
 			
-			analog_enabled: false,
+			analog_enabled: [[false; SLOTS]; PORTS],
+
+			current_effects: Default::default(),
+		}
+	}
+
+	fn slots(&self, port: usize) -> usize {
+		match self.multitaps[port] {
+			true => SLOTS,
+			false => 1,
+		}
+	}
+
+	// pointer is the pixel of the displayed picture under the mouse
+	pub fn update_psx(&mut self, ctx: &Context, psx: &mut PSXEmulator, pointer: Option<(f32, f32)>) {
+		let (delta, btn_left, btn_right, btn_middle) = ctx.input(|input| (
+			input.pointer.delta(),
+			input.pointer.primary_down(),
+			input.pointer.secondary_down(),
+			input.pointer.middle_down(),
+		));
 
-			current_effect: None,
+		for port in 0..PORTS {
+			psx.set_multitap(port, self.multitaps[port]);
+
+			for slot in 0..self.slots(port) {
+				self.update_slot(ctx, psx, port, slot, pointer, (delta, btn_left, btn_right, btn_middle));
+			}
 		}
 	}
 
-	pub fn get_input(&mut self, ctx: &Context) -> InputState {
-		if self.active_gamepad.is_some() {
-			if let Some(gamepad) = self.gilrs.connected_gamepad(self.active_gamepad.unwrap()) {
+	fn update_slot(&mut self, ctx: &Context, psx: &mut PSXEmulator, port: usize, slot: usize, pointer: Option<(f32, f32)>, mouse: (egui::Vec2, bool, bool, bool)) {
+		let (delta, btn_left, btn_right, btn_middle) = mouse;
+
+		psx.set_device(port, slot, self.devices[port][slot]);
+
+		match self.devices[port][slot] {
+			DeviceType::None => {},
+			DeviceType::Controller => {
+				let input = self.get_input(port, slot, ctx);
+				psx.update_input(port, slot, input, self.analog_enabled[port][slot]);
+			},
+			// only moves while the mouse is over the display
+			DeviceType::Mouse => psx.update_mouse(port, slot, match pointer {
+				Some(_) => MouseInput {
+					delta_x: delta.x.round() as i32,
+					delta_y: delta.y.round() as i32,
+					btn_left,
+					btn_right,
+				},
+				None => MouseInput::default(),
+			}),
+			DeviceType::NeGcon => {
+				let input = self.get_input(port, slot, ctx);
+				let (width, _) = psx.get_display_res();
+
+				// twisted with the mouse when there's no gamepad
+				let twist = match (self.active_gamepads[port][slot], pointer) {
+					(None, Some((x, _))) => (x / width as f32 * 255.0).clamp(0.0, 255.0) as u8,
+					_ => input.l_stick_x,
+				};
+
+				psx.update_negcon(port, slot, NeGconInput {
+					btn_up: input.btn_up,
+					btn_down: input.btn_down,
+					btn_left: input.btn_left,
+					btn_right: input.btn_right,
+
+					btn_start: input.btn_start,
+					btn_a: input.btn_circle,
+					btn_b: input.btn_triangle,
+					btn_r: input.btn_r1,
+
+					twist,
+
+					btn_i: if input.btn_cross || btn_left { 0xFF } else { 0 },
+					btn_ii: if input.btn_square || btn_right { 0xFF } else { 0 },
+					btn_l: if input.btn_l1 { 0xFF } else { 0 },
+				});
+			},
+			// the buttons work with the mouse off the display too, the gun then reports it sees no light
+			DeviceType::GunCon => {
+				let shoot_offscreen = self.is_keyboard_input_down(GUNCON_SHOOT_OFFSCREEN, ctx);
+
+				psx.update_guncon(port, slot, GunConInput {
+					btn_trigger: btn_left || shoot_offscreen,
+					btn_a: btn_right,
+					btn_b: btn_middle,
+
+					aim: if shoot_offscreen { None } else { pointer },
+				});
+			},
+		}
+	}
+
+	fn get_input(&mut self, port: usize, slot: usize, ctx: &Context) -> InputState {
+		if let Some(id) = self.active_gamepads[port][slot] {
+			if let Some(gamepad) = self.gilrs.connected_gamepad(id) {
 				return InputState {
 					btn_up: gamepad.is_pressed(Button::DPadUp),
 					btn_down: gamepad.is_pressed(Button::DPadDown),
@@ -78,27 +192,30 @@ impl Input {
 			}
 		}
 
+		// nothing pressed on the slots without a gamepad
+		let keyboard = port == 0 && slot == 0;
+
 		InputState {
-			btn_up: self.is_keyboard_input_down(BTN_UP, ctx),
-			btn_down: self.is_keyboard_input_down(BTN_DOWN, ctx),
-			btn_left: self.is_keyboard_input_down(BTN_LEFT, ctx),
-			btn_right: self.is_keyboard_input_down(BTN_RIGHT, ctx),
-
-			btn_cross: self.is_keyboard_input_down(BTN_CROSS, ctx),
-			btn_square: self.is_keyboard_input_down(BTN_SQUARE, ctx),
-			btn_triangle: self.is_keyboard_input_down(BTN_TRIANGLE, ctx),
-			btn_circle: self.is_keyboard_input_down(BTN_CIRCLE, ctx),
-
-			btn_l1: self.is_keyboard_input_down(BTN_L1, ctx),
-			btn_l2: self.is_keyboard_input_down(BTN_L2, ctx),
+			btn_up: keyboard && self.is_keyboard_input_down(BTN_UP, ctx),
+			btn_down: keyboard && self.is_keyboard_input_down(BTN_DOWN, ctx),
+			btn_left: keyboard && self.is_keyboard_input_down(BTN_LEFT, ctx),
+			btn_right: keyboard && self.is_keyboard_input_down(BTN_RIGHT, ctx),
+
+			btn_cross: keyboard && self.is_keyboard_input_down(BTN_CROSS, ctx),
+			btn_square: keyboard && self.is_keyboard_input_down(BTN_SQUARE, ctx),
+			btn_triangle: keyboard && self.is_keyboard_input_down(BTN_TRIANGLE, ctx),
+			btn_circle: keyboard && self.is_keyboard_input_down(BTN_CIRCLE, ctx),
+
+			btn_l1: keyboard && self.is_keyboard_input_down(BTN_L1, ctx),
+			btn_l2: keyboard && self.is_keyboard_input_down(BTN_L2, ctx),
 			btn_l3: false,
 
-			btn_r1: self.is_keyboard_input_down(BTN_R1, ctx),
-			btn_r2: self.is_keyboard_input_down(BTN_R2, ctx),
+			btn_r1: keyboard && self.is_keyboard_input_down(BTN_R1, ctx),
+			btn_r2: keyboard && self.is_keyboard_input_down(BTN_R2, ctx),
 			btn_r3: false,
 
-			btn_start: self.is_keyboard_input_down(BTN_START, ctx),
-			btn_select: self.is_keyboard_input_down(BTN_SELECT, ctx),
+			btn_start: keyboard && self.is_keyboard_input_down(BTN_START, ctx),
+			btn_select: keyboard && self.is_keyboard_input_down(BTN_SELECT, ctx),
 
 			l_stick_x: 0x80,
 			l_stick_y: 0x80,
@@ -111,14 +228,14 @@ impl Input {
 		while let Some(event) = self.gilrs.next_event() {
 			match event {
 				Event { id, event: EventType::Connected, .. } => {
-					if self.active_gamepad.is_none() && self.gilrs.connected_gamepad(id).unwrap().name() != EXCLUDED_CONTROLLER {
-						self.active_gamepad = Some(id);
+					if self.active_gamepads[0][0].is_none() && self.gilrs.connected_gamepad(id).unwrap().name() != EXCLUDED_CONTROLLER {
+						self.active_gamepads[0][0] = Some(id);
 					}
 				},
 				Event { id, event: EventType::Disconnected, .. } => {
-					if let Some(active_id) = self.active_gamepad {
-						if id == active_id {
-							self.active_gamepad = None;
+					for active_gamepad in self.active_gamepads.iter_mut().flatten() {
+						if *active_gamepad == Some(id) {
+							*active_gamepad = None;
 						}
 					}
 				},
@@ -148,15 +265,23 @@ impl Input {
 	}
 
 	pub fn handle_rumble(&mut self, psx: &PSXEmulator) {
-		let Some(gamepad) = self.active_gamepad else {
+		for port in 0..PORTS {
+			for slot in 0..self.slots(port) {
+				self.handle_slot_rumble(port, slot, psx);
+			}
+		}
+	}
+
+	fn handle_slot_rumble(&mut self, port: usize, slot: usize, psx: &PSXEmulator) {
+		let Some(gamepad) = self.active_gamepads[port][slot] else {
 			return;
 		};
 
-		let (strong_motor, weak_motor) = psx.get_rumble();
+		let (strong_motor, weak_motor) = psx.get_rumble(port, slot);
 
 		if strong_motor == 0 && weak_motor == 0 {
 			// effects stop when dropped
-			self.current_effect = None;
+			self.current_effects[port][slot] = None;
 		}
 
 		let rumble_ticks = Ticks::from_ms(16);
@@ -178,31 +303,61 @@ impl Input {
 			.finish(&mut self.gilrs).unwrap();
 
 		effect.play().unwrap();
-		self.current_effect = Some(effect);
+		self.current_effects[port][slot] = Some(effect);
 
-		trace!("play rumble: M1: 0x{weak_motor:X} M2: 0x{strong_motor:X}");
+		trace!("play rumble port {}{}: M1: 0x{weak_motor:X} M2: 0x{strong_motor:X}", port + 1, slot_name(slot));
 	}
 
 	pub fn show_settings(&mut self, ui: &mut Ui, psx: &PSXEmulator) {
 		let controllers: Vec<(GamepadId, Gamepad<'_>)> = self.gilrs.gamepads().filter(|(_, gamepad)| gamepad.name() != EXCLUDED_CONTROLLER).collect();
 
-		let mut selected_controller = "Keyboard".to_string();
-		if let Some(id) = self.active_gamepad {
-			if let Some(gamepad) = self.gilrs.connected_gamepad(id) {
-				selected_controller = gamepad.name().to_string();
-			}
-		}
+		for port in 0..PORTS {
+			ui.checkbox(&mut self.multitaps[port], format!("Port {} Multitap", port + 1));
 
-		egui::ComboBox::from_label("Connected controller")
-			.selected_text(selected_controller)
-			.show_ui(ui, |ui| {
-				for (gamepad_id, gamepad) in controllers {
-					ui.selectable_value(&mut self.active_gamepad, Some(gamepad_id), gamepad.name());
+			for slot in 0..self.slots(port) {
+				// only port 1 slot A can use the keyboard
+				let none_name = if port == 0 && slot == 0 { "Keyboard" } else { "None" };
+
+				let mut selected_controller = none_name.to_string();
+				if let Some(id) = self.active_gamepads[port][slot] {
+					if let Some(gamepad) = self.gilrs.connected_gamepad(id) {
+						selected_controller = gamepad.name().to_string();
+					}
 				}
 
-				ui.selectable_value(&mut self.active_gamepad, None, "keyboard")
-			});
-		
-		ui.add_enabled(!psx.is_analog_locked(), egui::Checkbox::new(&mut self.analog_enabled, "Analog"));
+				let label = match self.multitaps[port] {
+					true => format!("Port {}{}", port + 1, slot_name(slot)),
+					false => format!("Port {}", port + 1),
+				};
+
+				ui.horizontal(|ui| {
+					let device_name = DEVICE_TYPES.iter().find(|(device, _)| *device == self.devices[port][slot]).map_or("", |(_, name)| name);
+
+					egui::ComboBox::from_id_salt(format!("{label} device"))
+						.selected_text(device_name)
+						.show_ui(ui, |ui| {
+							for (device, name) in DEVICE_TYPES {
+								ui.selectable_value(&mut self.devices[port][slot], device, name);
+							}
+						});
+
+					egui::ComboBox::from_label(&label)
+						.selected_text(selected_controller)
+						.show_ui(ui, |ui| {
+							for (gamepad_id, gamepad) in controllers.iter() {
+								ui.selectable_value(&mut self.active_gamepads[port][slot], Some(*gamepad_id), gamepad.name());
+							}
+
+							ui.selectable_value(&mut self.active_gamepads[port][slot], None, none_name)
+						});
+
+					ui.add_enabled(!psx.is_analog_locked(port, slot), egui::Checkbox::new(&mut self.analog_enabled[port][slot], "Analog"));
+				});
+			}
+		}
 	}
-}
\ No newline at end of file
+}
+
+fn slot_name(slot: usize) -> char {
+	(b'A' + slot as u8) as char
+}
@@ -6,8 +6,10 @@ use crate::gpu::Gpu;
 use crate::dma::DmaController;
 use crate::interrupts::Interrupts;
 use crate::mdec::Mdec;
+use crate::pgxp::Pgxp;
 use crate::scheduler::Scheduler;
 use crate::sio0::Sio0;
+use crate::sio1::Sio1;
 use crate::spu::Spu;
 use crate::timers::Timers;
 
@@ -52,7 +54,7 @@ const PAD_START: usize = 0x1F801040;
 const PAD_END: usize = 0x1F80104E;
 
 const SIO1_START: usize = 0x1F801050;
-const SIO1_END: usize = 0x1F80105E;
+const SIO1_END: usize = 0x1F80105F;
 
 const CDROM_START: usize = 0x1F801800;
 const CDROM_END: usize = 0x1F801803;
@@ -85,9 +87,12 @@ pub struct Bus {
 	pub interrupts: Interrupts,
 	pub timers: Timers,
 	pub sio0: Sio0,
+	pub sio1: Sio1,
 	pub spu: Spu,
 	pub mdec: Mdec,
 
+	pub pgxp: Pgxp,
+
 	pub read_breakpoints: Vec<u32>,
 	pub write_breakpoints: Vec<u32>,
 	pub breakpoint_hit: (bool, u32),
@@ -110,9 +115,12 @@ impl Bus {
 			interrupts: Interrupts::new(),
 			timers: Timers::new(),
 			sio0: Sio0::new(),
+			sio1: Sio1::new(),
 			spu: Spu::new(),
 			mdec: Mdec::new(),
 
+			pgxp: Pgxp::new(),
+
 			read_breakpoints: Vec::new(),
 			write_breakpoints: Vec::new(),
 			breakpoint_hit: (false, 0),
@@ -138,7 +146,7 @@ impl Bus {
 			GPU_START			..= GPU_END => 0,
 			DMA_START			..= DMA_END => self.dma.read8(addr),
 			PAD_START 			..= PAD_END => self.sio0.read32(addr) as u8,
-			SIO1_START			..= SIO1_END => { warn!("[0x{addr:X}] Unhandled SIO1 read8"); 0 }
+			SIO1_START			..= SIO1_END => self.sio1.read8(addr),
 			CDROM_START			..= CDROM_END => self.cdrom.read8(addr),
 			REDUX_START			..= REDUX_END => 0,
 
@@ -171,7 +179,7 @@ impl Bus {
 			IRQ_START			..= IRQ_END => self.interrupts.read32(addr) as u16,
 			SPU_START			..= SPU_END => self.spu.read16(addr),
 			PAD_START			..= PAD_END => self.sio0.read32(addr) as u16,
-			SIO1_START			..= SIO1_END => { warn!("[0x{addr:X}] Unhandled SIO1 read16"); 0 }
+			SIO1_START			..= SIO1_END => self.sio1.read16(addr),
 			TIMERS_START		..= TIMERS_END => self.timers.read32(addr, scheduler) as u16,
 			0x1F801130 => 0,
 			MEMCONTROL_START	..= MEMCONTROL_END => { warn!("[{addr:X}] Unhandled read16 from memcontrol"); 0 },
@@ -195,7 +203,7 @@ impl Bus {
 			IRQ_START			..= IRQ_END => self.interrupts.read32(addr),
 			TIMERS_START		..= TIMERS_END => self.timers.read32(addr, scheduler),
 			SPU_START			..= SPU_END => self.spu.read32(addr),
-			MDEC_START			..= MDEC_END => self.mdec.read32(addr),
+			MDEC_START			..= MDEC_END => self.mdec.read32(addr, scheduler),
 
 			_ => u32::from_le_bytes([
 				self.read8(addr, scheduler),
@@ -249,13 +257,13 @@ impl Bus {
 			RAM_START			..= RAM_END => self.ram[(addr as usize) & RAM_SIZE - RAM_START] = write,
 			SCRATCHPAD_START	..= SCRATCHPAD_END => self.scratchpad[addr as usize -  SCRATCHPAD_START] = write,
 
-			SPU_START			..= SPU_END => self.spu.write16(addr, write.into()),
+			SPU_START			..= SPU_END => self.spu.write16(addr, write.into(), scheduler),
 			TIMERS_START		..= TIMERS_END => { error!("write8 to timers [0x{addr:X} 0x{write:X}"); self.timers.write32(addr, write as u32, scheduler, &self.gpu); },
 			0x1F802041 => error!("POST {write}"),
 			EXPANSION2_START	..= EXPANSION2_END => debug!("write to expansion 2 register [0x{addr:X}] 0x{write:X}. Ignoring."),
 			CDROM_START			..= CDROM_END => self.cdrom.write8(addr, write, scheduler),
 			PAD_START			..= PAD_END => self.sio0.write32(addr, write.into(), scheduler),
-			SIO1_START			..= SIO1_END => warn!("[0x{addr:X}] Unhandled SIO1 write8 0x{write:X}"),
+			SIO1_START			..= SIO1_END => self.sio1.write8(addr, write, scheduler),
 			DMA_START			..= DMA_END => self.dma.write8(addr, write),
 			REDUX_START 		..= REDUX_END => match addr {
 				0x1F802080 => print!("{}", char::from_u32(write as u32).unwrap_or('?')),
@@ -281,10 +289,10 @@ impl Bus {
 
 		match addr as usize {
 			IRQ_START		..= IRQ_END => self.interrupts.write32(addr, write as u32),
-			SPU_START		..=	SPU_END => self.spu.write16(addr, write),
+			SPU_START		..=	SPU_END => self.spu.write16(addr, write, scheduler),
 			TIMERS_START	..= TIMERS_END => self.timers.write32(addr, write as u32, scheduler, &self.gpu),
 			PAD_START 		..= PAD_END => self.sio0.write32(addr, write.into(), scheduler),
-			SIO1_START		..= SIO1_END => warn!("[0x{addr:X}] Unhandled SIO1 write16 0x{write:X}"),
+			SIO1_START		..= SIO1_END => self.sio1.write16(addr, write, scheduler),
 			MEMCONTROL_START..= MEMCONTROL_END => warn!("[{addr:X}] Unhandled write16 from memcontrol"),
 			DMA_START		..= DMA_END => self.dma.write32(addr, write_unmasked),
 
@@ -353,8 +361,9 @@ impl Bus {
 				}
 			},
 			GPU_START			..= GPU_END => self.gpu.write32(addr, write),
-			SPU_START			..= SPU_END => self.spu.write32(addr, write),
-			MDEC_START			..= MDEC_END => self.mdec.write32(addr, write),
+			SPU_START			..= SPU_END => self.spu.write32(addr, write, scheduler),
+			MDEC_START			..= MDEC_END => self.mdec.write32(addr, write, scheduler),
+			SIO1_START			..= SIO1_END => self.sio1.write16(addr, write as u16, scheduler),
 			REDUX_START			..= REDUX_END => {},
 
 			_ => panic!("unhandled write32 [0x{:X}/0x{:X}] 0x{:X}", addr, unmasked_addr, write)
@@ -0,0 +1,288 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use log::*;
+
+use crate::gpu::Gpu;
+use crate::scheduler::{SPU_TICK_CYCLES, VBLANK_CYCLES};
+
+// audio/video capture, the mixed spu output goes to a wav file and the displayed area of every
+// frame to a video file. both are fed from the scheduler so they run off the same clock and can't
+// drift. VBLANK_CYCLES / SPU_TICK_CYCLES isn't whole (~743.77), so frames are 743 or 744 samples
+// long depending on where the spu ticks fall, and the total always matches the frame count
+
+const CPU_CLOCK: u64 = SPU_TICK_CYCLES * SAMPLE_RATE as u64;
+const SAMPLE_RATE: u32 = 44100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoFormat {
+	// yuv 4:4:4, readable by ffmpeg/mpv without extra arguments
+	Y4m,
+	// bare rgb24 frames, bit exact but the size and rate have to be passed to the reader
+	RawRgb24,
+}
+
+// 44.1khz 16 bit stereo
+pub struct WavWriter {
+	file: BufWriter<File>,
+	samples: u32,
+}
+
+impl WavWriter {
+	pub fn create(path: &Path) -> io::Result<Self> {
+		let mut file = BufWriter::new(File::create(path)?);
+
+		// the chunk sizes are filled in by finish
+		file.write_all(&wav_header(2, SAMPLE_RATE, 0, 0))?;
+
+		Ok(Self { file, samples: 0 })
+	}
+
+	pub fn write_sample(&mut self, (l, r): (i16, i16)) -> io::Result<()> {
+		self.file.write_all(&l.to_le_bytes())?;
+		self.file.write_all(&r.to_le_bytes())?;
+		self.samples += 1;
+
+		Ok(())
+	}
+
+	pub fn finish(mut self) -> io::Result<()> {
+		let data_len = self.samples * 4;
+
+		self.file.seek(SeekFrom::Start(4))?;
+		self.file.write_all(&(36 + data_len).to_le_bytes())?;
+		self.file.seek(SeekFrom::Start(40))?;
+		self.file.write_all(&data_len.to_le_bytes())?;
+
+		self.file.flush()
+	}
+}
+
+pub struct VideoWriter {
+	file: BufWriter<File>,
+	format: VideoFormat,
+	// frames per second as a fraction
+	frame_rate: (u64, u64),
+	// the stream can't change size, it's fixed by the first frame
+	size: Option<(usize, usize)>,
+}
+
+impl VideoWriter {
+	pub fn create(path: &Path, format: VideoFormat, frame_rate: (u64, u64)) -> io::Result<Self> {
+		Ok(Self {
+			file: BufWriter::new(File::create(path)?),
+			format,
+			frame_rate,
+			size: None,
+		})
+	}
+
+	pub fn write_frame(&mut self, width: usize, height: usize, rgb: &[u8]) -> io::Result<()> {
+		let (out_width, out_height) = match self.size {
+			Some(size) => size,
+			None => {
+				let (num, den) = self.frame_rate;
+
+				if self.format == VideoFormat::Y4m {
+					writeln!(self.file, "YUV4MPEG2 W{width} H{height} F{num}:{den} Ip A1:1 C444 XCOLORRANGE=FULL")?;
+				}
+
+				info!("video capture: {width}x{height} at {:.3}fps", num as f64 / den as f64);
+
+				self.size = Some((width, height));
+				(width, height)
+			}
+		};
+
+		if (width, height) != (out_width, out_height) {
+			debug!("video capture: {width}x{height} frame centered in {out_width}x{out_height}");
+		}
+
+		let frame = fit_frame(rgb, (width, height), (out_width, out_height));
+
+		match self.format {
+			VideoFormat::Y4m => {
+				self.file.write_all(b"FRAME\n")?;
+				self.file.write_all(&rgb_to_yuv444(&frame))
+			},
+			VideoFormat::RawRgb24 => self.file.write_all(&frame),
+		}
+	}
+
+	pub fn finish(mut self) -> io::Result<()> {
+		self.file.flush()
+	}
+}
+
+pub struct AvCapture {
+	wav: Option<WavWriter>,
+	video: Option<VideoWriter>,
+
+	// nothing is written before the first vblank so both streams start on a frame boundary
+	started: bool,
+	frames: u64,
+	// stops at the vblank after the last frame so it gets its full share of audio
+	frame_limit: Option<u64>,
+	limit_reached: bool,
+
+	// the first write error stops the capture, it's returned by finish
+	error: Option<io::Error>,
+}
+
+impl AvCapture {
+	pub fn new(wav_path: Option<&Path>, video: Option<(&Path, VideoFormat)>) -> io::Result<Self> {
+		Ok(Self {
+			wav: wav_path.map(WavWriter::create).transpose()?,
+			video: video.map(|(path, format)| VideoWriter::create(path, format, frame_rate())).transpose()?,
+
+			started: false,
+			frames: 0,
+			frame_limit: None,
+			limit_reached: false,
+
+			error: None,
+		})
+	}
+
+	// frames captured so far
+	pub fn frames(&self) -> u64 {
+		self.frames
+	}
+
+	pub fn set_frame_limit(&mut self, limit: Option<u64>) {
+		self.frame_limit = limit;
+	}
+
+	// frame limit reached or a write failed, nothing more gets written
+	pub fn is_finished(&self) -> bool {
+		self.error.is_some() || self.limit_reached
+	}
+
+	pub(crate) fn audio_sample(&mut self, sample: (i16, i16)) {
+		if !self.started || self.is_finished() {
+			return;
+		}
+
+		if let Some(wav) = &mut self.wav {
+			if let Err(err) = wav.write_sample(sample) {
+				self.fail(err);
+			}
+		}
+	}
+
+	pub(crate) fn vblank(&mut self, gpu: &Gpu) {
+		if self.is_finished() {
+			return;
+		}
+
+		if self.frame_limit == Some(self.frames) {
+			self.limit_reached = true;
+			return;
+		}
+
+		self.started = true;
+		self.frames += 1;
+
+		if let Some(video) = &mut self.video {
+			let (width, height, rgb) = gpu.get_display_frame();
+
+			if let Err(err) = video.write_frame(width, height, &rgb) {
+				self.fail(err);
+			}
+		}
+	}
+
+	fn fail(&mut self, err: io::Error) {
+		error!("capture stopped: {err}");
+		self.error = Some(err);
+	}
+
+	pub fn finish(self) -> io::Result<()> {
+		if let Some(wav) = self.wav {
+			wav.finish()?;
+		}
+
+		if let Some(video) = self.video {
+			video.finish()?;
+		}
+
+		match self.error {
+			Some(err) => Err(err),
+			None => Ok(()),
+		}
+	}
+}
+
+// header of a 16 bit pcm wav up to the start of the sample data, extra_len is the size of any
+// chunks that follow the data
+pub(crate) fn wav_header(channels: u16, sample_rate: u32, data_len: u32, extra_len: u32) -> Vec<u8> {
+	let frame_len = channels * 2;
+
+	let mut header = Vec::with_capacity(44);
+
+	header.extend_from_slice(b"RIFF");
+	header.extend_from_slice(&(36 + data_len + extra_len).to_le_bytes());
+	header.extend_from_slice(b"WAVEfmt ");
+	header.extend_from_slice(&16u32.to_le_bytes());
+	header.extend_from_slice(&1u16.to_le_bytes());								// pcm
+	header.extend_from_slice(&channels.to_le_bytes());
+	header.extend_from_slice(&sample_rate.to_le_bytes());
+	header.extend_from_slice(&(sample_rate * u32::from(frame_len)).to_le_bytes());	// bytes per second
+	header.extend_from_slice(&frame_len.to_le_bytes());							// bytes per sample frame
+	header.extend_from_slice(&16u16.to_le_bytes());								// bits per sample
+	header.extend_from_slice(b"data");
+	header.extend_from_slice(&data_len.to_le_bytes());
+
+	header
+}
+
+// vblank rate as a reduced fraction
+fn frame_rate() -> (u64, u64) {
+	let (mut a, mut b) = (CPU_CLOCK, VBLANK_CYCLES);
+
+	while b != 0 {
+		(a, b) = (b, a % b);
+	}
+
+	(CPU_CLOCK / a, VBLANK_CYCLES / a)
+}
+
+// centers the frame in the output size, cropping or padding with black
+fn fit_frame(rgb: &[u8], (width, height): (usize, usize), (out_width, out_height): (usize, usize)) -> Vec<u8> {
+	if (width, height) == (out_width, out_height) {
+		return rgb.to_vec();
+	}
+
+	let mut out = vec![0; out_width * out_height * 3];
+
+	let copy_width = width.min(out_width);
+	let (src_x, dst_x) = ((width - copy_width) / 2, (out_width - copy_width) / 2);
+
+	for y in 0..height.min(out_height) {
+		let (src_y, dst_y) = (y + (height.saturating_sub(out_height)) / 2, y + (out_height.saturating_sub(height)) / 2);
+
+		let src = (src_y * width + src_x) * 3;
+		let dst = (dst_y * out_width + dst_x) * 3;
+
+		out[dst..dst + copy_width * 3].copy_from_slice(&rgb[src..src + copy_width * 3]);
+	}
+
+	out
+}
+
+// full range bt.601, planar y then u then v
+fn rgb_to_yuv444(rgb: &[u8]) -> Vec<u8> {
+	let pixels = rgb.len() / 3;
+	let mut yuv = vec![0; pixels * 3];
+
+	for (i, px) in rgb.chunks_exact(3).enumerate() {
+		let (r, g, b) = (f32::from(px[0]), f32::from(px[1]), f32::from(px[2]));
+
+		yuv[i] = (0.299 * r + 0.587 * g + 0.114 * b).round() as u8;
+		yuv[pixels + i] = (128.0 - 0.168736 * r - 0.331264 * g + 0.5 * b).round().clamp(0.0, 255.0) as u8;
+		yuv[pixels * 2 + i] = (128.0 + 0.5 * r - 0.418688 * g - 0.081312 * b).round().clamp(0.0, 255.0) as u8;
+	}
+
+	yuv
+}
@@ -9,6 +9,22 @@ pub const READ_CYCLES: [u64; 2] = [0x6E1CD, 0x36CD2]; // single speed, double sp
 //pub const READ_CYCLES: [u64; 2] = [0x100, 0x200];
 pub const PAUSE_CYCLES: [u64; 2] = [0x21181C, 0x10BD93];
 
+const CYCLES_PER_SECOND: u64 = 33868800;
+
+// from standstill, and between single and double speed
+const SPIN_UP_CYCLES: u64 = CYCLES_PER_SECOND;
+const SPEED_CHANGE_CYCLES: u64 = CYCLES_PER_SECOND * 65 / 100;
+// spinning down before the second response of Stop
+const STOP_CYCLES: [u64; 2] = [CYCLES_PER_SECOND * 4 / 10, CYCLES_PER_SECOND * 3 / 4];
+
+// targets closer than this are reached by moving the lens, further ones need the sled. the sled
+// accelerates, so the time grows with the square root of the distance up to a full stroke
+const LENS_SEEK_SECTORS: usize = 32;
+const SLED_SEEK_CYCLES: u64 = CYCLES_PER_SECOND / 10;
+const FULL_STROKE_CYCLES: u64 = CYCLES_PER_SECOND * 3 / 10;
+const DISC_SECTORS: usize = 74 * 60 * 75;
+
+const ERROR_SEEK_FAILED:	u8 = 0x04;	// no data header where one was expected
 const ERROR_INVALID_SUBCMD: u8 = 0x10;
 const ERROR_INVALID_PARAMS: u8 = 0x20;
 const ERROR_INVALID_CMD: 	u8 = 0x40;
@@ -34,6 +50,7 @@ impl Cdrom {
 
 						second_response: None,
 						on_complete: None,
+						first_of_cmd: None,
 					}, AVG_CYCLES)
 				},
 				_ => todo!("subcommand 0x{sub_cmd:X}")
@@ -60,6 +77,7 @@ impl Cdrom {
 
 			second_response: None,
 			on_complete: None,
+			first_of_cmd: None,
 		};
 
 		if self.disc.is_none() {
@@ -84,7 +102,8 @@ impl Cdrom {
 			int_level: 3,
 			result: vec![self.get_stat(), first, last],
 			second_response: None,
-			on_complete: None
+			on_complete: None,
+			first_of_cmd: None
 		}, AVG_CYCLES)
 	}
 
@@ -117,7 +136,8 @@ impl Cdrom {
 				int_level: 3,
 				result: vec![self.get_stat(), binary_to_bcd(track_index.minutes), binary_to_bcd(track_index.seconds)],
 				second_response: None,
-				on_complete: None
+				on_complete: None,
+				first_of_cmd: None
 			}, AVG_CYCLES)
 		} else {
 			(CmdResponse::error(&self, ERROR_CANNOT_RESPOND), AVG_CYCLES)
@@ -171,32 +191,24 @@ impl Cdrom {
 
 	pub fn get_loc_p(&mut self) -> (CmdResponse, u64) {
 		if let Some(disc) = &self.disc {
-			let current_sector = match self.drive_state {
-				DriveState::Idle => self.current_seek,
-				DriveState::Seek => self.seek_target,
-				DriveState::Read => self.current_seek + self.read_offset,
-				DriveState::Play => self.current_seek,
+			// the last sector that went under the laser
+			let lba = match self.drive_state {
+				DriveState::Idle => self.current_seek.to_lba(),
+				DriveState::Seek => self.seek_target.to_lba(),
+				DriveState::Read | DriveState::Play => (self.current_seek + self.read_offset).to_lba().saturating_sub(1).max(self.current_seek.to_lba()),
 			};
 
-			let (track, track_addr) = disc.get_track_number(current_sector.to_lba());
-			let relative_time = CdIndex::from_lba(current_sector.to_lba() - disc.tracks[track].start_lba);
+			// LibCrypt sectors have a bad crc, the position of the last good one is returned
+			let q = disc.last_valid_subchannel_q(lba);
 
-			debug!("GetLocP {current_sector}, relative: {relative_time}");
+			debug!("GetLocP lba {lba} track {:02X} index {:02X} relative {:02X?} absolute {:02X?}", q[1], q[2], &q[3..6], &q[7..10]);
 
-			let index: u8 = 1;
-			
 			let mut response = CmdResponse::int3_status(&self);
 			response.result = vec![
-				binary_to_bcd(track as u8),
-				binary_to_bcd(index),
-
-				binary_to_bcd(relative_time.minutes),
-				binary_to_bcd(relative_time.seconds),
-				binary_to_bcd(relative_time.sectors),
-
-				binary_to_bcd(current_sector.minutes),
-				binary_to_bcd(current_sector.seconds),
-				binary_to_bcd(current_sector.sectors),
+				// track, index, relative msf
+				q[1], q[2], q[3], q[4], q[5],
+				// absolute msf
+				q[7], q[8], q[9],
 			];
 
 			(response, AVG_CYCLES)
@@ -205,9 +217,78 @@ impl Cdrom {
 		}
 	}
 
+	// Q of a lead-in toc entry
+	pub fn get_q(&mut self) -> (CmdResponse, u64) {
+		if self.params_fifo.len() < 2 {
+			return (CmdResponse::error(self, ERROR_INVALID_PARAMS), AVG_CYCLES);
+		}
+
+		let adr = self.params_fifo.pop_front().unwrap();
+		let point = self.params_fifo.pop_front().unwrap();
+
+		debug!("GetQ adr {adr} point {point:02X}");
+
+		let Some(disc) = &self.disc else {
+			return (CmdResponse::error(self, ERROR_CANNOT_RESPOND), AVG_CYCLES);
+		};
+
+		let Some(q) = disc.toc_q(point).filter(|_| adr == 1) else {
+			return (CmdResponse::error(self, ERROR_INVALID_PARAMS), AVG_CYCLES);
+		};
+
+		let mut first_response = CmdResponse::int3_status(self);
+		let second_response = CmdResponse {
+			int_level: 2,
+			result: q.to_vec(),
+			second_response: None,
+			on_complete: None,
+			first_of_cmd: None,
+		};
+
+		first_response.second_response = Some((Box::new(second_response), DELAY_1MS));
+		(first_response, AVG_CYCLES)
+	}
+
+	// time for the laser to get from the current position to the target, including whatever is
+	// left of a spin up
+	fn seek_cycles(&mut self, target: CdIndex) -> u64 {
+		if self.fast_cd {
+			return 0;
+		}
+
+		let distance = (self.current_seek + self.read_offset).to_lba().abs_diff(target.to_lba());
+
+		let travel = match distance {
+			0 => 0,
+			// the lens reaches it, then waits for the sectors to come around
+			1..LENS_SEEK_SECTORS => READ_CYCLES[self.drive_speed as usize] * distance.min(5) as u64,
+			_ => SLED_SEEK_CYCLES + (FULL_STROKE_CYCLES as f64 * (distance as f64 / DISC_SECTORS as f64).sqrt()) as u64,
+		};
+
+		trace!("seek {} -> {target} ({distance} sectors) {travel} cycles", self.current_seek + self.read_offset);
+
+		travel + self.spindle_delay()
+	}
+
+	// starts the motor if it's off, then the time until the spindle is at speed
+	fn spindle_delay(&mut self) -> u64 {
+		if self.fast_cd {
+			return 0;
+		}
+
+		if !self.motor_on {
+			self.motor_on = true;
+			self.spindle_ready_at = self.now + SPIN_UP_CYCLES;
+		}
+
+		self.spindle_ready_at.saturating_sub(self.now)
+	}
+
 	pub fn seek_l(&mut self) -> (CmdResponse, u64) {
 		debug!("SeekL");
 
+		let seek_cycles = self.seek_cycles(self.seek_target);
+
 		let mut first_response = CmdResponse::int3_status(&self);
 
 		let second_response = CmdResponse {
@@ -215,11 +296,12 @@ impl Cdrom {
 			result: vec![self.get_stat()],
 			second_response: None,
 			on_complete: Some(Self::seek_l_complete),
+			first_of_cmd: None,
 		};
 
 		self.drive_state = DriveState::Seek;
 
-		first_response.second_response = Some((Box::new(second_response), DELAY_1MS));
+		first_response.second_response = Some((Box::new(second_response), seek_cycles.max(DELAY_1MS)));
 
 		(first_response, AVG_CYCLES)
 	}
@@ -228,6 +310,8 @@ impl Cdrom {
 	pub fn seek_p(&mut self) -> (CmdResponse, u64) {
 		debug!("SeekP");
 
+		let seek_cycles = self.seek_cycles(self.seek_target);
+
 		let mut first_response = CmdResponse::int3_status(&self);
 
 		let second_response = CmdResponse {
@@ -235,11 +319,12 @@ impl Cdrom {
 			result: vec![self.get_stat()],
 			second_response: None,
 			on_complete: Some(Self::seek_l_complete),
+			first_of_cmd: None,
 		};
 
 		self.drive_state = DriveState::Seek;
 
-		first_response.second_response = Some((Box::new(second_response), DELAY_1MS));
+		first_response.second_response = Some((Box::new(second_response), seek_cycles.max(DELAY_1MS)));
 
 		(first_response, AVG_CYCLES)
 	}
@@ -247,6 +332,7 @@ impl Cdrom {
 	pub fn seek_l_complete(&mut self) -> Option<(CmdResponse, u64)> {
 		//trace!("SeekL complete");
 		self.current_seek = self.seek_target;
+		self.read_offset = CdIndex::ZERO;
 		self.seek_complete = true;
 		self.drive_state =  DriveState::Idle;
 
@@ -262,6 +348,8 @@ impl Cdrom {
 		self.xa_adpcm_info.xa_file = self.params_fifo.pop_front().unwrap();
 		self.xa_adpcm_info.xa_channel = self.params_fifo.pop_front().unwrap();
 
+		debug!("SetFilter file {} channel {}", self.xa_adpcm_info.xa_file, self.xa_adpcm_info.xa_channel);
+
 		(CmdResponse::int3_status(&self), AVG_CYCLES)
 	}
 
@@ -274,13 +362,23 @@ impl Cdrom {
 		self.last_sector_size = self.sector_size;
 
 		let new_mode = self.params_fifo.pop_front().unwrap();
-		self.drive_speed = DriveSpeed::from_bits((new_mode >> 7) & 1 != 0);
+		let new_speed = DriveSpeed::from_bits((new_mode >> 7) & 1 != 0);
+
+		// reads wait for the spindle to settle at the new speed
+		if new_speed != self.drive_speed && self.motor_on && !self.fast_cd {
+			self.spindle_ready_at = self.spindle_ready_at.max(self.now) + SPEED_CHANGE_CYCLES;
+		}
+
+		self.drive_speed = new_speed;
 		self.xa_adpcm_info.xa_enabled = (new_mode >> 6) & 1 != 0;
 		self.sector_size = SectorSize::from_bits((new_mode >> 5) & 1 != 0);
 		self.ignore_cur_sector_size = (new_mode >> 4) & 1 != 0;
 		self.xa_adpcm_info.xa_filter = (new_mode >> 3) & 1 != 0;
+		self.report_enabled = (new_mode >> 2) & 1 != 0;
+		self.auto_pause = (new_mode >> 1) & 1 != 0;
+		self.cdda_enabled = new_mode & 1 != 0;
 
-		debug!("SetMode 0b{new_mode:b} {:?} {:?} ignore bit: {}", self.drive_speed, self.sector_size, self.ignore_cur_sector_size);
+		debug!("SetMode 0b{new_mode:b} {:?} {:?} ignore bit: {} cdda: {} autopause: {} report: {}", self.drive_speed, self.sector_size, self.ignore_cur_sector_size, self.cdda_enabled, self.auto_pause, self.report_enabled);
 
 		(CmdResponse::int3_status(self), AVG_CYCLES)
 	}
@@ -290,15 +388,15 @@ impl Cdrom {
 			return (CmdResponse::error(&self, ERROR_CANNOT_RESPOND), AVG_CYCLES);
 		}
 
+		let target = if !self.seek_complete { self.seek_target } else { self.current_seek };
+		let seek_cycles = self.seek_cycles(target);
+
 		self.read_offset = CdIndex::ZERO;
 		self.read_paused = false;
 		self.drive_state = DriveState::Read;
+		self.current_seek = target;
 		
-		if !self.seek_complete {
-			self.current_seek = self.seek_target;
-		}
-		
-		debug!("ReadN START @ {}", self.current_seek);
+		debug!("ReadN START @ {} after {seek_cycles} seek cycles", self.current_seek);
 
 		let mut first_response = CmdResponse::int3_status(self);
 
@@ -306,10 +404,13 @@ impl Cdrom {
 			int_level: 1,
 			result: vec![self.get_stat()],
 			second_response: None,
-			on_complete: Some(Self::read_n_complete)
+			on_complete: Some(Self::read_n_complete),
+			first_of_cmd: None
 		};
 
-		first_response.second_response = Some((Box::new(first_read), READ_CYCLES[self.drive_speed as usize]));
+		self.seeking = seek_cycles > 0;
+
+		first_response.second_response = Some((Box::new(first_read), seek_cycles + READ_CYCLES[self.drive_speed as usize]));
 		(first_response, AVG_CYCLES)
 	}
 
@@ -318,11 +419,36 @@ impl Cdrom {
 			return None;
 		}
 
+		self.seeking = false;
+
 		if let Some(disc) = &self.disc {
 			trace!("Read sector {} ({} + {}) {} {:?}", (self.current_seek + self.read_offset), self.current_seek, self.read_offset, self.read_paused, self.drive_state);
 
 			let sector = disc.read_sector(self.current_seek + self.read_offset);
 
+			// cd-da sectors can only be read in cdda mode, and are always read whole
+			if sector.is_audio() {
+				if !self.cdda_enabled {
+					warn!("ReadN on a CD-DA sector at {} without CD-DA mode", self.current_seek + self.read_offset);
+
+					self.drive_state = DriveState::Idle;
+					return Some((CmdResponse::error(self, ERROR_SEEK_FAILED), READ_CYCLES[self.drive_speed as usize]));
+				}
+
+				self.data_fifo.read_sector(sector.audio_sector());
+				self.read_offset = self.read_offset + CdIndex::new(0, 0, 1);
+
+				let next_read = CmdResponse {
+					int_level: 1,
+					result: vec![self.get_stat()],
+					second_response: None,
+					on_complete: Some(Self::read_n_complete),
+					first_of_cmd: None
+				};
+
+				return Some((next_read, READ_CYCLES[self.drive_speed as usize]));
+			}
+
 			let data = match if !self.ignore_cur_sector_size { self.sector_size } else { self.last_sector_size } {
 				SectorSize::DataOnly => sector.data_only(),
 				SectorSize::WholeSector => sector.whole_sector()
@@ -332,8 +458,15 @@ impl Cdrom {
 			let sect_pos = CdIndex::from_bcd(raw_sector[0xC], raw_sector[0xD], raw_sector[0xE]);
 
 			debug!("ReadN sector: {} actual sector: {sect_pos}", self.current_seek + self.read_offset); */
-			if sector.is_xa_adpcm(&self.xa_adpcm_info) {
-				
+			// adpcm sectors don't reach the cpu, so there's no INT1 for them
+			let is_xa_adpcm = sector.is_xa_adpcm(&self.xa_adpcm_info);
+
+			if is_xa_adpcm {
+				let subheader = sector.subheader();
+
+				if self.xa_adpcm_info.filter_matches(subheader[0], subheader[1]) {
+					self.xa_adpcm.decode_xa_sector(&sector);
+				}
 			} else {
 				self.data_fifo.read_sector(data);
 			}
@@ -341,10 +474,11 @@ impl Cdrom {
 			self.read_offset = self.read_offset + CdIndex::new(0, 0, 1);
 
 			let next_read = CmdResponse {
-				int_level: 1,
-				result: vec![self.get_stat()],
+				int_level: if is_xa_adpcm { 0 } else { 1 },
+				result: if is_xa_adpcm { vec![] } else { vec![self.get_stat()] },
 				second_response: None,
-				on_complete: Some(Self::read_n_complete)
+				on_complete: Some(Self::read_n_complete),
+				first_of_cmd: None
 			};
 
 			return Some((next_read, READ_CYCLES[self.drive_speed as usize]));
@@ -363,12 +497,14 @@ impl Cdrom {
 		// clear stat for second response
 		self.read_paused = true;
 		self.drive_state = DriveState::Idle;
+		self.seeking = false;
 
 		let second_response = CmdResponse {
 			int_level: 2, 
 			result: vec![self.get_stat()],
 			second_response: None,
 			on_complete: None,
+			first_of_cmd: None,
 		};
 
 
@@ -382,26 +518,26 @@ impl Cdrom {
 			return (CmdResponse::error(&self, ERROR_CANNOT_RESPOND), AVG_CYCLES);
 		};
 
+		// if track param is sent and track>0, start playback at the start of the track
+		// otherwise start playback for current seek location
+		let target = match self.params_fifo.pop_front() {
+			Some(track) if track > 0 => disc.get_track_start(track as usize),
+			Some(_) => self.current_seek,
+			None => self.seek_target,
+		};
+
+		let play_track = disc.get_track_offset(target).1;
+		let seek_cycles = self.seek_cycles(target);
+
+		debug!("Play track {play_track} @ {target}");
+
 		self.read_offset = CdIndex::ZERO;
 		self.read_paused = false;
 		self.drive_state = DriveState::Play;
+		self.current_seek = target;
 
-		/* if !self.seek_complete {
-			self.current_seek = self.seek_target;
-		} */
-
-		// if track param is sent and track>0, start playback at the start of the track
-		// otherwise start playback for current seek location
-		if let Some(track) = self.params_fifo.pop_front() {
-			debug!("Play track {track}");
-			if track > 0 {
-				self.current_seek = disc.get_track_start(track as usize);
-				debug!("Play track {track} @ {}", self.current_seek);
-			}
-		} else {
-			self.current_seek = self.seek_target;
-			debug!("Play @ {}", self.current_seek);
-		}
+		self.play_track = play_track;
+		self.last_report_frame = None;
 
 		let mut first_response = CmdResponse::int3_status(self);
 
@@ -409,10 +545,13 @@ impl Cdrom {
 			int_level: 0,
 			result: vec![],
 			second_response: None,
-			on_complete: Some(Self::play_complete)
+			on_complete: Some(Self::play_complete),
+			first_of_cmd: None
 		};
 
-		first_response.second_response = Some((Box::new(first_read), READ_CYCLES[0]));
+		self.seeking = seek_cycles > 0;
+
+		first_response.second_response = Some((Box::new(first_read), seek_cycles + READ_CYCLES[0]));
 		(first_response, AVG_CYCLES)
 
 	}
@@ -422,22 +561,53 @@ impl Cdrom {
 			return None;
 		}
 
+		self.seeking = false;
+
 		if let Some(disc) = &self.disc {
-			trace!("Play sector {} ({} + {}) {} {:?}", (self.current_seek + self.read_offset), self.current_seek, self.read_offset, self.read_paused, self.drive_state);
+			let index = self.current_seek + self.read_offset;
 
-			let sector = disc.read_sector(self.current_seek + self.read_offset);
+			trace!("Play sector {index} ({} + {}) {} {:?}", self.current_seek, self.read_offset, self.read_paused, self.drive_state);
+
+			// the end of the disc always stops playback, the end of a track only with autopause
+			if index.to_lba() >= disc.end_lba() {
+				debug!("Play reached the end of the disc");
+				return Some((self.data_end(), 0));
+			}
+
+			let track = disc.get_track_offset(index).1;
+			let q = disc.last_valid_subchannel_q(index.to_lba());
+
+			if track != self.play_track {
+				if self.auto_pause {
+					debug!("Play autopause at the end of track {}", self.play_track);
+					return Some((self.data_end(), 0));
+				}
+
+				self.play_track = track;
+			}
+
+			let sector = disc.read_sector(index);
 			let data = sector.audio_sector();
 
 			self.audio_buf.read_sector(data);
 
 			self.read_offset = self.read_offset + CdIndex::new(0, 0, 1);
 
-			// TODO report irqs
-			let next_read = CmdResponse {
-				int_level: 0,
-				result: vec![0],
-				second_response: None,
-				on_complete: Some(Self::play_complete)
+			// the tens digit of the absolute frame
+			let report_frame = q[9] >> 4;
+
+			let next_read = match self.report_enabled && self.last_report_frame != Some(report_frame) {
+				true => {
+					self.last_report_frame = Some(report_frame);
+					self.report(q, data)
+				},
+				false => CmdResponse {
+					int_level: 0,
+					result: vec![],
+					second_response: None,
+					on_complete: Some(Self::play_complete),
+					first_of_cmd: None
+				},
 			};
 
 			// single speed only (?)
@@ -447,21 +617,87 @@ impl Cdrom {
 		None
 	}
 
+	// INT1 while playing with report mode on, every 10 sectors. the time from subchannel Q
+	// alternates between absolute and relative to the track, relative has bit 7 of the seconds set
+	fn report(&mut self, q: [u8; 12], data: &[u8]) -> CmdResponse {
+		let (minutes, seconds, sectors) = match q[9] & 0x10 {
+			0 => (q[7], q[8], q[9]),
+			_ => (q[3], q[4] | 0x80, q[5]),
+		};
+
+		// highest level of one channel in the sector, bit 15 says which
+		let channel = usize::from(self.report_right_peak);
+
+		let peak = data.chunks_exact(4)
+			.map(|frame| i16::from_le_bytes([frame[channel * 2], frame[channel * 2 + 1]]).unsigned_abs().min(0x7FFF))
+			.max()
+			.unwrap_or(0) | (u16::from(self.report_right_peak) << 15);
+
+		self.report_right_peak = !self.report_right_peak;
+
+		trace!("Play report track {:02X} {minutes:02X}:{seconds:02X}:{sectors:02X} peak 0x{peak:04X}", q[1]);
+
+		CmdResponse {
+			int_level: 1,
+			result: vec![
+				self.get_stat(),
+				q[1],
+				q[2],
+				minutes,
+				seconds,
+				sectors,
+				peak as u8,
+				(peak >> 8) as u8,
+			],
+			second_response: None,
+			on_complete: Some(Self::play_complete),
+			first_of_cmd: None
+		}
+	}
+
+	// INT4 at the end of a track with autopause, or at the end of the disc. the drive pauses there
+	fn data_end(&mut self) -> CmdResponse {
+		self.read_paused = true;
+		self.drive_state = DriveState::Idle;
+		self.current_seek = self.current_seek + self.read_offset;
+		self.read_offset = CdIndex::ZERO;
+
+		CmdResponse {
+			int_level: 4,
+			result: vec![self.get_stat()],
+			second_response: None,
+			on_complete: None,
+			first_of_cmd: None,
+		}
+	}
+
 	pub fn stop(&mut self) -> (CmdResponse, u64) {
 		debug!("Stop");
 
+		let stop_cycles = match self.motor_on && !self.fast_cd {
+			true => STOP_CYCLES[self.drive_speed as usize],
+			false => DELAY_1MS,
+		};
+
 		self.drive_state = DriveState::Idle;
 		self.read_paused = true;
+		self.seeking = false;
+		self.xa_adpcm.clear();
 
 		let mut first_response = CmdResponse::int3_status(&self);
+
+		// the motor is off by the second response
+		self.motor_on = false;
+
 		let second_response = CmdResponse {
 			int_level: 2,
 			result: vec![self.get_stat()],
 			second_response: None,
 			on_complete: None,
+			first_of_cmd: None,
 		};
 
-		first_response.second_response = Some((Box::new(second_response), DELAY_1MS));
+		first_response.second_response = Some((Box::new(second_response), stop_cycles));
 		(first_response, AVG_CYCLES)
 	}
 
@@ -469,7 +705,9 @@ impl Cdrom {
 		// TODO this cmd should abort all other commands
 		// TODO set mode to 0x20
 		// this should also happen on the second response
+		let spin_up_cycles = self.spindle_delay();
 		self.motor_on = true;
+		self.seeking = false;
 
 		debug!("Init");
 
@@ -479,9 +717,10 @@ impl Cdrom {
 			result: vec![self.get_stat()],
 			second_response: None,
 			on_complete: None,
+			first_of_cmd: None,
 		};
 
-		first_response.second_response = Some((Box::new(second_response), DELAY_1MS));
+		first_response.second_response = Some((Box::new(second_response), DELAY_1MS + spin_up_cycles));
 		(first_response, 0x13CCE)
 	}
 
@@ -503,6 +742,7 @@ impl Cdrom {
 
 	// just a copy of init
 	pub fn motor_on(&mut self) -> (CmdResponse, u64) {
+		let spin_up_cycles = self.spindle_delay();
 		self.motor_on = true;
 
 		let mut first_response = CmdResponse::int3_status(&self);
@@ -511,9 +751,10 @@ impl Cdrom {
 			result: vec![self.get_stat()],
 			second_response: None,
 			on_complete: None,
+			first_of_cmd: None,
 		};
 
-		first_response.second_response = Some((Box::new(second_response), DELAY_1MS));
+		first_response.second_response = Some((Box::new(second_response), DELAY_1MS + spin_up_cycles));
 		(first_response, 0x13CCE)
 	}
 
@@ -1,12 +1,24 @@
-use std::{fmt::Display, fs::File, io::Read, ops::{Add, Sub}, path::Path};
+use std::{collections::HashMap, fmt::Display, fs::{self, File}, io::{self, Read}, ops::{Add, Sub}, path::Path};
 use log::*;
 
 use crate::cdrom::XaAdpcmInfo;
+use crate::cdrom::patch::{self, invalid_data};
 
 const SECONDS_PER_MINUTE: usize = 60;
 const SECTORS_PER_SECOND: usize = 75;
 pub const BYTES_PER_SECTOR: usize = 0x930;
 
+// start of every data sector, cd-da sectors are samples from the first byte
+pub const SECTOR_SYNC: [u8; 12] = [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+
+// raw subchannel files hold 96 bytes per sector, P then Q then R-W, 12 bytes each
+const SUB_BYTES_PER_SECTOR: usize = 96;
+const SBI_MAGIC: &[u8; 4] = b"SBI\0";
+const LSD_ENTRY_LEN: usize = 15;
+
+// without a crc match the drive keeps reporting the last good position, this far back at most
+const MAX_BAD_Q_SECTORS: usize = 16;
+
 #[derive(Clone, Copy, Debug)]
 pub struct CdIndex {
 	pub minutes: u8,
@@ -35,11 +47,14 @@ impl CdIndex {
 	}
 
 	pub fn from_lba(lba: usize) -> Self {
-		let lba = lba + 150;
+		Self::from_sectors(lba + 150)
+	}
 
-		let minutes = lba / (SECTORS_PER_SECOND * SECONDS_PER_MINUTE);
-		let seconds = (lba / SECTORS_PER_SECOND) % SECONDS_PER_MINUTE;
-		let sectors = lba % SECTORS_PER_SECOND;
+	// a length in sectors, without the 2 second lead-in of absolute positions
+	pub fn from_sectors(sectors: usize) -> Self {
+		let minutes = sectors / (SECTORS_PER_SECOND * SECONDS_PER_MINUTE);
+		let seconds = (sectors / SECTORS_PER_SECOND) % SECONDS_PER_MINUTE;
+		let sectors = sectors % SECTORS_PER_SECOND;
 
 		Self::new(minutes as u8, seconds as u8, sectors as u8)
 	}
@@ -88,16 +103,252 @@ impl Track {
 }
 
 pub struct Disc {
-	pub tracks: Vec<Track>
+	pub tracks: Vec<Track>,
+
+	// subchannel Q by lba from .sub/.sbi/.lsd files, everything else is generated from the
+	// position. control/adr, track, index, relative msf, zero, absolute msf, crc
+	subchannel_q: HashMap<usize, [u8; 12]>,
+
+	// whole sectors by lba changed by patches, read instead of the image which is never modified
+	patched_sectors: HashMap<usize, Vec<u8>>,
+}
+
+// a file or directory in the ISO9660 filesystem
+#[derive(Debug, Clone)]
+pub struct IsoFile {
+	// full path with directories separated by '/' and the ';1' version stripped
+	pub path: String,
+	pub lba: usize,
+	pub len: usize,
+	pub is_dir: bool,
 }
 
 impl Disc {
 	pub fn new() -> Self {
 		Self {
 			tracks: Vec::new(),
+
+			subchannel_q: HashMap::new(),
+
+			patched_sectors: HashMap::new(),
 		}
 	}
 
+	// patches next to the cue with the same name, e.g. a translation as game.ppf
+	pub fn load_patch_files(&mut self, cue_path: &Path) {
+		for ext in ["ppf", "ips", "xdelta", "vcdiff"] {
+			let path = cue_path.with_extension(ext);
+
+			if path.exists() {
+				if let Err(err) = self.apply_patch_file(&path) {
+					error!("unable to apply patch {}, the disc is left unpatched by it: {err}", path.display());
+				}
+			}
+		}
+	}
+
+	pub fn apply_patch_file(&mut self, path: &Path) -> io::Result<()> {
+		self.apply_patch(&fs::read(path)?)?;
+
+		info!("applied patch {}, {} sectors patched", path.display(), self.patched_sectors.len());
+
+		Ok(())
+	}
+
+	// PPF 1/2/3, IPS or xdelta (VCDIFF) against the tracks as one image, found from the magic
+	pub fn apply_patch(&mut self, data: &[u8]) -> io::Result<()> {
+		patch::apply(self, data)
+	}
+
+	pub fn clear_patches(&mut self) {
+		self.patched_sectors.clear();
+	}
+
+	pub fn patched_sectors(&self) -> usize {
+		self.patched_sectors.len()
+	}
+
+	// length of every track together, what patches are made against
+	pub fn image_len(&self) -> usize {
+		self.end_lba() * BYTES_PER_SECTOR
+	}
+
+	// sector with the patches so far applied
+	pub(crate) fn patched_sector(&self, lba: usize) -> &[u8] {
+		match self.patched_sectors.get(&lba) {
+			Some(sector) => sector,
+			None => self.image_sector(lba),
+		}
+	}
+
+	// sectors changed by a patch that applied cleanly, they already include earlier patches
+	pub(crate) fn merge_patched_sectors(&mut self, sectors: HashMap<usize, Vec<u8>>) {
+		self.patched_sectors.extend(sectors);
+	}
+
+	// unpatched sector from the track data
+	pub(crate) fn image_sector(&self, lba: usize) -> &[u8] {
+		let sector_addr = lba * BYTES_PER_SECTOR;
+		let (track_num, start_addr) = self.get_track_number(sector_addr);
+		let track_addr = sector_addr - start_addr;
+
+		&self.tracks[track_num].data[track_addr..track_addr + BYTES_PER_SECTOR]
+	}
+
+	// subchannel data next to the cue with the same name. a .sub has all of it, .sbi and .lsd
+	// only the sectors LibCrypt modified
+	pub fn load_subchannel_files(&mut self, cue_path: &Path) {
+		let sub = fs::read(cue_path.with_extension("sub")).ok();
+		let sbi = fs::read(cue_path.with_extension("sbi")).ok();
+		let lsd = fs::read(cue_path.with_extension("lsd")).ok();
+
+		if let Some(sub) = sub {
+			self.load_sub(&sub);
+			info!("loaded subchannel data for {} sectors", sub.len() / SUB_BYTES_PER_SECTOR);
+		}
+
+		if let Some(sbi) = sbi {
+			match self.load_sbi(&sbi) {
+				Some(patched) => info!("loaded {patched} LibCrypt sectors from sbi"),
+				None => warn!("invalid sbi file"),
+			}
+		}
+
+		if let Some(lsd) = lsd {
+			let patched = self.load_lsd(&lsd);
+			info!("loaded {patched} LibCrypt sectors from lsd");
+		}
+	}
+
+	// starts at lba 0, same as the first track
+	pub fn load_sub(&mut self, data: &[u8]) {
+		for (lba, sector) in data.chunks_exact(SUB_BYTES_PER_SECTOR).enumerate() {
+			self.subchannel_q.insert(lba, sector[12..24].try_into().unwrap());
+		}
+	}
+
+	// entries are an absolute bcd msf and a type: 1 is the whole Q without the crc, 2 only the
+	// relative msf and 3 only the absolute msf. the crc isn't stored, the modified sectors are
+	// given a bad one like on the disc
+	pub fn load_sbi(&mut self, data: &[u8]) -> Option<usize> {
+		if !data.starts_with(SBI_MAGIC) {
+			return None;
+		}
+
+		let mut offset = SBI_MAGIC.len();
+		let mut patched = 0;
+
+		while offset + 4 <= data.len() {
+			let lba = CdIndex::from_bcd(data[offset], data[offset + 1], data[offset + 2]).to_lba();
+			let entry_type = data[offset + 3];
+			offset += 4;
+
+			let (range, len) = match entry_type {
+				1 => (0..10, 10),
+				2 => (3..6, 3),
+				3 => (7..10, 3),
+				_ => return None,
+			};
+
+			let mut q = self.subchannel_q(lba);
+			q[range].copy_from_slice(data.get(offset..offset + len)?);
+			offset += len;
+
+			let crc = !subchannel_q_crc(&q);
+			q[10..12].copy_from_slice(&crc.to_be_bytes());
+
+			self.subchannel_q.insert(lba, q);
+			patched += 1;
+		}
+
+		Some(patched)
+	}
+
+	// entries are an absolute bcd msf and the whole Q with its crc
+	pub fn load_lsd(&mut self, data: &[u8]) -> usize {
+		for entry in data.chunks_exact(LSD_ENTRY_LEN) {
+			let lba = CdIndex::from_bcd(entry[0], entry[1], entry[2]).to_lba();
+			self.subchannel_q.insert(lba, entry[3..].try_into().unwrap());
+		}
+
+		data.len() / LSD_ENTRY_LEN
+	}
+
+	// Q of a sector, from the subchannel files or generated for index 1 of its track
+	pub fn subchannel_q(&self, lba: usize) -> [u8; 12] {
+		if let Some(q) = self.subchannel_q.get(&lba) {
+			return *q;
+		}
+
+		let (relative_time, track) = self.get_track_offset(CdIndex::from_lba(lba));
+		let absolute_time = CdIndex::from_lba(lba);
+
+		let control = match self.track_is_audio(track) {
+			true => 0x01,
+			false => 0x41,
+		};
+
+		let mut q = [
+			control,
+			binary_to_bcd(track as u8),
+			1,
+			binary_to_bcd(relative_time.minutes),
+			binary_to_bcd(relative_time.seconds),
+			binary_to_bcd(relative_time.sectors),
+			0,
+			binary_to_bcd(absolute_time.minutes),
+			binary_to_bcd(absolute_time.seconds),
+			binary_to_bcd(absolute_time.sectors),
+			0,
+			0,
+		];
+
+		let crc = subchannel_q_crc(&q);
+		q[10..12].copy_from_slice(&crc.to_be_bytes());
+
+		q
+	}
+
+	// what the drive reports at a sector, Q with a bad crc is ignored and the last good one stays
+	pub fn last_valid_subchannel_q(&self, lba: usize) -> [u8; 12] {
+		(lba.saturating_sub(MAX_BAD_Q_SECTORS)..=lba).rev()
+			.map(|lba| self.subchannel_q(lba))
+			.find(|q| u16::from_be_bytes([q[10], q[11]]) == subchannel_q_crc(q))
+			.unwrap_or_else(|| self.subchannel_q(lba))
+	}
+
+	// Q of the lead-in toc entry for a track (or A0/A1/A2 for first track, last track and
+	// lead-out), without the crc
+	pub fn toc_q(&self, point: u8) -> Option<[u8; 10]> {
+		let bcd_msf = |index: CdIndex| [binary_to_bcd(index.minutes), binary_to_bcd(index.seconds), binary_to_bcd(index.sectors)];
+
+		let (control, [pmin, psec, pframe]) = match point {
+			// first track and the disc type, 0x20 for XA
+			0xA0 => (0x41, [0x01, 0x20, 0]),
+			0xA1 => (0x41, [binary_to_bcd(self.tracks.len() as u8), 0, 0]),
+			0xA2 => (0x41, bcd_msf(CdIndex::from_lba(self.end_lba()))),
+			_ => {
+				let track = usize::from(bcd_to_binary(point));
+
+				if track == 0 || track > self.tracks.len() {
+					return None;
+				}
+
+				let control = if self.track_is_audio(track) { 0x01 } else { 0x41 };
+
+				(control, bcd_msf(self.get_track_start(track)))
+			},
+		};
+
+		Some([control, 0, point, 0, 0, 0, 0, pmin, psec, pframe])
+	}
+
+	fn track_is_audio(&self, track_num: usize) -> bool {
+		let track = &self.tracks[track_num - 1];
+
+		track.end_lba > track.start_lba && self.read_sector(CdIndex::from_lba(track.start_lba)).is_audio()
+	}
+
 	pub fn add_tracks(&mut self, tracks: Vec<Vec<u8>>) {
 		let mut total_sectors = 0;
 		let mut track_num = 0;
@@ -128,6 +379,10 @@ impl Disc {
 
 		trace!("addr: {sector_addr} lba: {} msf: {} {sector_addr}..{sector_addr} + {BYTES_PER_SECTOR}", index.to_lba(), index);
 
+		if let Some(sector) = self.patched_sectors.get(&index.to_lba()) {
+			return Sector::new(sector.clone());
+		}
+
 		//if self.data.len() < sector_addr + BYTES_PER_SECTOR {
 		let (track_num, start_addr) = self.get_track_number(sector_addr);
 		let track_addr = sector_addr - start_addr;
@@ -154,10 +409,15 @@ impl Disc {
 		CdIndex::from_lba(self.tracks[track_num - 1].start_lba)
 	}
 
+	// time relative to the start of the track and the track number
 	pub fn get_track_offset(&self, abs_index: CdIndex) -> (CdIndex, usize) {
 		let track = &self.tracks[self.get_track_from_index(abs_index)];
 
-		(CdIndex::from_lba(abs_index.to_lba() - track.start_lba), track.number)
+		(CdIndex::from_sectors(abs_index.to_lba().saturating_sub(track.start_lba)), track.number)
+	}
+
+	pub fn end_lba(&self) -> usize {
+		self.tracks.last().map_or(0, |track| track.end_lba)
 	}
 
 	fn get_track_from_index(&self, index: CdIndex) -> usize {
@@ -175,6 +435,140 @@ impl Disc {
 	pub fn get_disc_end(&self) -> CdIndex {
 		CdIndex::from_lba(self.tracks.last().unwrap().end_lba + 150)
 	}
+
+	// boot executable from SYSTEM.CNF (e.g. SLUS_012.34), identifies the game
+	pub fn get_game_id(&self) -> Option<String> {
+		let system_cnf = self.read_iso_file("SYSTEM.CNF")?;
+		let text = String::from_utf8_lossy(&system_cnf);
+
+		let boot_line = text.lines().find(|line| line.trim_start().to_uppercase().starts_with("BOOT"))?;
+		let boot_path = boot_line.split('=').nth(1)?.trim();
+
+		// cdrom:\SLUS_012.34;1
+		let file_name = boot_path.rsplit(['\\', ':']).next()?;
+		let game_id = file_name.split(';').next()?.trim();
+
+		(!game_id.is_empty()).then(|| game_id.to_uppercase())
+	}
+
+	// reads a file from the root directory of the ISO9660 filesystem on the first track
+	pub fn read_iso_file(&self, name: &str) -> Option<Vec<u8>> {
+		let (root_lba, root_len) = self.iso_root()?;
+
+		let file = self.read_iso_dir(root_lba, root_len)?
+			.into_iter()
+			.find(|file| !file.is_dir && file.path.eq_ignore_ascii_case(name))?;
+
+		self.read_iso_extent(file.lba, file.len)
+	}
+
+	// every file and directory in the ISO9660 filesystem on the first track
+	pub fn iso_files(&self) -> Vec<IsoFile> {
+		let mut files = Vec::new();
+
+		let Some((root_lba, root_len)) = self.iso_root() else {
+			return files;
+		};
+
+		let mut dirs = vec![(String::new(), root_lba, root_len)];
+		// broken images can have directories that contain themselves
+		let mut visited = vec![root_lba];
+
+		while let Some((dir_path, lba, len)) = dirs.pop() {
+			for mut file in self.read_iso_dir(lba, len).unwrap_or_default() {
+				file.path = format!("{dir_path}{}", file.path);
+
+				if file.is_dir && !visited.contains(&file.lba) {
+					visited.push(file.lba);
+					dirs.push((format!("{}/", file.path), file.lba, file.len));
+				}
+
+				files.push(file);
+			}
+		}
+
+		files
+	}
+
+	// data of a file from its directory record
+	pub fn read_iso_extent(&self, lba: usize, len: usize) -> Option<Vec<u8>> {
+		let mut data = Vec::with_capacity(len);
+
+		for file_sector in 0..len.div_ceil(0x800) {
+			data.extend_from_slice(&self.read_data_sector(lba + file_sector)?);
+		}
+
+		data.truncate(len);
+		Some(data)
+	}
+
+	// whole sectors of a file, for STR/XA files where the form 2 sectors don't fit in 0x800 bytes.
+	// the directory length still counts 0x800 per sector
+	pub fn read_iso_sectors(&self, lba: usize, len: usize) -> Option<Vec<Sector>> {
+		let end_lba = self.tracks.first()?.end_lba;
+
+		(lba..lba + len.div_ceil(0x800))
+			.map(|lba| (lba < end_lba).then(|| self.read_sector(CdIndex::from_lba(lba))))
+			.collect()
+	}
+
+	// lba and length of the root directory from the primary volume descriptor
+	fn iso_root(&self) -> Option<(usize, usize)> {
+		let pvd = self.read_data_sector(16)?;
+
+		if &pvd[1..6] != b"CD001" {
+			return None;
+		}
+
+		let root_lba = u32::from_le_bytes(pvd[156 + 2..156 + 6].try_into().unwrap()) as usize;
+		let root_len = u32::from_le_bytes(pvd[156 + 10..156 + 14].try_into().unwrap()) as usize;
+
+		Some((root_lba, root_len))
+	}
+
+	// entries of one directory, the paths are just the names
+	fn read_iso_dir(&self, lba: usize, len: usize) -> Option<Vec<IsoFile>> {
+		let mut files = Vec::new();
+
+		for dir_sector in 0..len.div_ceil(0x800) {
+			let sector = self.read_data_sector(lba + dir_sector)?;
+			let mut offset = 0;
+
+			while offset < 0x800 && sector[offset] != 0 {
+				let record = &sector[offset..(offset + sector[offset] as usize).min(0x800)];
+				offset += record.len();
+
+				if record.len() < 33 || record.len() < 33 + record[32] as usize {
+					break;
+				}
+
+				let name = &record[33..33 + record[32] as usize];
+
+				// '.' and '..' are stored as 0 and 1
+				if name == [0] || name == [1] {
+					continue;
+				}
+
+				files.push(IsoFile {
+					path: String::from_utf8_lossy(name).split(';').next().unwrap().to_string(),
+					lba: u32::from_le_bytes(record[2..6].try_into().unwrap()) as usize,
+					len: u32::from_le_bytes(record[10..14].try_into().unwrap()) as usize,
+					is_dir: record[25] & 2 != 0,
+				});
+			}
+		}
+
+		Some(files)
+	}
+
+	// user data of a mode 1 / mode 2 form 1 sector, None past the end of the disc
+	fn read_data_sector(&self, lba: usize) -> Option<Vec<u8>> {
+		if lba >= self.tracks.first()?.end_lba {
+			return None;
+		}
+
+		Some(self.read_sector(CdIndex::from_lba(lba)).data_only().to_vec())
+	}
 }
 
 pub struct Sector {
@@ -204,26 +598,39 @@ impl Sector {
 		&self.data[0x18..0x18 + 0x914]
 	}
 
+	// no sync pattern, the whole sector is 16 bit stereo samples
+	pub fn is_audio(&self) -> bool {
+		self.data[..0xC] != SECTOR_SYNC
+	}
+
+	// file, channel, submode, coding info
+	pub fn subheader(&self) -> &[u8] {
+		&self.data[0x10..0x14]
+	}
+
+	// sectors that go to the adpcm decoder instead of the data fifo, whether they're played
+	// depends on the filter
 	pub fn is_xa_adpcm(&self, xa_info: &XaAdpcmInfo) -> bool {
 		let mode = self.data[0xF];
-		let file = self.data[0x10];
-		let channel = self.data[0x11];
 		let submode = self.data[0x12];
 
-		if mode == 2 && xa_info.xa_enabled {
-			if !xa_info.xa_filter || (xa_info.xa_filter && xa_info.xa_file == file && xa_info.xa_channel == channel) {
-				// submode must be Audio+Realtime
-				if submode & 0x44 == 0x44 {
-					return true;
-				}
-			}
-		}
-		
-
-		false
+		// submode must be Audio+Realtime
+		mode == 2 && xa_info.xa_enabled && submode & 0x44 == 0x44
 	}
 }
 
+// crc-16/ccitt of the first 10 bytes, stored inverted and big endian in the last 2
+fn subchannel_q_crc(q: &[u8]) -> u16 {
+	let crc = q[..10].iter().fold(0u16, |crc, &byte| {
+		(0..8).fold(crc ^ (u16::from(byte) << 8), |crc, _| match crc & 0x8000 {
+			0 => crc << 1,
+			_ => (crc << 1) ^ 0x1021,
+		})
+	});
+
+	!crc
+}
+
 pub fn bcd_to_binary(value: u8) -> u8 {
     10 * (value >> 4) + (value & 0xF)
 }
@@ -6,10 +6,12 @@ use log::*;
 
 use crate::{cdrom::disc::Sector, interrupts::{InterruptFlag, Interrupts}, scheduler::{EventType, Scheduler, SchedulerEvent}, spu::Spu};
 use self::commands::*;
+use self::xa_apdcm::XaAdpcmState;
 
 mod commands;
-mod xa_apdcm;
+pub mod xa_apdcm;
 pub mod disc;
+mod patch;
 
 struct CdromInterrupts {
 	int_flags: u8,
@@ -105,6 +107,13 @@ pub struct XaAdpcmInfo {
 	xa_channel: u8,
 }
 
+impl XaAdpcmInfo {
+	// with the filter on only the file/channel picked by Setfilter is played
+	pub fn filter_matches(&self, file: u8, channel: u8) -> bool {
+		!self.xa_filter || (self.xa_file == file && self.xa_channel == channel)
+	}
+}
+
 pub struct DataFifo {
 	buffer: [u8; 0x930],
 	index: usize,
@@ -189,6 +198,8 @@ pub struct CmdResponse {
 	
 	second_response: Option<(Box<CmdResponse>, u64)>,
 	on_complete: Option<fn(&mut Cdrom) -> Option<(CmdResponse, u64)>>,
+	// set on the first response of a command, to the command it answers
+	first_of_cmd: Option<u32>,
 }
 
 impl CmdResponse {
@@ -199,6 +210,7 @@ impl CmdResponse {
 
 			second_response: None,
 			on_complete: None,
+			first_of_cmd: None,
 		}
 	}
 
@@ -209,6 +221,7 @@ impl CmdResponse {
 
 			second_response: None,
 			on_complete: None,
+			first_of_cmd: None,
 		}
 	}
 }
@@ -238,9 +251,36 @@ pub struct Cdrom {
 	ignore_cur_sector_size: bool,
 	motor_on: bool,
 
+	// scheduler time of the command or response being handled
+	now: u64,
+	// the spindle is spinning up or changing speed until then, reads and seeks wait for it
+	spindle_ready_at: u64,
+	// laser moving to the target of a seek, shown in the stat instead of the drive state
+	seeking: bool,
+	// a command has been written and its first response isn't out yet
+	cmd_busy: bool,
+	// commands written so far, tells the first response of the latest one apart
+	cmd_count: u32,
+	// constant seek and spin times instead of modeling the drive mechanics
+	fast_cd: bool,
+
+	// Setmode bits 0-2
+	cdda_enabled: bool,
+	auto_pause: bool,
+	report_enabled: bool,
+
+	// track being played, autopause stops when it changes
+	play_track: usize,
+	// reports go out when the tens digit of the sector changes
+	last_report_frame: Option<u8>,
+	// the peak level in reports alternates between left and right
+	report_right_peak: bool,
+
 	xa_adpcm_info: XaAdpcmInfo,
+	xa_adpcm: XaAdpcmState,
 
 	audio_muted: bool,
+	adpcm_muted: bool,
 	pending_atv: [[u8; 2]; 2],
 	atv: [[u8; 2]; 2],
 }
@@ -272,9 +312,26 @@ impl Cdrom {
 			ignore_cur_sector_size: false,
 			motor_on: true,
 
+			now: 0,
+			spindle_ready_at: 0,
+			seeking: false,
+			cmd_busy: false,
+			cmd_count: 0,
+			fast_cd: false,
+
+			cdda_enabled: false,
+			auto_pause: false,
+			report_enabled: false,
+
+			play_track: 0,
+			last_report_frame: None,
+			report_right_peak: false,
+
 			xa_adpcm_info: XaAdpcmInfo::default(),
+			xa_adpcm: XaAdpcmState::new(),
 
 			audio_muted: false,
+			adpcm_muted: false,
 			pending_atv: [[0x80; 2]; 2],
 			atv: [[0x80; 2]; 2],
 		}
@@ -284,6 +341,14 @@ impl Cdrom {
 		self.disc = Some(disc);
 	}
 
+	pub fn set_fast_cd(&mut self, enabled: bool) {
+		self.fast_cd = enabled;
+	}
+
+	pub fn is_fast_cd(&self) -> bool {
+		self.fast_cd
+	}
+
 	pub fn read8(&mut self, addr: u32) -> u8 {
 		let reg = addr & 0xF;
 
@@ -336,7 +401,14 @@ impl Cdrom {
 			1 => match reg {
 				0 => self.write_status(write),
 				2 => self.int_regs.write_mask(write),
-				3 => self.int_regs.ack_interrupt(write, &mut self.params_fifo),
+				3 => {
+					self.int_regs.ack_interrupt(write, &mut self.params_fifo);
+
+					// CHPRST, the pending command is dropped
+					if (write >> 7) & 1 != 0 {
+						self.abort_cmd();
+					}
+				},
 				_ => todo!("CDROM write [0x{addr:X}][{}] 0x{write:X}", self.bank),
 			},
 			2 => match reg {
@@ -351,6 +423,8 @@ impl Cdrom {
 				2 => self.pending_atv[1][1] = write,
 				// ADPCTL
 				3 => {
+					self.adpcm_muted = write & 1 != 0;
+
 					if (write >> 5) & 1 != 0 {
 						self.atv = self.pending_atv;
 					}
@@ -368,7 +442,7 @@ impl Cdrom {
 			| (u8::from(!(self.params_fifo.len() >= 16)) << 4)
 			| (u8::from(!self.result_fifo.is_empty()) << 5)
 			| (u8::from(!self.data_fifo.is_empty()) << 6)
-			| (u8::from(self.int_regs.int_flags != 0) << 7);
+			| (u8::from(self.cmd_busy) << 7);
 		
 		trace!("read status: 0b{result:b}");
 
@@ -386,7 +460,11 @@ impl Cdrom {
 	fn exec_cmd(&mut self, cmd: u8, scheduler: &mut Scheduler) {
 		//info!("exec cmd 0x{cmd:X}");
 
-		let (response, delay) = match cmd {
+		self.now = scheduler.cpu_cycle_counter;
+		self.cmd_busy = true;
+		self.cmd_count = self.cmd_count.wrapping_add(1);
+
+		let (mut response, delay) = match cmd {
 			// Nop
 			0x1 => self.nop(),
 			// Setloc
@@ -429,27 +507,48 @@ impl Cdrom {
 			0x1A => self.get_id(),
 			// ReadS (currently the same as ReadN)
 			0x1B => self.read_n(),
+			// GetQ
+			0x1D => self.get_q(),
 
 			_ => todo!("cmd 0x{cmd:X}")
 		};
 
 		self.params_fifo.clear();
 
+		response.first_of_cmd = Some(self.cmd_count);
 		scheduler.schedule_event(SchedulerEvent::new(EventType::CdromCmd(response)), delay);
 	}
 
 	// different from STATUS/ADDRESS register
 	fn get_stat(&self) -> u8 {
+		// a read or play starts out seeking
+		let state = match self.seeking {
+			true => DriveState::Seek,
+			false => self.drive_state,
+		};
+
 		let result = (u8::from(self.motor_on) << 1) // motor state
 			| (u8::from(self.disc.is_none()) << 4)		// shell open
-			| (self.drive_state as u8);					// reading data sectors
+			| (state as u8);							// reading, seeking or playing
 		
 		trace!("getstat: 0b{result:b} (motor_on: {}, shell_open: {}, drive state: {:?})", self.motor_on, self.disc.is_none(), self.drive_state);
 
 		result
 	}
 
+	// the response of an aborted command doesn't clear the busy bit of a later one
+	fn abort_cmd(&mut self) {
+		self.cmd_busy = false;
+		self.cmd_count = self.cmd_count.wrapping_add(1);
+	}
+
 	pub fn handle_cmd_response(&mut self, response: CmdResponse, scheduler: &mut Scheduler, irq: &mut Interrupts) {
+		self.now = scheduler.cpu_cycle_counter;
+
+		// only the first response of the latest command, INT1/INT5 from reads don't count
+		if response.first_of_cmd == Some(self.cmd_count) {
+			self.cmd_busy = false;
+		}
 
 		// stops an extra INT1 from being raised after a pause
 		// TODO find a better way to fix this
@@ -480,12 +579,19 @@ impl Cdrom {
 	}
 
 	pub fn get_audio_sample(&mut self) -> (i16, i16) {
-		if !self.audio_muted && self.drive_state == DriveState::Play {
+		// xa samples are consumed even when muted so the stream stays in sync
+		let (xa_l, xa_r) = self.xa_adpcm.get_sample();
+
+		if self.audio_muted {
+			(0, 0)
+		} else if self.drive_state == DriveState::Play {
 			// big endian as we are removing fifo entries from the front
 			let sample_l = self.audio_buf.get_sample();
 			let sample_r = self.audio_buf.get_sample();
 			
 			self.apply_volume(sample_l, sample_r)
+		} else if !self.adpcm_muted {
+			self.apply_volume(xa_l, xa_r)
 		} else {
 			(0, 0)
 		}
@@ -0,0 +1,810 @@
+use std::collections::HashMap;
+use std::io;
+use log::*;
+
+use super::disc::{Disc, BYTES_PER_SECTOR};
+
+// patches address the tracks as one raw image, 0x930 bytes per sector
+
+const PPF_MAGIC: &[u8; 3] = b"PPF";
+const IPS_MAGIC: &[u8; 5] = b"PATCH";
+const IPS_EOF: &[u8; 3] = b"EOF";
+const VCDIFF_MAGIC: &[u8; 3] = &[0xD6, 0xC3, 0xC4];
+
+// description is 50 bytes after the magic and the encoding method
+const PPF_DESCRIPTION_END: usize = 56;
+// 1024 bytes of the image to check it's the right one
+const PPF_BLOCK_CHECK_LEN: usize = 1024;
+const PPF_BLOCK_CHECK_BIN: usize = 0x9320;
+const PPF_BLOCK_CHECK_GI: usize = 0x80A0;
+const PPF_DIZ_BEGIN_LEN: usize = 18;
+const PPF_DIZ_END_LEN: usize = 16;
+
+// vcdiff header and window indicators, xdelta adds a checksum of each window
+const VCD_DECOMPRESS: u8 = 1 << 0;
+const VCD_CODETABLE: u8 = 1 << 1;
+const VCD_APPHEADER: u8 = 1 << 2;
+const VCD_SOURCE: u8 = 1 << 0;
+const VCD_TARGET: u8 = 1 << 1;
+const VCD_ADLER32: u8 = 1 << 2;
+
+const VCD_NEAR_SIZE: usize = 4;
+const VCD_SAME_SIZE: usize = 3;
+
+#[derive(Clone, Copy, PartialEq)]
+enum VcdInstruction {
+	Noop,
+	Add,
+	Run,
+	Copy,
+}
+
+// one code of the instruction table, up to two instructions with a size (0 means it follows
+// in the instruction section) and the address mode of copies
+#[derive(Clone, Copy)]
+struct VcdCode {
+	instructions: [(VcdInstruction, usize, usize); 2],
+}
+
+// what a patch changes, on top of the disc and its earlier patches. only merged into the disc
+// once the whole patch is decoded, so a corrupt or truncated one leaves the disc as it was
+struct PatchOverlay<'a> {
+	disc: &'a Disc,
+	sectors: HashMap<usize, Vec<u8>>,
+}
+
+impl<'a> PatchOverlay<'a> {
+	fn new(disc: &'a Disc) -> Self {
+		Self {
+			disc,
+			sectors: HashMap::new(),
+		}
+	}
+
+	fn image_len(&self) -> usize {
+		self.disc.image_len()
+	}
+
+	fn sector(&self, lba: usize) -> &[u8] {
+		match self.sectors.get(&lba) {
+			Some(sector) => sector,
+			None => self.disc.patched_sector(lba),
+		}
+	}
+
+	// bytes of the image, from the original tracks or with the patches so far applied
+	fn read_image(&self, offset: usize, len: usize, patched: bool) -> io::Result<Vec<u8>> {
+		if offset + len > self.image_len() {
+			return Err(invalid_data("read past the end of the image"));
+		}
+
+		let mut data = Vec::with_capacity(len);
+		let mut pos = offset;
+
+		while pos < offset + len {
+			let lba = pos / BYTES_PER_SECTOR;
+			let sector_offset = pos % BYTES_PER_SECTOR;
+			let chunk_len = (BYTES_PER_SECTOR - sector_offset).min(offset + len - pos);
+
+			let sector = match patched {
+				true => self.sector(lba),
+				false => self.disc.image_sector(lba),
+			};
+
+			data.extend_from_slice(&sector[sector_offset..sector_offset + chunk_len]);
+			pos += chunk_len;
+		}
+
+		Ok(data)
+	}
+
+	// only sectors that end up different get copied into the overlay
+	fn write_image(&mut self, offset: usize, data: &[u8]) -> io::Result<()> {
+		if offset + data.len() > self.image_len() {
+			return Err(invalid_data("patch writes past the end of the image"));
+		}
+
+		let mut pos = offset;
+		let mut remaining = data;
+
+		while !remaining.is_empty() {
+			let lba = pos / BYTES_PER_SECTOR;
+			let sector_offset = pos % BYTES_PER_SECTOR;
+			let (bytes, rest) = remaining.split_at((BYTES_PER_SECTOR - sector_offset).min(remaining.len()));
+			let range = sector_offset..sector_offset + bytes.len();
+
+			if &self.sector(lba)[range.clone()] != bytes {
+				if !self.sectors.contains_key(&lba) {
+					self.sectors.insert(lba, self.disc.patched_sector(lba).to_vec());
+				}
+
+				self.sectors.get_mut(&lba).unwrap()[range].copy_from_slice(bytes);
+			}
+
+			pos += bytes.len();
+			remaining = rest;
+		}
+
+		Ok(())
+	}
+}
+
+pub(crate) fn apply(disc: &mut Disc, data: &[u8]) -> io::Result<()> {
+	let mut overlay = PatchOverlay::new(disc);
+
+	if data.starts_with(PPF_MAGIC) {
+		apply_ppf(&mut overlay, data)?;
+	} else if data.starts_with(IPS_MAGIC) {
+		apply_ips(&mut overlay, data)?;
+	} else if data.starts_with(VCDIFF_MAGIC) {
+		apply_vcdiff(&mut overlay, data)?;
+	} else {
+		return Err(invalid_data("unknown patch format"));
+	}
+
+	let sectors = overlay.sectors;
+	disc.merge_patched_sectors(sectors);
+
+	Ok(())
+}
+
+fn apply_ppf(image: &mut PatchOverlay, data: &[u8]) -> io::Result<()> {
+	let version = *data.get(3).ok_or_else(|| invalid_data("truncated ppf header"))?;
+	let description = data.get(6..PPF_DESCRIPTION_END).ok_or_else(|| invalid_data("truncated ppf header"))?;
+
+	debug!("PPF{} patch: {}", version as char, String::from_utf8_lossy(description).trim_end());
+
+	// start of the patch records, where they end, whether there's a block check and undo data
+	let (start, block_check, undo, end) = match version {
+		b'1' => (PPF_DESCRIPTION_END, None, false, data.len()),
+		b'2' => {
+			let diz_len = match data.len() >= 8 && &data[data.len() - 8..data.len() - 4] == b".DIZ" {
+				true => read_u32_le(data, data.len() - 4)? as usize + PPF_DIZ_BEGIN_LEN + PPF_DIZ_END_LEN + 4,
+				false => 0,
+			};
+
+			let image_len = read_u32_le(data, PPF_DESCRIPTION_END)? as usize;
+
+			if image_len != image.image_len() {
+				warn!("ppf is for an image of {image_len} bytes, this one is {}", image.image_len());
+			}
+
+			(PPF_DESCRIPTION_END + 4 + PPF_BLOCK_CHECK_LEN, Some(PPF_BLOCK_CHECK_BIN), false, data.len().saturating_sub(diz_len))
+		},
+		b'3' => {
+			let header = data.get(PPF_DESCRIPTION_END..PPF_DESCRIPTION_END + 4).ok_or_else(|| invalid_data("truncated ppf header"))?;
+
+			let diz_len = match data.len() >= 6 && &data[data.len() - 6..data.len() - 2] == b".DIZ" {
+				true => u16::from_le_bytes([data[data.len() - 2], data[data.len() - 1]]) as usize + PPF_DIZ_BEGIN_LEN + PPF_DIZ_END_LEN + 2,
+				false => 0,
+			};
+
+			let block_check_offset = match header[0] {
+				0 => PPF_BLOCK_CHECK_BIN,
+				_ => PPF_BLOCK_CHECK_GI,
+			};
+
+			let (start, block_check) = match header[1] {
+				0 => (PPF_DESCRIPTION_END + 4, None),
+				_ => (PPF_DESCRIPTION_END + 4 + PPF_BLOCK_CHECK_LEN, Some(block_check_offset)),
+			};
+
+			(start, block_check, header[2] != 0, data.len().saturating_sub(diz_len))
+		},
+		_ => return Err(invalid_data("unknown ppf version")),
+	};
+
+	if let Some(offset) = block_check {
+		let expected = data.get(start - PPF_BLOCK_CHECK_LEN..start).ok_or_else(|| invalid_data("truncated ppf block check"))?;
+
+		if image.read_image(offset, PPF_BLOCK_CHECK_LEN, false).ok().as_deref() != Some(expected) {
+			warn!("ppf block check doesn't match, the patch is probably for another version of the game");
+		}
+	}
+
+	// ppf 3 has 64 bit offsets
+	let offset_len = match version {
+		b'3' => 8,
+		_ => 4,
+	};
+
+	let records = data.get(start..end).ok_or_else(|| invalid_data("truncated ppf"))?;
+	let mut pos = 0;
+
+	while pos < records.len() {
+		let offset = match offset_len {
+			8 => read_u64_le(records, pos)? as usize,
+			_ => read_u32_le(records, pos)? as usize,
+		};
+
+		let len = *records.get(pos + offset_len).ok_or_else(|| invalid_data("truncated ppf record"))? as usize;
+		pos += offset_len + 1;
+
+		let bytes = records.get(pos..pos + len).ok_or_else(|| invalid_data("truncated ppf record"))?;
+		image.write_image(offset, bytes)?;
+
+		// the original bytes follow for undoing the patch
+		pos += match undo {
+			true => len * 2,
+			false => len,
+		};
+	}
+
+	Ok(())
+}
+
+fn apply_ips(image: &mut PatchOverlay, data: &[u8]) -> io::Result<()> {
+	let mut pos = IPS_MAGIC.len();
+
+	loop {
+		// a truncate length can follow, the image is never shortened
+		if data.get(pos..pos + 3) == Some(IPS_EOF) {
+			break;
+		}
+
+		let record = data.get(pos..pos + 5).ok_or_else(|| invalid_data("truncated ips record"))?;
+
+		let offset = u32::from_be_bytes([0, record[0], record[1], record[2]]) as usize;
+		let len = u16::from_be_bytes([record[3], record[4]]) as usize;
+		pos += 5;
+
+		// a length of 0 is a run of one byte
+		if len == 0 {
+			let run = data.get(pos..pos + 3).ok_or_else(|| invalid_data("truncated ips run"))?;
+			let run_len = u16::from_be_bytes([run[0], run[1]]) as usize;
+
+			image.write_image(offset, &vec![run[2]; run_len])?;
+			pos += 3;
+		} else {
+			let bytes = data.get(pos..pos + len).ok_or_else(|| invalid_data("truncated ips record"))?;
+
+			image.write_image(offset, bytes)?;
+			pos += len;
+		}
+	}
+
+	Ok(())
+}
+
+// only what xdelta writes without secondary compression (xdelta3 -S none) or a custom
+// instruction table is supported
+fn apply_vcdiff(image: &mut PatchOverlay, data: &[u8]) -> io::Result<()> {
+	let mut reader = VcdReader::new(data);
+	reader.bytes(4)?;
+
+	let indicator = reader.byte()?;
+
+	if indicator & (VCD_DECOMPRESS | VCD_CODETABLE) != 0 {
+		return Err(invalid_data("compressed or custom table vcdiff patches aren't supported, make them with xdelta3 -S none"));
+	}
+
+	if indicator & VCD_APPHEADER != 0 {
+		let len = reader.varint()?;
+		reader.bytes(len)?;
+	}
+
+	let code_table = vcd_code_table();
+	let mut target_offset = 0;
+
+	while !reader.is_empty() {
+		let window_indicator = reader.byte()?;
+
+		// the source segment is from the original image, or from output that was already decoded
+		let segment = window_indicator & (VCD_SOURCE | VCD_TARGET);
+
+		let source = match segment {
+			0 => Vec::new(),
+			VCD_SOURCE | VCD_TARGET if segment.count_ones() == 1 => {
+				let len = reader.varint()?;
+				let pos = reader.varint()?;
+
+				image.read_image(pos, len, segment == VCD_TARGET)?
+			},
+			_ => return Err(invalid_data("vcdiff window has source and target segments")),
+		};
+
+		let _delta_len = reader.varint()?;
+		let target_len = reader.varint()?;
+
+		if reader.byte()? != 0 {
+			return Err(invalid_data("compressed vcdiff sections aren't supported"));
+		}
+
+		let data_len = reader.varint()?;
+		let inst_len = reader.varint()?;
+		let addr_len = reader.varint()?;
+
+		let checksum = match window_indicator & VCD_ADLER32 {
+			0 => None,
+			_ => Some(u32::from_be_bytes(reader.bytes(4)?.try_into().unwrap())),
+		};
+
+		let mut data_section = VcdReader::new(reader.bytes(data_len)?);
+		let mut inst_section = VcdReader::new(reader.bytes(inst_len)?);
+		let mut addr_section = VcdReader::new(reader.bytes(addr_len)?);
+
+		let mut cache = VcdAddressCache::new();
+		let mut target = Vec::with_capacity(target_len);
+
+		while !inst_section.is_empty() {
+			let code = code_table[inst_section.byte()? as usize];
+
+			for (instruction, size, mode) in code.instructions {
+				if instruction == VcdInstruction::Noop {
+					continue;
+				}
+
+				let size = match size {
+					0 => inst_section.varint()?,
+					size => size,
+				};
+
+				match instruction {
+					VcdInstruction::Add => target.extend_from_slice(data_section.bytes(size)?),
+					VcdInstruction::Run => {
+						let value = data_section.byte()?;
+						target.resize(target.len() + size, value);
+					},
+					VcdInstruction::Copy => {
+						let here = source.len() + target.len();
+						let addr = cache.decode(&mut addr_section, here, mode)?;
+
+						// copies from the target can overlap what they write
+						for i in addr..addr + size {
+							let value = match i < source.len() {
+								true => source[i],
+								false => *target.get(i - source.len()).ok_or_else(|| invalid_data("vcdiff copy past the target"))?,
+							};
+
+							target.push(value);
+						}
+					},
+					VcdInstruction::Noop => (),
+				}
+			}
+		}
+
+		if target.len() != target_len {
+			return Err(invalid_data("vcdiff window decoded to the wrong length"));
+		}
+
+		if checksum.is_some_and(|checksum| checksum != adler32(&target)) {
+			return Err(invalid_data("vcdiff window checksum doesn't match"));
+		}
+
+		image.write_image(target_offset, &target)?;
+		target_offset += target_len;
+	}
+
+	if target_offset != image.image_len() {
+		warn!("xdelta output is {target_offset} bytes, the image is {}", image.image_len());
+	}
+
+	Ok(())
+}
+
+struct VcdReader<'a> {
+	data: &'a [u8],
+}
+
+impl<'a> VcdReader<'a> {
+	fn new(data: &'a [u8]) -> Self {
+		Self {
+			data
+		}
+	}
+
+	fn is_empty(&self) -> bool {
+		self.data.is_empty()
+	}
+
+	fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+		if len > self.data.len() {
+			return Err(invalid_data("truncated vcdiff"));
+		}
+
+		let (bytes, rest) = self.data.split_at(len);
+		self.data = rest;
+
+		Ok(bytes)
+	}
+
+	fn byte(&mut self) -> io::Result<u8> {
+		Ok(self.bytes(1)?[0])
+	}
+
+	// 7 bits at a time, most significant first, bit 7 set on all but the last
+	fn varint(&mut self) -> io::Result<usize> {
+		let mut value: usize = 0;
+
+		loop {
+			let byte = self.byte()?;
+
+			value = value.checked_mul(128).ok_or_else(|| invalid_data("vcdiff integer overflow"))? | (byte & 0x7F) as usize;
+
+			if byte & 0x80 == 0 {
+				return Ok(value);
+			}
+		}
+	}
+}
+
+// recent copy addresses, so they can be encoded relative to one or as a single byte
+struct VcdAddressCache {
+	near: [usize; VCD_NEAR_SIZE],
+	next_slot: usize,
+	same: [usize; VCD_SAME_SIZE * 256],
+}
+
+impl VcdAddressCache {
+	fn new() -> Self {
+		Self {
+			near: [0; VCD_NEAR_SIZE],
+			next_slot: 0,
+			same: [0; VCD_SAME_SIZE * 256],
+		}
+	}
+
+	// mode 0 is absolute, 1 back from here, then relative to a near address or a same entry
+	fn decode(&mut self, section: &mut VcdReader, here: usize, mode: usize) -> io::Result<usize> {
+		let addr = match mode {
+			0 => section.varint()?,
+			1 => here.checked_sub(section.varint()?).ok_or_else(|| invalid_data("vcdiff address before the start"))?,
+			_ if mode < 2 + VCD_NEAR_SIZE => self.near[mode - 2] + section.varint()?,
+			_ => self.same[(mode - 2 - VCD_NEAR_SIZE) * 256 + section.byte()? as usize],
+		};
+
+		self.near[self.next_slot] = addr;
+		self.next_slot = (self.next_slot + 1) % VCD_NEAR_SIZE;
+		self.same[addr % (VCD_SAME_SIZE * 256)] = addr;
+
+		Ok(addr)
+	}
+}
+
+// the default instruction table from RFC 3284
+fn vcd_code_table() -> Vec<VcdCode> {
+	use VcdInstruction::*;
+
+	const NOOP: (VcdInstruction, usize, usize) = (Noop, 0, 0);
+
+	let single = |instruction| VcdCode { instructions: [instruction, NOOP] };
+	let pair = |first, second| VcdCode { instructions: [first, second] };
+
+	let mut table = vec![single((Run, 0, 0))];
+
+	for size in 0..=17 {
+		table.push(single((Add, size, 0)));
+	}
+
+	for mode in 0..9 {
+		table.push(single((Copy, 0, mode)));
+
+		for size in 4..=18 {
+			table.push(single((Copy, size, mode)));
+		}
+	}
+
+	for mode in 0..6 {
+		for add_size in 1..=4 {
+			for copy_size in 4..=6 {
+				table.push(pair((Add, add_size, 0), (Copy, copy_size, mode)));
+			}
+		}
+	}
+
+	for mode in 6..9 {
+		for add_size in 1..=4 {
+			table.push(pair((Add, add_size, 0), (Copy, 4, mode)));
+		}
+	}
+
+	for mode in 0..9 {
+		table.push(pair((Copy, 4, mode), (Add, 1, 0)));
+	}
+
+	table
+}
+
+fn adler32(data: &[u8]) -> u32 {
+	let (mut a, mut b) = (1u32, 0u32);
+
+	for chunk in data.chunks(5552) {
+		for &byte in chunk {
+			a += byte as u32;
+			b += a;
+		}
+
+		a %= 65521;
+		b %= 65521;
+	}
+
+	(b << 16) | a
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> io::Result<u32> {
+	data.get(offset..offset + 4)
+		.map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+		.ok_or_else(|| invalid_data("truncated patch"))
+}
+
+fn read_u64_le(data: &[u8], offset: usize) -> io::Result<u64> {
+	data.get(offset..offset + 8)
+		.map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+		.ok_or_else(|| invalid_data("truncated patch"))
+}
+
+pub(crate) fn invalid_data(msg: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::cdrom::disc::CdIndex;
+
+	const SECTOR_LEN: usize = 0x930;
+	const IMAGE_LEN: usize = SECTOR_LEN * 2;
+
+	fn image() -> Vec<u8> {
+		(0..IMAGE_LEN).map(|i| (i % 251) as u8).collect()
+	}
+
+	// one track of 2 sectors
+	fn disc() -> Disc {
+		let mut disc = Disc::new();
+		disc.add_tracks(vec![image()]);
+		disc
+	}
+
+	fn sector(disc: &Disc, lba: usize) -> Vec<u8> {
+		disc.read_sector(CdIndex::from_lba(lba)).audio_sector().to_vec()
+	}
+
+	// the image with the bytes at offset replaced
+	fn patched_image(offset: usize, bytes: &[u8]) -> Vec<u8> {
+		let mut image = image();
+		image[offset..offset + bytes.len()].copy_from_slice(bytes);
+		image
+	}
+
+	fn assert_image(disc: &Disc, expected: &[u8]) {
+		assert_eq!(sector(disc, 0), expected[..SECTOR_LEN]);
+		assert_eq!(sector(disc, 1), expected[SECTOR_LEN..]);
+	}
+
+	fn ppf_header(version: u8) -> Vec<u8> {
+		let mut data = PPF_MAGIC.to_vec();
+		data.extend([version, b'0', version - b'1']);
+		data.extend([b' '; 50]);
+		data
+	}
+
+	fn varint(mut value: usize) -> Vec<u8> {
+		let mut bytes = vec![(value & 0x7F) as u8];
+		value >>= 7;
+
+		while value > 0 {
+			bytes.insert(0, (value & 0x7F) as u8 | 0x80);
+			value >>= 7;
+		}
+
+		bytes
+	}
+
+	#[test]
+	fn ppf1() {
+		let mut patch = ppf_header(b'1');
+		patch.extend((SECTOR_LEN as u32 + 10).to_le_bytes());
+		patch.extend([3, 0xAA, 0xBB, 0xCC]);
+
+		let mut disc = disc();
+		disc.apply_patch(&patch).unwrap();
+
+		assert_eq!(disc.patched_sectors(), 1);
+		assert_image(&disc, &patched_image(SECTOR_LEN + 10, &[0xAA, 0xBB, 0xCC]));
+	}
+
+	#[test]
+	fn ppf2_with_diz() {
+		let mut patch = ppf_header(b'2');
+		patch.extend((IMAGE_LEN as u32).to_le_bytes());
+		// the block check is past the end of this image, it only warns
+		patch.extend([0; PPF_BLOCK_CHECK_LEN]);
+		patch.extend(20u32.to_le_bytes());
+		patch.extend([2, 0x11, 0x22]);
+		patch.extend(b"@BEGIN_FILE_ID.DIZhello@END_FILE_ID.DIZ");
+		patch.extend(5u32.to_le_bytes());
+
+		let mut disc = disc();
+		disc.apply_patch(&patch).unwrap();
+
+		assert_eq!(disc.patched_sectors(), 1);
+		assert_image(&disc, &patched_image(20, &[0x11, 0x22]));
+	}
+
+	#[test]
+	fn ppf3_with_undo_across_sectors() {
+		let mut patch = ppf_header(b'3');
+		// bin image, no block check, undo data
+		patch.extend([0, 0, 1, 0]);
+		patch.extend((SECTOR_LEN as u64 - 2).to_le_bytes());
+		patch.extend([4, 1, 2, 3, 4]);
+		patch.extend([9, 9, 9, 9]);
+		patch.extend(b"@BEGIN_FILE_ID.DIZhello@END_FILE_ID.DIZ");
+		patch.extend(5u16.to_le_bytes());
+
+		let mut disc = disc();
+		disc.apply_patch(&patch).unwrap();
+
+		assert_eq!(disc.patched_sectors(), 2);
+		assert_image(&disc, &patched_image(SECTOR_LEN - 2, &[1, 2, 3, 4]));
+	}
+
+	#[test]
+	fn ppf_past_the_end() {
+		let mut patch = ppf_header(b'1');
+		patch.extend((IMAGE_LEN as u32 - 1).to_le_bytes());
+		patch.extend([2, 1, 1]);
+
+		assert!(disc().apply_patch(&patch).is_err());
+	}
+
+	#[test]
+	fn ips_with_rle() {
+		let mut patch = IPS_MAGIC.to_vec();
+		patch.extend([0x00, 0x00, 0x05, 0x00, 0x02, 0xAA, 0xBB]);
+
+		let offset = SECTOR_LEN + 100;
+		patch.extend([(offset >> 16) as u8, (offset >> 8) as u8, offset as u8, 0x00, 0x00, 0x00, 0x04, 0xCC]);
+		patch.extend(IPS_EOF);
+		// truncate length, ignored
+		patch.extend([0x00, 0x10, 0x00]);
+
+		let mut disc = disc();
+		disc.apply_patch(&patch).unwrap();
+
+		let mut expected = patched_image(5, &[0xAA, 0xBB]);
+		expected[offset..offset + 4].fill(0xCC);
+
+		assert_eq!(disc.patched_sectors(), 2);
+		assert_image(&disc, &expected);
+	}
+
+	#[test]
+	fn ips_truncated() {
+		let mut patch = IPS_MAGIC.to_vec();
+		patch.extend([0x00, 0x00, 0x05, 0x00, 0x04, 0xAA]);
+
+		assert!(disc().apply_patch(&patch).is_err());
+	}
+
+	#[test]
+	fn ips_truncated_after_a_record() {
+		let mut patch = IPS_MAGIC.to_vec();
+		patch.extend([0x00, 0x00, 0x05, 0x00, 0x02, 0xAA, 0xBB]);
+		patch.extend([0x00, 0x00, 0x10, 0x00, 0x04, 0xCC]);
+
+		let mut disc = disc();
+
+		assert!(disc.apply_patch(&patch).is_err());
+		assert_eq!(disc.patched_sectors(), 0);
+		assert_image(&disc, &image());
+	}
+
+	#[test]
+	fn ppf_truncated_after_a_record() {
+		let mut patch = ppf_header(b'1');
+		patch.extend((SECTOR_LEN as u32 + 10).to_le_bytes());
+		patch.extend([2, 0x11, 0x22]);
+		patch.extend([0x20, 0x00]);
+
+		let mut disc = disc();
+		disc.apply_patch(&ppf_patch_at(0, &[0x33])).unwrap();
+
+		assert!(disc.apply_patch(&patch).is_err());
+		// only the earlier patch is left
+		assert_eq!(disc.patched_sectors(), 1);
+		assert_image(&disc, &patched_image(0, &[0x33]));
+	}
+
+	fn ppf_patch_at(offset: u32, bytes: &[u8]) -> Vec<u8> {
+		let mut patch = ppf_header(b'1');
+		patch.extend(offset.to_le_bytes());
+		patch.push(bytes.len() as u8);
+		patch.extend(bytes);
+		patch
+	}
+
+	// a window over the whole image with the original as the source, checksummed when checksum
+	// is set
+	fn vcdiff(instructions: &[u8], data: &[u8], addresses: &[u8], target: &[u8], checksum: Option<u32>) -> Vec<u8> {
+		let mut delta = varint(target.len());
+		delta.push(0);
+		delta.extend(varint(data.len()));
+		delta.extend(varint(instructions.len()));
+		delta.extend(varint(addresses.len()));
+
+		if let Some(checksum) = checksum {
+			delta.extend(checksum.to_be_bytes());
+		}
+
+		delta.extend(data);
+		delta.extend(instructions);
+		delta.extend(addresses);
+
+		let mut patch = VCDIFF_MAGIC.to_vec();
+		patch.extend([0, 0]);
+		patch.push(VCD_SOURCE | if checksum.is_some() { VCD_ADLER32 } else { 0 });
+		patch.extend(varint(IMAGE_LEN));
+		patch.extend(varint(0));
+		patch.extend(varint(delta.len()));
+		patch.extend(delta);
+
+		patch
+	}
+
+	#[test]
+	fn vcdiff_instructions() {
+		let mut target = image();
+		let changed = SECTOR_LEN + 100;
+		target[changed..changed + 8].copy_from_slice(b"ABABABAB");
+		target[changed + 8..changed + 18].fill(0xEE);
+
+		// with the default table, 19 copies a sized run from an absolute address, 3 adds 2 bytes,
+		// 35 copies from an address relative to here and 0 is a run
+		let instructions = [
+			[19].as_slice(), &varint(changed),
+			&[3],
+			&[35], &varint(6),
+			&[0], &varint(10),
+			&[19], &varint(IMAGE_LEN - changed - 18),
+		].concat();
+
+		// the second copy overlaps what it writes, 2 back from here repeats AB
+		let addresses = [varint(0), varint(2), varint(changed + 18)].concat();
+
+		let patch = vcdiff(&instructions, b"AB\xEE", &addresses, &target, Some(adler32(&target)));
+
+		let mut disc = disc();
+		disc.apply_patch(&patch).unwrap();
+
+		assert_eq!(disc.patched_sectors(), 1);
+		assert_image(&disc, &target);
+	}
+
+	#[test]
+	fn vcdiff_bad_checksum() {
+		let target = patched_image(0, b"X");
+		let instructions = [[2].as_slice(), &[19], &varint(IMAGE_LEN - 1)].concat();
+
+		let patch = vcdiff(&instructions, b"X", &varint(1), &target, Some(adler32(&target) ^ 1));
+
+		let mut disc = disc();
+
+		assert!(disc.apply_patch(&patch).is_err());
+		assert_eq!(disc.patched_sectors(), 0);
+		assert_image(&disc, &image());
+	}
+
+	#[test]
+	fn vcdiff_code_table() {
+		let table = vcd_code_table();
+
+		assert_eq!(table.len(), 256);
+		assert!(matches!(table[0].instructions[0], (VcdInstruction::Run, 0, 0)));
+		assert!(matches!(table[18].instructions[0], (VcdInstruction::Add, 17, 0)));
+		assert!(matches!(table[35].instructions[0], (VcdInstruction::Copy, 0, 1)));
+		assert!(matches!(table[255].instructions, [(VcdInstruction::Copy, 4, 8), (VcdInstruction::Add, 1, 0)]));
+	}
+
+	#[test]
+	fn adler32_reference() {
+		assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+	}
+
+	#[test]
+	fn unknown_format() {
+		assert!(disc().apply_patch(b"NOTAPATCH").is_err());
+	}
+}
@@ -1,73 +1,303 @@
+use std::collections::VecDeque;
+
+use log::*;
+
 use crate::cdrom::disc::Sector;
 
-const POS_XA_ADPCM_TABLE: [i16; 5] = [0, 60, 115, 98, 122];
-const NEG_XA_ADPCM_TABLE: [i16; 5] = [0, 0, -52, -55, -60];
+const POS_XA_ADPCM_TABLE: [i32; 4] = [0, 60, 115, 98];
+const NEG_XA_ADPCM_TABLE: [i32; 4] = [0, 0, -52, -55];
+
+// 18 sound groups of 128 bytes in the user data of a form 2 sector
+const SOUND_GROUP_LEN: usize = 128;
+const SOUND_GROUPS: usize = 18;
+const SAMPLES_PER_UNIT: usize = 28;
+
+// the resampler turns every 6 input samples into 7 output samples (37.8khz to 44.1khz), each output
+// sample is the last 29 input samples weighted by one of these tables
+const ZIGZAG_TABLES: [[i32; 29]; 7] = [
+	[
+		0x0000, 0x0000, 0x0000, 0x0000, 0x0000, -0x0002, 0x000A, -0x0022,
+		0x0041, -0x0054, 0x0034, 0x0009, -0x010A, 0x0400, -0x0A78, 0x234C,
+		0x6794, -0x1780, 0x0BCD, -0x0623, 0x0350, -0x016D, 0x006B, 0x000A,
+		-0x0010, 0x0011, -0x0008, 0x0003, -0x0001,
+	],
+	[
+		0x0000, 0x0000, 0x0000, -0x0002, 0x0000, 0x0003, -0x0013, 0x003C,
+		-0x004B, 0x00A2, -0x00E3, 0x0132, -0x0043, -0x0267, 0x0C9D, 0x74BB,
+		-0x11B4, 0x09B8, -0x05BF, 0x0372, -0x01A8, 0x00A6, -0x001B, 0x0005,
+		0x0006, -0x0008, 0x0003, -0x0001, 0x0000,
+	],
+	[
+		0x0000, 0x0000, -0x0001, 0x0003, -0x0002, -0x0005, 0x001F, -0x004A,
+		0x00B3, -0x0192, 0x02B1, -0x039E, 0x04F8, -0x05A6, 0x7939, -0x05A6,
+		0x04F8, -0x039E, 0x02B1, -0x0192, 0x00B3, -0x004A, 0x001F, -0x0005,
+		-0x0002, 0x0003, -0x0001, 0x0000, 0x0000,
+	],
+	[
+		0x0000, -0x0001, 0x0003, -0x0008, 0x0006, 0x0005, -0x001B, 0x00A6,
+		-0x01A8, 0x0372, -0x05BF, 0x09B8, -0x11B4, 0x74BB, 0x0C9D, -0x0267,
+		-0x0043, 0x0132, -0x00E3, 0x00A2, -0x004B, 0x003C, -0x0013, 0x0003,
+		0x0000, -0x0002, 0x0000, 0x0000, 0x0000,
+	],
+	[
+		-0x0001, 0x0003, -0x0008, 0x0011, -0x0010, 0x000A, 0x006B, -0x016D,
+		0x0350, -0x0623, 0x0BCD, -0x1780, 0x6794, 0x234C, -0x0A78, 0x0400,
+		-0x010A, 0x0009, 0x0034, -0x0054, 0x0041, -0x0022, 0x000A, -0x0001,
+		0x0000, 0x0001, 0x0000, 0x0000, 0x0000,
+	],
+	[
+		0x0002, -0x0008, 0x0010, -0x0023, 0x002B, 0x001A, -0x00EB, 0x027B,
+		-0x0548, 0x0AFA, -0x16FA, 0x53E0, 0x3C07, -0x1249, 0x080E, -0x0347,
+		0x015B, -0x0044, -0x0017, 0x0046, -0x0023, 0x0011, -0x0005, 0x0000,
+		0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+	],
+	[
+		-0x0005, 0x0011, -0x0023, 0x0046, -0x0017, -0x0044, 0x015B, -0x0347,
+		0x080E, -0x1249, 0x3C07, 0x53E0, -0x16FA, 0x0AFA, -0x0548, 0x027B,
+		-0x00EB, 0x001A, 0x002B, -0x0023, 0x0010, -0x0008, 0x0002, 0x0000,
+		0x0000, 0x0000, 0x0000, 0x0000, 0x0000,
+	],
+];
+
+// about half a second at 44.1khz, more than that means the sectors come in faster than the spu
+// plays them and the oldest samples are dropped
+const MAX_BUFFERED_SAMPLES: usize = 0x5800;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum XaSampleRate {
+	Hz37800,
+	Hz18900,
+}
+
+impl XaSampleRate {
+	pub fn from_bits(bit: bool) -> Self {
+		match bit {
+			true => Self::Hz18900,
+			false => Self::Hz37800,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum XaSampleDepth {
+	Bit4,
+	Bit8,
+}
+
+impl XaSampleDepth {
+	pub fn from_bits(bit: bool) -> Self {
+		match bit {
+			true => Self::Bit8,
+			false => Self::Bit4,
+		}
+	}
+}
+
+// coding info byte of the subheader
+#[derive(Debug, Clone, Copy)]
+struct CodingInfo {
+	stereo: bool,
+	sample_rate: XaSampleRate,
+	depth: XaSampleDepth,
+}
+
+impl CodingInfo {
+	pub fn from_bits(bits: u8) -> Self {
+		Self {
+			stereo: bits & 3 == 1,
+			sample_rate: XaSampleRate::from_bits((bits >> 2) & 1 != 0),
+			depth: XaSampleDepth::from_bits((bits >> 4) & 1 != 0),
+		}
+	}
+}
 
 pub struct XaAdpcmState {
-	output_l: Vec<i16>,
-	output_r: Vec<i16>,
+	// old and older sample for each channel, mono only uses the left one
+	prev_samples: [[i32; 2]; 2],
 
-	prev_samples_l: [i16; 2],
-	prev_samples_r: [i16; 2],
+	// last 32 decoded frames, the zigzag filter looks at the last 29
+	ring_buf: [[i16; 2]; 0x20],
+	ring_index: usize,
+	six_step: usize,
+
+	output: VecDeque<(i16, i16)>,
 }
+
 impl XaAdpcmState {
+	pub fn new() -> Self {
+		Self {
+			prev_samples: [[0; 2]; 2],
+
+			ring_buf: [[0; 2]; 0x20],
+			ring_index: 0,
+			six_step: 6,
+
+			output: VecDeque::new(),
+		}
+	}
+
 	pub fn decode_xa_sector(&mut self, sector: &Sector) {
-		let coding_info = sector.audio_sector().get(0x13).unwrap();
-		let is_stereo = if (coding_info & 3) == 1 { true } else { false };
-		let is_37800hz = if ((coding_info >> 2) & 1) == 0 { true } else { false };
-
-		for data_block in sector.audio_sector().chunks_exact(128) {
-			for audio_block in 0..4 {
-				if is_stereo {
-					Self::deocde_block(
-						data_block, 
-						audio_block, 
-						0, 
-						&mut self.output_l, 
-						&mut self.prev_samples_l
-					);
-					Self::deocde_block(
-						data_block, 
-						audio_block, 
-						1, 
-						&mut self.output_r, 
-						&mut self.prev_samples_r
-					);
+		let coding_info = CodingInfo::from_bits(sector.subheader()[3]);
+
+		trace!("XA sector {coding_info:?}");
+
+		let mut left = Vec::with_capacity(SOUND_GROUPS * 8 * SAMPLES_PER_UNIT);
+		let mut right = Vec::with_capacity(SOUND_GROUPS * 4 * SAMPLES_PER_UNIT);
+
+		let units = match coding_info.depth {
+			XaSampleDepth::Bit4 => 8,
+			XaSampleDepth::Bit8 => 4,
+		};
+
+		for group in sector.xa_audio().chunks_exact(SOUND_GROUP_LEN).take(SOUND_GROUPS) {
+			for unit in 0..units {
+				// stereo interleaves the units, left first
+				let (out, prev_samples) = if coding_info.stereo && unit & 1 != 0 {
+					(&mut right, &mut self.prev_samples[1])
 				} else {
-					Self::deocde_block(
-						data_block, 
-						audio_block, 
-						0, 
-						&mut self.output_l, 
-						&mut self.prev_samples_l
-					);
-					Self::deocde_block(
-						data_block, 
-						audio_block, 
-						1, 
-						&mut self.output_l, 
-						&mut self.prev_samples_l
-					);
-				}
+					(&mut left, &mut self.prev_samples[0])
+				};
+
+				Self::decode_unit(group, unit, coding_info.depth, out, prev_samples);
+			}
+		}
+
+		let right = if coding_info.stereo { &right } else { &left };
+
+		for (&l, &r) in left.iter().zip(right.iter()) {
+			// 18.9khz is played by repeating every sample
+			let repeat = match coding_info.sample_rate {
+				XaSampleRate::Hz37800 => 1,
+				XaSampleRate::Hz18900 => 2,
+			};
+
+			for _ in 0..repeat {
+				self.resample([l, r]);
 			}
 		}
+
+		if self.output.len() > MAX_BUFFERED_SAMPLES {
+			debug!("XA buffer overrun, dropping {} samples", self.output.len() - MAX_BUFFERED_SAMPLES);
+			self.output.drain(..self.output.len() - MAX_BUFFERED_SAMPLES);
+		}
 	}
 
-	fn deocde_block(data_block: &[u8], audio_block_index: usize, nibble: usize, out_buf: &mut Vec<i16>, prev_samples: &mut [i16; 2]) {
-		let shift = 12 - (data_block[4 + audio_block_index * 2 + nibble] & 0xF);
-		let filter = (data_block[4 + audio_block_index * 2 + nibble] & 0x30) >> 4;
+	fn decode_unit(group: &[u8], unit: usize, depth: XaSampleDepth, out: &mut Vec<i16>, prev_samples: &mut [i32; 2]) {
+		// header bytes 4..12 hold the unit parameters, 0..4 and 12..16 are copies
+		let header = group[4 + unit];
+
+		// shifts above 12 act as 9
+		let shift = match header & 0xF {
+			shift @ 0..=12 => shift,
+			_ => 9,
+		};
+
+		let filter = usize::from((header >> 4) & 3);
+
+		let filter_0 = POS_XA_ADPCM_TABLE[filter];
+		let filter_1 = NEG_XA_ADPCM_TABLE[filter];
 
-		let filter_0 = POS_XA_ADPCM_TABLE[filter as usize];
-		let filter_1 = NEG_XA_ADPCM_TABLE[filter as usize];
+		for i in 0..SAMPLES_PER_UNIT {
+			let sample = match depth {
+				XaSampleDepth::Bit4 => {
+					let nibble = (group[16 + i * 4 + unit / 2] >> ((unit & 1) * 4)) & 0xF;
+					i32::from(((nibble << 4) as i8) >> 4) << 12
+				},
+				XaSampleDepth::Bit8 => i32::from(group[16 + i * 4 + unit] as i8) << 8,
+			};
 
-		for nibble in 0..28 {
-			let sample_byte = (data_block[16 + audio_block_index + nibble * 4] << (nibble * 4) & 0xF);
-			let sample_halfword = (i16::from(sample_byte) << 12) >> 12;
-			let filtered_sample = (sample_halfword << shift) + ((prev_samples[0] * filter_0 + prev_samples[1] * filter_1 + 32) / 64).clamp(-0x8000, 0x7FFF);
+			let filtered_sample = ((sample >> shift) + ((prev_samples[0] * filter_0 + prev_samples[1] * filter_1 + 32) >> 6))
+				.clamp(-0x8000, 0x7FFF);
 
 			prev_samples[1] = prev_samples[0];
 			prev_samples[0] = filtered_sample;
-			out_buf.push(filtered_sample);
+			out.push(filtered_sample as i16);
+		}
+	}
+
+	fn resample(&mut self, frame: [i16; 2]) {
+		self.ring_buf[self.ring_index & 0x1F] = frame;
+		self.ring_index = self.ring_index.wrapping_add(1);
+		self.six_step -= 1;
+
+		if self.six_step == 0 {
+			self.six_step = 6;
+
+			for table in ZIGZAG_TABLES.iter() {
+				let l = self.zigzag_interpolate(table, 0);
+				let r = self.zigzag_interpolate(table, 1);
+
+				self.output.push_back((l, r));
+			}
 		}
 	}
-}
\ No newline at end of file
+
+	fn zigzag_interpolate(&self, table: &[i32; 29], channel: usize) -> i16 {
+		let sum: i32 = table.iter().enumerate()
+			.map(|(i, weight)| (i32::from(self.ring_buf[self.ring_index.wrapping_sub(i + 1) & 0x1F][channel]) * weight) >> 15)
+			.sum();
+
+		sum.clamp(-0x8000, 0x7FFF) as i16
+	}
+
+	// next 44.1khz sample, silence when the buffer has run out
+	pub fn get_sample(&mut self) -> (i16, i16) {
+		self.output.pop_front().unwrap_or((0, 0))
+	}
+
+	// everything decoded so far, for decoding outside of the cdrom
+	pub fn take_samples(&mut self) -> Vec<(i16, i16)> {
+		self.output.drain(..).collect()
+	}
+
+	pub fn clear(&mut self) {
+		self.output.clear();
+	}
+}
+
+impl Default for XaAdpcmState {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn decode_4bit_unit() {
+		let mut group = [0; SOUND_GROUP_LEN];
+
+		// filter 1 and shift 4, nibbles 7 and -8 then silence
+		group[4] = 0x14;
+		group[16] = 0x07;
+		group[20] = 0x08;
+
+		let mut out = Vec::new();
+		let mut prev_samples = [0; 2];
+
+		XaAdpcmState::decode_unit(&group, 0, XaSampleDepth::Bit4, &mut out, &mut prev_samples);
+
+		assert_eq!(out.len(), SAMPLES_PER_UNIT);
+		// the filter keeps decaying the last sample by 60/64
+		assert_eq!(out[..4], [0x700, -368, -345, -323]);
+		assert_eq!(prev_samples, [i32::from(out[27]), i32::from(out[26])]);
+	}
+
+	#[test]
+	fn decode_8bit_unit() {
+		let mut group = [0; SOUND_GROUP_LEN];
+
+		// unit 1 with filter 0 and shift 0, the byte is the top of the sample
+		group[5] = 0x00;
+		group[17] = 0x12;
+		group[21] = 0x80;
+
+		let mut out = Vec::new();
+
+		XaAdpcmState::decode_unit(&group, 1, XaSampleDepth::Bit8, &mut out, &mut [0; 2]);
+
+		assert_eq!(out[..3], [0x1200, -0x8000, 0]);
+	}
+}
@@ -1,3 +1,5 @@
+use crate::pgxp::PreciseVertex;
+
 const I44_MIN: i64 = -(1 << 43);
 const I44_MAX: i64 = (1 << 43) - 1;
 
@@ -132,6 +134,8 @@ struct GteRegisters {
 	sxy0: Vector2,
 	sxy1: Vector2,
 	sxy2: Vector2,
+	// unrounded positions of the SXY FIFO entries for pgxp
+	sxy_precise: [Option<PreciseVertex>; 3],
 	// Screen Z-coordinate FIFO   (4 stages)
 	sz0: u16,
 	sz1: u16,
@@ -199,6 +203,7 @@ impl GteRegisters {
 			sxy0: Vector2::default(),
 			sxy1: Vector2::default(),
 			sxy2: Vector2::default(),
+			sxy_precise: [None; 3],
 			// Screen Z-coordinate FIFO   (4 stages)
 			sz0: 0,
 			sz1: 0,
@@ -303,9 +308,9 @@ impl GteRegisters {
 			9 => self.ir1 = write as i16,
 			10 => self.ir2 = write as i16,
 			11 => self.ir3 = write as i16,
-			12 => self.sxy0 = Vector2::from_word(write),
-			13 => self.sxy1 = Vector2::from_word(write),
-			14 => self.sxy2 = Vector2::from_word(write),
+			12 => { self.sxy0 = Vector2::from_word(write); self.sxy_precise[0] = None; },
+			13 => { self.sxy1 = Vector2::from_word(write); self.sxy_precise[1] = None; },
+			14 => { self.sxy2 = Vector2::from_word(write); self.sxy_precise[2] = None; },
 			15 => self.push_sxy(write),
 			16 => self.sz0 = write as u16,
 			17 => self.sz1 = write as u16,
@@ -439,6 +444,8 @@ impl GteRegisters {
 		self.sxy0 = self.sxy1;
 		self.sxy1 = self.sxy2;
 		self.sxy2 = Vector2::from_word(word);
+
+		self.sxy_precise = [self.sxy_precise[1], self.sxy_precise[2], None];
 	}
 }
 
@@ -484,12 +491,17 @@ impl GteInstruction {
 
 pub struct Gte {
 	regs: GteRegisters,
+
+	// scales projected X by 3/4 so 16:9 worth of scene fits in the framebuffer
+	pub widescreen: bool,
 }
 
 impl Gte {
 	pub fn new() -> Self {
 		Self {
 			regs: GteRegisters::new(),
+
+			widescreen: false,
 		}
 	}
 
@@ -535,6 +547,24 @@ impl Gte {
 		self.regs.write_data_register(reg_index, write);
 	}
 
+	// unrounded position behind SXY0-2 / SXYP
+	pub fn read_precise_sxy(&self, reg_index: u32) -> Option<PreciseVertex> {
+		match reg_index {
+			12 ..= 14 => self.regs.sxy_precise[(reg_index - 12) as usize],
+			15 => self.regs.sxy_precise[2],
+			_ => None,
+		}
+	}
+
+	// called after a SXY register write when the written word carries a precise position
+	pub fn set_precise_sxy(&mut self, reg_index: u32, vertex: Option<PreciseVertex>) {
+		match reg_index {
+			12 ..= 14 => self.regs.sxy_precise[(reg_index - 12) as usize] = vertex,
+			15 => self.regs.sxy_precise[2] = vertex,
+			_ => {},
+		}
+	}
+
 	pub fn read_control_reg(&self, reg_index: u32) -> u32 {
 		self.regs.read_control_register(reg_index)
 	}
@@ -800,7 +830,13 @@ impl Gte {
 		self.regs.sxy0 = self.regs.sxy1;
 		self.regs.sxy1 = self.regs.sxy2;
 		
-		let sx = div * (self.regs.ir1 as i64) + (self.regs.screen_offset.x as i32 as i64);
+		let mut sx = div * (self.regs.ir1 as i64);
+
+		if self.widescreen {
+			sx = (sx * 3) >> 2;
+		}
+
+		sx += i64::from(self.regs.screen_offset.x);
 		self.clamp_mac0(sx);
 
 		let sy = div * (self.regs.ir2 as i64) + (self.regs.screen_offset.y as i32 as i64);
@@ -809,12 +845,35 @@ impl Gte {
 		self.regs.sxy2.x = self.clamp_sxy(1, sx >> 16);
 		self.regs.sxy2.y = self.clamp_sxy(2, sy >> 16);
 
+		self.regs.sxy_precise = [self.regs.sxy_precise[1], self.regs.sxy_precise[2], self.precise_projection(div)];
+
 		if depth_queue {
 			self.regs.mac0 = self.clamp_mac0(self.regs.dqb as i64 + (self.regs.dqa as i64 * div));
 			self.regs.ir0 = self.clamp_ir0((self.regs.mac0_unclamped >> 12) as i32);
 		}
 	}
 
+	// same projection as do_rtp without rounding the divide result or the screen position
+	fn precise_projection(&self, div: i64) -> Option<PreciseVertex> {
+		let h = f64::from(self.regs.h);
+		let z = f64::from(self.regs.sz3);
+
+		// keep the integer result when the divide overflowed
+		let ratio = if div == 0x1FFFF || z == 0.0 { div as f64 / 65536.0 } else { h / z };
+
+		let widescreen_scale = if self.widescreen { 0.75 } else { 1.0 };
+
+		let x = f64::from(self.regs.ir1) * ratio * widescreen_scale + f64::from(self.regs.screen_offset.x) / 65536.0;
+		let y = f64::from(self.regs.ir2) * ratio + f64::from(self.regs.screen_offset.y) / 65536.0;
+
+		// saturated or otherwise too far from the real result to be trusted
+		if (x.floor() - f64::from(self.regs.sxy2.x)).abs() > 1.0 || (y.floor() - f64::from(self.regs.sxy2.y)).abs() > 1.0 {
+			return None;
+		}
+
+		Some(PreciseVertex { x, y, z: z.max(1.0) })
+	}
+
 	fn do_depth_queue(&mut self, instr: GteInstruction, rgb: Rgb) {
 		self.interp_far_colour(
 			instr, 
@@ -303,8 +303,8 @@ impl R3000 {
 
 			// COP0
 			0x10 => match instr.cop_opcode() {
-				0x00 => self.op_mfcn(instr),
-				0x04 => self.op_mtcn(instr),
+				0x00 => self.op_mfcn(instr, bus),
+				0x04 => self.op_mtcn(instr, bus),
 				0x10 => self.op_rfe(instr),
 				_ => self.op_illegal(instr),
 			},
@@ -313,9 +313,9 @@ impl R3000 {
 			0x11 => self.op_copn(),
 			// COP2
 			0x12 => match instr.cop_opcode() {
-				0x00 => self.op_mfcn(instr),
+				0x00 => self.op_mfcn(instr, bus),
 				0x02 => self.op_cfcn(instr),
-				0x04 => self.op_mtcn(instr),
+				0x04 => self.op_mtcn(instr, bus),
 				0x06 => self.op_ctcn(instr),
 				0x10..=0x1F => self.op_gte(instr), // COP2 imm25
 				_ => self.op_illegal(instr),
@@ -362,8 +362,14 @@ impl R3000 {
 		let offset = self.registers.read_gpr(instr.reg_src());
 
 		let addr = offset.wrapping_add(instr.imm16_se());
+		let write = self.registers.read_gpr(instr.reg_tgt());
+
+		if bus.pgxp.enabled {
+			let vertex = bus.pgxp.lookup_register(instr.reg_tgt(), write);
+			bus.pgxp_store(addr, write, vertex);
+		}
 
-		self.store32(bus, addr, self.registers.read_gpr(instr.reg_tgt()), scheduler);
+		self.store32(bus, addr, write, scheduler);
 	}
 
 	fn op_lw(&mut self, instr: Instruction, bus: &mut Bus, scheduler: &mut Scheduler) {
@@ -377,7 +383,13 @@ impl R3000 {
 		let addr = offset.wrapping_add(instr.imm16_se());
 
 		if addr % 4 == 0 {
-			self.registers.write_gpr_delayed(instr.reg_tgt(), Self::load32(bus, addr, scheduler));
+			let value = Self::load32(bus, addr, scheduler);
+
+			if bus.pgxp.enabled {
+				bus.pgxp.load_register(instr.reg_tgt(), addr, value);
+			}
+
+			self.registers.write_gpr_delayed(instr.reg_tgt(), value);
 		} else {
 			self.exception(Exception::AddrLoadError);
 			self.cop0.reg_badvaddr = addr;
@@ -859,13 +871,17 @@ impl R3000 {
 	}
 
 	// ? Coprocessor Instructions
-	fn op_mfcn(&mut self, instr: Instruction) {
+	fn op_mfcn(&mut self, instr: Instruction, bus: &mut Bus) {
 		let value = match instr.cop_num() {
 			0 => self.cop0.read_reg(instr.reg_dst()),
 			2 => self.gte.read_data_reg(instr.reg_dst()),
 			_ => todo!("MFC{} $r{}", instr.cop_num(), instr.reg_dst())
 		};
 
+		if bus.pgxp.enabled && instr.cop_num() == 2 {
+			bus.pgxp.tag_register(instr.reg_tgt(), value, self.gte.read_precise_sxy(instr.reg_dst()));
+		}
+
 		self.registers.write_gpr_delayed(instr.reg_tgt(), value);
 	}
 
@@ -878,12 +894,18 @@ impl R3000 {
 		self.registers.write_gpr_delayed(instr.reg_tgt(), value);
 	}
 	
-	fn op_mtcn(&mut self, instr: Instruction) {
+	fn op_mtcn(&mut self, instr: Instruction, bus: &mut Bus) {
 		let write = self.registers.read_gpr(instr.reg_tgt());
 
 		match instr.cop_num() {
 			0 => self.cop0.write_reg(instr.reg_dst(), write),
-			2 => self.gte.write_data_reg(instr.reg_dst(), write),
+			2 => {
+				self.gte.write_data_reg(instr.reg_dst(), write);
+
+				if bus.pgxp.enabled {
+					self.gte.set_precise_sxy(instr.reg_dst(), bus.pgxp.lookup_register(instr.reg_tgt(), write));
+				}
+			},
 			_ => todo!("MTC{} $r{}", instr.cop_num(), instr.reg_dst()),
 		};
 	}
@@ -928,7 +950,13 @@ impl R3000 {
 		if addr % 4 == 0 {
 			match instr.cop_num() {
 				0 => self.exception(Exception::ReservedInstruction),
-				2 => self.gte.write_data_reg(instr.reg_tgt(), value),
+				2 => {
+					self.gte.write_data_reg(instr.reg_tgt(), value);
+
+					if bus.pgxp.enabled {
+						self.gte.set_precise_sxy(instr.reg_tgt(), bus.pgxp.lookup_memory(addr, value));
+					}
+				},
 				_ => self.exception(Exception::CopUnusable),
 			}
 		} else {
@@ -951,6 +979,11 @@ impl R3000 {
 			_ => { self.exception(Exception::CopUnusable); return; }
 		};
 
+		if bus.pgxp.enabled {
+			let vertex = self.gte.read_precise_sxy(instr.reg_tgt());
+			bus.pgxp_store(addr, write, vertex);
+		}
+
 		self.store32(bus, addr, write, scheduler);
 	}
 
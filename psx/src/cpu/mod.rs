@@ -199,6 +199,15 @@ impl R3000 {
 
 	}
 
+	// GTE widescreen hack, squeezes 3D geometry horizontally for 16:9 output
+	pub fn set_widescreen_hack(&mut self, enabled: bool) {
+		self.gte.widescreen = enabled;
+	}
+
+	pub fn is_widescreen_hack(&self) -> bool {
+		self.gte.widescreen
+	}
+
 	fn exception(&mut self, exception: Exception) {
 		self.cop0.reg_cause.exception = exception;
 		
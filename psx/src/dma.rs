@@ -2,7 +2,7 @@
 use std::array;
 use log::*;
 
-use crate::{bus::Bus, interrupts::Interrupts, scheduler::{Scheduler, SchedulerEvent}};
+use crate::{bus::Bus, interrupts::Interrupts, scheduler::{EventType, Scheduler, SchedulerEvent}};
 
 const CHANNEL_MDECIN: usize = 0;
 const CHANNEL_MDECOUT: usize = 1;
@@ -13,7 +13,13 @@ const CHANNEL_PIO: usize = 5;
 const CHANNEL_OTC: usize = 6;
 
 const CDROM_CLKS: u64 = 40;
-const AVG_CLKS: u64 = 1;
+const SPU_CLKS: u64 = 2 * crate::spu::TRANSFER_CYCLES_PER_HALFWORD;
+
+// cycles per word, ram to ram speed apart from the cdrom and spu which can't keep up
+const CHANNEL_CLKS: [u64; 7] = [1, 1, 1, CDROM_CLKS, SPU_CLKS, 1, 1];
+
+// how often a channel waiting on its device checks again, the cpu has the bus in between
+const DMA_POLL_CYCLES: u64 = 0x100;
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum SyncMode {
@@ -62,6 +68,10 @@ pub struct Channel {
 	pub transfer_active: bool,
 	pub manual_trigger: bool,
 
+	// started and not finished yet, words_left counts down the burst
+	running: bool,
+	words_left: u32,
+
 	// unimplemented
 	pause_transfer: bool,
 	bus_snooping: bool,
@@ -112,6 +122,11 @@ impl Channel {
 				self.transfer_active = (write >> 24) & 1 != 0;
 				self.manual_trigger = (write >> 28) & 1 != 0;
 
+				// stopped by software, the next start begins a new transfer
+				if !self.transfer_active {
+					self.stop();
+				}
+
 				self.bus_snooping = (write >> 30) & 1 != 0;
 
 				if self.channel_num == CHANNEL_OTC {
@@ -164,6 +179,19 @@ impl Channel {
 		self.transfer_active && trigger
 		//self.transfer_active
 	}
+
+	fn stop(&mut self) {
+		self.running = false;
+		self.words_left = 0;
+	}
+
+	// words for the next burst step, a single window when chopping
+	fn burst_len(&self, chopping: bool) -> u32 {
+		match chopping {
+			true => self.words_left.min(1 << self.chopping_dma_window_size),
+			false => self.words_left,
+		}
+	}
 }
 
 #[derive(Default)]
@@ -360,7 +388,7 @@ impl DmaController {
 			// DMA control
 			0x1F8010F0 => {
 				trace!("DPCR write 0x{write:X}");
-				self.control.write(write)
+				self.write_control(write)
 			},
 			// DMA interrupt
 			0x1F8010F4 => {
@@ -377,10 +405,10 @@ impl DmaController {
 
 		match addr & 0xFF {
 			// DPCR
-			0xF0 => self.control.write(u32::from(write) << 00),
-			0xF1 => self.control.write(u32::from(write) << 08),
-			0xF2 => self.control.write(u32::from(write) << 16),
-			0xF3 => self.control.write(u32::from(write) << 24),
+			0xF0 => self.write_control(u32::from(write) << 00),
+			0xF1 => self.write_control(u32::from(write) << 08),
+			0xF2 => self.write_control(u32::from(write) << 16),
+			0xF3 => self.write_control(u32::from(write) << 24),
 			// DICR
 			0xF4 => self.irq.write(u32::from(write) << 00),
 			0xF5 => self.irq.write(u32::from(write) << 08),
@@ -395,100 +423,178 @@ impl DmaController {
 		self.irq.raise_int(flag, interrupts);
 	}
 
+	// channels disabled mid transfer are stopped
+	fn write_control(&mut self, write: u32) {
+		self.control.write(write);
+
+		for (channel, enabled) in self.channels.iter_mut().zip(self.control.channel_enable) {
+			if !enabled {
+				channel.stop();
+			}
+		}
+	}
+
+	// the running channel with the highest priority out of the ones whose device is ready, 0 is
+	// the highest priority and on a tie the higher channel number wins
+	fn next_channel(&self, ready: [bool; 7]) -> Option<usize> {
+		(0..7).rev()
+			.filter(|&channel| ready[channel] && self.channel_running(channel))
+			.min_by_key(|&channel| self.control.channel_priority[channel])
+	}
+
+	fn channels_waiting(&self) -> bool {
+		(0..7).any(|channel| self.channel_running(channel))
+	}
+
+	fn channel_running(&self, channel: usize) -> bool {
+		self.control.channel_enable[channel] && self.channels[channel].running && self.channels[channel].active()
+	}
+
 }
 
 impl Bus {
+	// starts a channel, the transfer itself runs from DmaTransfer events so the cpu only sees the
+	// data once the dma has had the bus for long enough to move it
 	pub fn do_dma(&mut self, channel: usize, scheduler: &mut Scheduler) {
 
-		trace!("doing DMA{channel} {:?}", self.dma.channels[channel].sync_mode);
+		trace!("starting DMA{channel} {:?}", self.dma.channels[channel].sync_mode);
 
 		if !self.dma.control.channel_enable[channel] {
 			warn!("triggered DMA{channel} when disabled in control reg");
 			return;
 		}
 
-		if channel == CHANNEL_OTC {
-			self.do_dma_otc(scheduler);
-			return;
+		let channel = &mut self.dma.channels[channel];
+
+		// already running channels carry on where they are
+		if !channel.running {
+			channel.running = true;
+			channel.words_left = match channel.block_size {
+				0 => 0x10000,
+				size => size,
+			};
 		}
 
-		let words = match self.dma.channels[channel].sync_mode {
-			SyncMode::LinkedList => self.do_dma_linked_list(channel, scheduler),
-			_ => self.do_dma_block(channel, scheduler),
+		scheduler.remove_event(EventType::DmaTransfer);
+		scheduler.schedule_event(SchedulerEvent::new(EventType::DmaTransfer), 0);
+	}
+
+	// gives the bus to the highest priority channel with its device ready for one burst, slice
+	// block or list node. the cpu is stalled for as long as that takes
+	pub fn dma_transfer(&mut self, scheduler: &mut Scheduler) {
+		let ready: [bool; 7] = array::from_fn(|channel| self.dma_request(channel));
+
+		let Some(channel_num) = self.dma.next_channel(ready) else {
+			// running channels waiting on their device, the cpu keeps the bus in the meantime
+			if self.dma.channels_waiting() {
+				scheduler.schedule_event(SchedulerEvent::new(EventType::DmaTransfer), DMA_POLL_CYCLES);
+			}
+
+			return;
 		};
 
-		let dma_clks = match channel {
-			CHANNEL_CDROM => CDROM_CLKS,
-			_ => AVG_CLKS
+		let channel = self.dma.channels[channel_num].clone();
+
+		// burst transfers give the bus back to the cpu between windows when chopping is enabled
+		let chopping = channel.chopping_enabled && channel.sync_mode == SyncMode::Burst;
+
+		let (words, finished) = match (channel_num, channel.sync_mode) {
+			(CHANNEL_OTC, _) => self.do_dma_otc(chopping, scheduler),
+			(_, SyncMode::LinkedList) => self.do_dma_linked_list(channel_num, scheduler),
+			(_, SyncMode::Slice) => self.do_dma_slice(channel_num, scheduler),
+			(_, SyncMode::Burst) => self.do_dma_burst(channel_num, chopping, scheduler),
 		};
 
-		scheduler.schedule_event(SchedulerEvent::new(crate::scheduler::EventType::DmaIrq(channel as u8)), words * dma_clks);
+		let cycles = words * CHANNEL_CLKS[channel_num];
+		scheduler.tick_scheduler(cycles);
 
+		if channel_num == CHANNEL_SPU {
+			self.spu.finish_transfer(scheduler, cycles);
+		}
+
+		let next_step = if finished {
+			trace!("DMA{channel_num} finished");
+
+			let channel = &mut self.dma.channels[channel_num];
+
+			channel.running = false;
+			channel.transfer_active = false;
+			channel.manual_trigger = false;
+
+			self.dma.raise_int(channel_num as u8, &mut self.interrupts);
+
+			0
+		} else if chopping {
+			1 << channel.chopping_cpu_window_size
+		} else {
+			0
+		};
+
+		scheduler.schedule_event(SchedulerEvent::new(EventType::DmaTransfer), next_step);
 	}
-	
-	fn do_dma_linked_list(&mut self, channel_num: usize, scheduler: &mut Scheduler) -> u64 {
+
+	// device side of the handshake, slice and linked list transfers only move data while it's set
+	fn dma_request(&self, channel: usize) -> bool {
+		match channel {
+			CHANNEL_MDECIN => self.mdec.dma_in_request(),
+			CHANNEL_MDECOUT => self.mdec.dma_out_request(),
+			CHANNEL_GPU => self.gpu.dma_request(),
+			_ => true,
+		}
+	}
+
+	// one node per step
+	fn do_dma_linked_list(&mut self, channel_num: usize, scheduler: &mut Scheduler) -> (u64, bool) {
 		
 		assert_eq!(channel_num, 2);
 		assert_eq!(self.dma.channels[channel_num].transfer_dir, DmaDirection::FromRam);
-		
-		trace!("start linked list DMA{channel_num} step: {:?}", self.dma.channels[channel_num].step_dir);
 
-		let channel = self.dma.channels[channel_num].clone();
+		let addr = self.dma.channels[channel_num].base_addr;
 
-		let mut addr = channel.base_addr;
-		let mut words_sent = 0;
+		let header = self.read32(addr, scheduler);
+		let words_to_send = header >> 24;
+		let next_addr = header & 0xFFFFFF;
 
-		loop {
+		//trace!("node: 0x{header:X} word count: 0x{words_to_send:X} next addr: 0x{next_addr:X}");
 
-			let header = self.read32(addr, scheduler);
-			let words_to_send = header >> 24;
-			let next_addr = header & 0xFFFFFF;
+		for i in 0..words_to_send {
 
-			//trace!("node: 0x{header:X} word count: 0x{words_to_send:X} next addr: 0x{next_addr:X}");
+			let word_addr = addr.wrapping_add(4 * (i + 1));
+			let data = self.read32(word_addr, scheduler);
 
-			for i in 0..words_to_send {
-
-				let data = self.read32( addr.wrapping_add(4 * (i + 1)), scheduler);
-				self.gpu.gp0_cmd(data);
-
-				//trace!("[0x{i:X}] linked list write 0x{data:X} to GP0");
-				words_sent += 1;
+			if self.pgxp.enabled {
+				self.gpu.set_next_word_precise(self.pgxp.lookup_memory(word_addr, data));
 			}
 
-			addr = next_addr;
-
-			// the end node only needs bit 23 to be set
-			if next_addr & (1 << 23) != 0 {
-				//trace!("linked list end (old addr is 0x{addr:X})");
-				break;
-			}
+			self.gpu.gp0_cmd(data);
 
+			//trace!("[0x{i:X}] linked list write 0x{data:X} to GP0");
 		}
 
-		self.dma.channels[channel_num].transfer_active = false;
-		self.dma.channels[channel_num].manual_trigger = false;
-		self.dma.channels[channel_num].base_addr = addr;
+		self.dma.channels[channel_num].base_addr = next_addr;
 
-		words_sent
+		// the end node only needs bit 23 to be set
+		(u64::from(words_to_send) + 1, next_addr & (1 << 23) != 0)
 
 	}
 
-	fn do_dma_otc(&mut self, scheduler: &mut Scheduler) -> u64 {
+	fn do_dma_otc(&mut self, chopping: bool, scheduler: &mut Scheduler) -> (u64, bool) {
 
-		let mut addr = self.dma.channels[CHANNEL_OTC].base_addr;
-		let mut dma_len = self.dma.channels[CHANNEL_OTC].block_size as u32;
+		let channel = &self.dma.channels[CHANNEL_OTC];
 
-		if dma_len == 0 {
-			dma_len = 0x10000;
-		}
+		let mut addr = channel.base_addr;
+		let words = channel.burst_len(chopping);
 
-		trace!("DMA6 len: 0x{dma_len:X} start: 0x{addr:X}");
+		trace!("DMA6 len: 0x{words:X} start: 0x{addr:X}");
 		
-		for i in 0..dma_len {
+		for _ in 0..words {
 
 			//println!("[0x{addr:X}] writing OTC");
 			
-			let next_addr = if i == dma_len - 1 {
+			let channel = &mut self.dma.channels[CHANNEL_OTC];
+			channel.words_left -= 1;
+
+			let next_addr = if channel.words_left == 0 {
 				trace!("DMA6 end: 0x{addr:X}");
 				0xFFFFFF
 			} else {
@@ -501,14 +607,44 @@ impl Bus {
 
 		}
 
-		self.dma.channels[CHANNEL_OTC].transfer_active = false;
-		self.dma.channels[CHANNEL_OTC].manual_trigger = false;
+		let channel = &mut self.dma.channels[CHANNEL_OTC];
+		channel.base_addr = addr;
 
-		dma_len as u64
+		(u64::from(words), channel.words_left == 0)
 
 	}
 
-	fn do_dma_block(&mut self, channel_num: usize, scheduler: &mut Scheduler) -> u64 {
+	fn do_dma_burst(&mut self, channel_num: usize, chopping: bool, scheduler: &mut Scheduler) -> (u64, bool) {
+		let words = self.dma.channels[channel_num].burst_len(chopping);
+
+		if channel_num == CHANNEL_CDROM {
+			trace!("CDROM words: {words} (reading 0x{:X} bytes)", words * 4);
+		}
+
+		self.transfer_words(channel_num, words, scheduler);
+
+		let channel = &mut self.dma.channels[channel_num];
+		channel.words_left -= words;
+
+		(u64::from(words), channel.words_left == 0)
+	}
+
+	// one block per step, the remaining block count is kept in the block amount like on hardware
+	fn do_dma_slice(&mut self, channel_num: usize, scheduler: &mut Scheduler) -> (u64, bool) {
+		let channel = &self.dma.channels[channel_num];
+		let words = channel.block_size;
+
+		trace!("DMA{channel_num} slice (0x{:X} * 0x{:X})", channel.block_size, channel.block_amount);
+
+		self.transfer_words(channel_num, words, scheduler);
+
+		let channel = &mut self.dma.channels[channel_num];
+		channel.block_amount = channel.block_amount.saturating_sub(1);
+
+		(u64::from(words), channel.block_amount == 0)
+	}
+
+	fn transfer_words(&mut self, channel_num: usize, words: u32, scheduler: &mut Scheduler) {
 
 		let channel = self.dma.channels[channel_num].clone();
 
@@ -518,30 +654,10 @@ impl Bus {
 		};
 
 		let mut addr = channel.base_addr;
-		let words_left = match channel.sync_mode {
-			SyncMode::Burst => channel.block_size,
-			// TODO handle this properly
-			SyncMode::Slice => channel.block_size * (if channel.block_amount == 0 { 1 } else { channel.block_amount }),
-			SyncMode::LinkedList => unimplemented!()
-		};
 
-		if channel_num == CHANNEL_CDROM {
-			trace!("CDROM words left: {words_left} (reading 0x{:X} bytes)", words_left * 4);
-		}
-		if channel_num == CHANNEL_GPU {
-			trace!("GPU {:?} (0x{:X} * 0x{:X}) words left: 0x{words_left:X} (writing 0x{:X} halfwords)", channel.sync_mode, channel.block_size, channel.block_amount, words_left * 2);
-		}
-		if channel_num == CHANNEL_MDECIN {
-			trace!("MDECIN {:?} (0x{:X} * 0x{:X}) words left: 0x{words_left:X} (writing 0x{:X} halfwords)", channel.sync_mode, channel.block_size, channel.block_amount, words_left * 2);
-		}
-		if channel_num == CHANNEL_MDECOUT {
-			trace!("MDECOUT {:?} (0x{:X} * 0x{:X}) words left: 0x{words_left:X} (writing 0x{:X} halfwords)", channel.sync_mode, channel.block_size, channel.block_amount, words_left * 2);
-		}
-
-		trace!("doing DMA{channel_num} {:?} start: 0x{addr:X} words: 0x{words_left:X}", channel.sync_mode);
-		trace!("{} * {}", channel.block_size, channel.block_amount);
+		trace!("doing DMA{channel_num} {:?} start: 0x{addr:X} words: 0x{words:X}", channel.sync_mode);
 
-		for _ in 0..words_left {
+		for _ in 0..words {
 
 			match channel.transfer_dir {
 				DmaDirection::FromRam => {
@@ -550,6 +666,10 @@ impl Bus {
 					match channel_num {
 						CHANNEL_GPU => {
 							//trace!("dma block write 0x{word:X} to GP0");
+							if self.pgxp.enabled {
+								self.gpu.set_next_word_precise(self.pgxp.lookup_memory(addr, word));
+							}
+
 							self.gpu.gp0_cmd(word);
 						},
 						CHANNEL_SPU => {
@@ -557,7 +677,7 @@ impl Bus {
 							self.spu.write_sram((word >> 16) as u16);
 						},
 						CHANNEL_MDECIN => {
-							self.mdec.write32(0x1F801820, word);
+							self.mdec.write32(0x1F801820, word, scheduler);
 						},
 						_ => todo!("FromRam DMA{channel_num}")
 					}
@@ -582,7 +702,7 @@ impl Bus {
 							u32::from_le_bytes(data)
 						},
 						CHANNEL_MDECOUT => {
-							self.mdec.read32(0x1F801820)
+							self.mdec.read32(0x1F801820, scheduler)
 						},
 						CHANNEL_SPU => {
 							u32::from(self.spu.read_sram())
@@ -599,18 +719,8 @@ impl Bus {
 
 		}
 
-		trace!("DMA{channel_num} finished");
-
-		self.dma.channels[channel_num].transfer_active = false;
-		self.dma.channels[channel_num].manual_trigger = false;
 		self.dma.channels[channel_num].base_addr = addr;
-		
-		if channel.sync_mode == SyncMode::Slice {
-			self.dma.channels[channel_num].block_amount = 0;
-		}
-
-		words_left as u64
 
 	}
 
-}
\ No newline at end of file
+}
@@ -1,9 +1,17 @@
 #![allow(dead_code)]
 use std::cmp;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use log::*;
 
+use crate::gpu_debug::{DrawCall, DrawCallKind, DrawVertex, FrameCapture};
+use crate::gpu_dump::{GpuDump, GpuDumpEvent, TextureCacheDump};
+use crate::pgxp::PreciseVertex;
+
 const DITHERING_TABLE: &[[i8; 4]; 4] = &[[-4, 0, -3, 1], [2, -2, 3, -1], [-3, 1, -4, 0], [3, -1, 2, -2]];
 
+// primitives covering fewer pixels than this aren't worth splitting across threads
+const PARALLEL_MIN_PIXELS: i32 = 64 * 64;
+
 #[derive(Debug, Clone, Copy)]
 enum DrawCommand {
 	CpuVramDma,
@@ -15,7 +23,7 @@ enum DrawCommand {
 	QuickFill(u32)
 }
 
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone, Copy, PartialEq)]
 enum TexBitDepth {
 	#[default]
 	FourBit = 0,
@@ -156,6 +164,13 @@ impl ColourDepth {
 	}
 }
 
+// 160-pin GPU of early consoles and the 208-pin GPU of later ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpuModel {
+	V0,
+	V2,
+}
+
 #[derive(Debug, Clone, Copy)]
 enum DmaDirection {
 	Off = 0,
@@ -231,6 +246,8 @@ struct TexturePage {
 	bit_depth: TexBitDepth,
 	dithering: bool,
 	allow_drawing_to_display_area: bool,
+	// only settable on v2 gpus after GP1(09h)
+	texture_disable: bool,
 	flip_x: bool,
 	flip_y: bool,
 }
@@ -241,6 +258,101 @@ struct TextureWindow {
 	offset: Vertex,
 }
 
+// 2KB direct mapped cache of 256 lines, each line holds 4 halfwords (8 bytes) of vram.
+// the cached area of the texture page is 64x64 texels at 4bit, 64x32 at 8bit and 32x32 at 16bit.
+// vram writes don't update it, only GP0(01h) does, so stale texels keep being drawn until then
+struct TextureCache {
+	enabled: bool,
+
+	// tags and lines are atomics only so the gpu stays Sync for the threaded rasterizer,
+	// textured primitives always take the serial path while the cache is enabled
+	tags: [AtomicU32; 256],
+	lines: [AtomicU64; 256],
+
+	// the clut is loaded when a textured primitive uses a different clut position, or an 8bit one
+	// after only 16 entries were loaded for 4bit. like the texels it isn't reloaded on vram writes,
+	// only GP0(01h) drops it
+	clut_tag: Option<(i32, i32, TexBitDepth)>,
+	clut: [u16; 256],
+}
+
+impl TextureCache {
+	// set on valid tags, the rest of the tag is the line address and the bit depth
+	const TAG_VALID: u32 = 1 << 31;
+
+	// can be turned off to let large textured primitives use the threaded rasterizer
+	fn new() -> Self {
+		Self {
+			enabled: true,
+
+			tags: std::array::from_fn(|_| AtomicU32::new(0)),
+			lines: std::array::from_fn(|_| AtomicU64::new(0)),
+
+			clut_tag: None,
+			clut: [0; 256],
+		}
+	}
+
+	fn invalidate(&mut self) {
+		for tag in self.tags.iter_mut() {
+			*tag.get_mut() = 0;
+		}
+
+		self.clut_tag = None;
+	}
+
+	// returns the halfword at (x, y), loading the line from vram on a miss
+	fn read(&self, vram: &[u16], x: u32, y: u32, line_index: usize, bit_depth: TexBitDepth) -> u16 {
+		let line_x = x & 0x3FC;
+		let tag = Self::TAG_VALID | (bit_depth as u32) << 20 | (y & 0x1FF) << 10 | line_x;
+
+		let line = if self.tags[line_index].load(Ordering::Relaxed) == tag {
+			self.lines[line_index].load(Ordering::Relaxed)
+		} else {
+			let line = (0..4).fold(0, |line, i| line | u64::from(vram[coord_to_vram_index(line_x + i, y) as usize]) << (i * 16));
+
+			self.tags[line_index].store(tag, Ordering::Relaxed);
+			self.lines[line_index].store(line, Ordering::Relaxed);
+
+			line
+		};
+
+		(line >> ((x & 3) * 16)) as u16
+	}
+
+	// saved before the upscaled pass so the native pass sees the cache the way the hardware would
+	fn save(&self) -> Vec<(u32, u64)> {
+		self.tags.iter().zip(self.lines.iter())
+			.map(|(tag, line)| (tag.load(Ordering::Relaxed), line.load(Ordering::Relaxed)))
+			.collect()
+	}
+
+	fn restore(&mut self, saved: Vec<(u32, u64)>) {
+		for (i, (tag, line)) in saved.into_iter().enumerate() {
+			*self.tags[i].get_mut() = tag;
+			*self.lines[i].get_mut() = line;
+		}
+	}
+
+	fn dump(&self) -> TextureCacheDump {
+		TextureCacheDump {
+			lines: self.save(),
+			clut_tag: self.clut_tag.map(|(x, y, depth)| (x as u16, y as u16, depth as u8)),
+			clut: self.clut.to_vec(),
+		}
+	}
+
+	fn load_dump(&mut self, dump: &TextureCacheDump) {
+		self.restore(dump.lines.clone());
+		self.clut_tag = dump.clut_tag.map(|(x, y, depth)| (x.into(), y.into(), match depth {
+			0 => TexBitDepth::FourBit,
+			1 => TexBitDepth::EightBit,
+			_ => TexBitDepth::FiveteenBit,
+		}));
+		self.clut.copy_from_slice(&dump.clut);
+	}
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 struct Colour {
 	r: u8,
@@ -294,6 +406,14 @@ impl Colour {
 	}
 }
 
+// result of shading a single pixel, written to vram by draw_pixel_15bit
+#[derive(Debug, Clone, Copy)]
+struct ShadedPixel {
+	colour: u16,
+	semi_transparent: bool,
+	mask_bit: bool,
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 struct Vertex {
 	x: i32,
@@ -301,6 +421,8 @@ struct Vertex {
 	tex_x: i32,
 	tex_y: i32,
 	colour: Colour,
+	// unrounded position from the GTE when pgxp is enabled, drawing offset included
+	precise: Option<PreciseVertex>,
 }
 
 impl Vertex {
@@ -318,6 +440,27 @@ impl Vertex {
 			..Default::default()
 		}
 	}
+	fn scaled(&self, scale: i32) -> Self {
+		match self.precise {
+			Some(precise) => Self {
+				x: (precise.x * f64::from(scale)).floor() as i32,
+				y: (precise.y * f64::from(scale)).floor() as i32,
+				..*self
+			},
+			None => Self {
+				x: self.x * scale,
+				y: self.y * scale,
+				..*self
+			},
+		}
+	}
+	fn unscaled(&self, scale: i32) -> Self {
+		Self {
+			x: self.x.div_euclid(scale),
+			y: self.y.div_euclid(scale),
+			..*self
+		}
+	}
 	fn plus_offset(&self, offset: Vertex) -> Self {
 		Self {
 			x: self.x + offset.x,
@@ -330,6 +473,14 @@ impl Vertex {
 pub struct Gpu {
 	pub vram: Box<[u16]>,
 
+	model: GpuModel,
+	// GP1(09h), allows GP0(E1h) to disable textures
+	texture_disable_allowed: bool,
+	// toggled every vblank, odd/even field in interlaced modes
+	interlace_field: bool,
+	// GPUSTAT from the gpu state instead of the fixed 0x1C000000, off until more games are tested with it
+	accurate_gpustat: bool,
+
 	gp0_state: GP0State,
 	gp0_params: Vec<u32>,
 
@@ -347,6 +498,7 @@ pub struct Gpu {
 
 	tex_page: TexturePage,
 	tex_window: TextureWindow,
+	tex_cache: TextureCache,
 
 	display_enabled: bool,
 
@@ -368,6 +520,28 @@ pub struct Gpu {
 	vertical_display_range: (u32, u32),
 
 	internal_reg: Option<u32>,
+
+	// pgxp positions of the words in gp0_params, set by the bus before each GP0 write
+	next_word_precise: Option<PreciseVertex>,
+	gp0_precise: Vec<Option<PreciseVertex>>,
+
+	// number of worker threads used to rasterize large primitives, 1 disables threading
+	render_threads: usize,
+
+	// internal resolution multiplier, polygons, lines and rects are drawn again into hires_vram
+	// while vram stays at native resolution
+	resolution_scale: u32,
+	hires_vram: Box<[u16]>,
+
+	// gpu dump being recorded, armed waits for GP0 to finish its current command first
+	recording_armed: bool,
+	recording: Option<GpuDump>,
+
+	// per frame draw call capture for the debugger, restarted every vblank
+	debug_capture: bool,
+	debug_armed: bool,
+	debug_frame: Option<FrameCapture>,
+	debug_last_frame: Option<FrameCapture>,
 }
 
 impl Gpu {
@@ -375,6 +549,11 @@ impl Gpu {
 		Self {
 			vram: vec![0; 512 * 1024].into_boxed_slice().try_into().unwrap(),
 
+			model: GpuModel::V0,
+			texture_disable_allowed: false,
+			interlace_field: false,
+			accurate_gpustat: false,
+
 			gp0_state: GP0State::WaitingForNextCmd,
 			gp0_params: vec![0; 16],
 
@@ -391,6 +570,7 @@ impl Gpu {
 
 			tex_page: TexturePage::default(),
 			tex_window: TextureWindow::default(),
+			tex_cache: TextureCache::new(),
 
 			display_enabled: false,
 
@@ -411,12 +591,324 @@ impl Gpu {
 			vertical_display_range: (0, 0),
 
 			internal_reg: None,
+
+			next_word_precise: None,
+			gp0_precise: vec![None; 16],
+
+			render_threads: 1,
+
+			resolution_scale: 1,
+			hires_vram: Box::new([]),
+
+			recording_armed: false,
+			recording: None,
+
+			debug_capture: false,
+			debug_armed: false,
+			debug_frame: None,
+			debug_last_frame: None,
+		}
+	}
+
+	// every primitive is fully rasterized before the gp0 write returns, so VRAM reads,
+	// VRAM copies and the display output never observe a partially drawn primitive
+	pub fn set_render_threads(&mut self, threads: usize) {
+		self.render_threads = threads.max(1);
+	}
+
+	pub fn get_render_threads(&self) -> usize {
+		self.render_threads
+	}
+
+	pub fn set_resolution_scale(&mut self, scale: u32) {
+		let scale = match scale {
+			1 | 2 | 4 | 8 => scale,
+			_ => {
+				warn!("unsupported resolution scale {scale}x, using 1x");
+				1
+			}
+		};
+
+		self.resolution_scale = scale;
+
+		if scale == 1 {
+			self.hires_vram = Box::new([]);
+			return;
+		}
+
+		self.hires_vram = vec![0; 1024 * 512 * (scale * scale) as usize].into_boxed_slice();
+
+		// start from the current native contents
+		for y in 0..512 {
+			for x in 0..1024 {
+				let colour = self.vram[coord_to_vram_index(x, y) as usize];
+				self.fill_hires_block(x, y, colour);
+			}
+		}
+	}
+
+	pub fn set_texture_cache_enabled(&mut self, enabled: bool) {
+		self.tex_cache.enabled = enabled;
+		self.tex_cache.invalidate();
+	}
+
+	pub fn is_texture_cache_enabled(&self) -> bool {
+		self.tex_cache.enabled
+	}
+
+	pub fn get_resolution_scale(&self) -> u32 {
+		self.resolution_scale
+	}
+
+	// (1024 * scale) x (512 * scale) halfwords, empty at 1x
+	pub fn get_hires_vram(&self) -> &[u16] {
+		&self.hires_vram
+	}
+
+	pub fn set_model(&mut self, model: GpuModel) {
+		self.model = model;
+
+		if model == GpuModel::V0 {
+			self.texture_disable_allowed = false;
+			self.tex_page.texture_disable = false;
+		} else {
+			self.flip_screen = false;
+		}
+	}
+
+	pub fn get_model(&self) -> GpuModel {
+		self.model
+	}
+
+	pub fn set_accurate_gpustat(&mut self, enabled: bool) {
+		self.accurate_gpustat = enabled;
+	}
+
+	pub fn is_accurate_gpustat(&self) -> bool {
+		self.accurate_gpustat
+	}
+
+	// GP1(00h), the same as GP1(01h), GP1(02h), GP1(03h) display off, GP1(04h) to GP1(08h)
+	// and GP0(E1h) to GP0(E6h) with 0, except for the default display ranges
+	fn reset(&mut self) {
+		self.gp0_state = GP0State::WaitingForNextCmd;
+		self.irq = false;
+		self.display_enabled = false;
+		self.dma_direction = DmaDirection::Off;
+
+		self.display_start = Vertex::new(0, 0);
+		self.horizontal_display_range = (0x200, 0x200 + 256 * 10);
+		self.vertical_display_range = (0x10, 0x10 + 240);
+
+		self.horizontal_res = HorizontalRes::H256;
+		self.vertical_res = VerticalRes::V240;
+		self.video_mode = VideoMode::Ntsc;
+		self.display_colour_depth = ColourDepth::FiveteenBit;
+		self.vertical_interlace = false;
+		self.force_h368 = false;
+		self.flip_screen = false;
+
+		self.tex_page = TexturePage::default();
+		self.tex_window = TextureWindow::default();
+		self.draw_area_top_left = Vertex::new(0, 0);
+		self.draw_area_bottom_right = Vertex::new(0, 0);
+		self.drawing_offset = Vertex::new(0, 0);
+		self.force_mask_bit = false;
+		self.check_mask_bit = false;
+
+		self.tex_cache.invalidate();
+	}
+
+	pub fn vblank(&mut self) {
+		self.interlace_field = !self.interlace_field;
+
+		if let Some(dump) = &mut self.recording {
+			dump.events.push(GpuDumpEvent::Vblank);
+		}
+
+		if self.debug_capture {
+			if let Some(frame) = self.debug_frame.take() {
+				self.debug_last_frame = Some(frame);
+			}
+
+			self.debug_armed = true;
+		}
+	}
+
+	pub fn set_debug_capture(&mut self, enabled: bool) {
+		self.debug_capture = enabled;
+		self.debug_armed = false;
+		self.debug_frame = None;
+
+		if !enabled {
+			self.debug_last_frame = None;
+		}
+	}
+
+	pub fn get_last_frame_capture(&self) -> Option<&FrameCapture> {
+		self.debug_last_frame.as_ref()
+	}
+
+	pub fn start_recording(&mut self) {
+		self.recording_armed = true;
+		self.recording = None;
+	}
+
+	pub fn stop_recording(&mut self) -> Option<GpuDump> {
+		self.recording_armed = false;
+		self.recording.take()
+	}
+
+	pub fn is_recording(&self) -> bool {
+		self.recording_armed || self.recording.is_some()
+	}
+
+	fn record(&mut self, event: GpuDumpEvent) {
+		// the dump can only start between commands, the snapshot can't hold half a command
+		if self.recording_armed && matches!(self.gp0_state, GP0State::WaitingForNextCmd) {
+			self.recording_armed = false;
+			self.recording = Some(self.snapshot());
+		}
+
+		if self.debug_armed && matches!(self.gp0_state, GP0State::WaitingForNextCmd) {
+			self.debug_armed = false;
+			self.debug_frame = Some(FrameCapture { dump: self.snapshot(), draws: Vec::new() });
+		}
+
+		if let Some(dump) = &mut self.recording {
+			dump.events.push(event);
+		}
+
+		if let Some(frame) = &mut self.debug_frame {
+			frame.dump.events.push(event);
+		}
+	}
+
+	fn snapshot(&self) -> GpuDump {
+		GpuDump {
+			model: self.model,
+			vram: self.vram.to_vec(),
+			interlace_field: self.interlace_field,
+			tex_cache: self.tex_cache.enabled.then(|| self.tex_cache.dump()),
+			events: self.state_commands(),
 		}
 	}
 
+	// the part of a snapshot that isn't replayed as commands
+	pub(crate) fn load_snapshot(&mut self, dump: &GpuDump) {
+		self.set_model(dump.model);
+		self.vram.copy_from_slice(&dump.vram);
+		self.interlace_field = dump.interlace_field;
+		self.set_texture_cache_enabled(dump.tex_cache.is_some());
+
+		if let Some(cache) = &dump.tex_cache {
+			self.tex_cache.load_dump(cache);
+		}
+	}
+
+	// adds a draw to the debugger's frame capture, called once the draw's last word has been recorded
+	fn log_draw(&mut self, kind: DrawCallKind, vertices: &[Vertex], textured: bool, raw_texture: bool, clut: Vertex, semi_transparent: bool) {
+		if self.debug_frame.is_none() {
+			return;
+		}
+
+		let draw = DrawCall {
+			kind,
+			vertices: vertices.iter().map(|v| DrawVertex {
+				x: v.x,
+				y: v.y,
+				colour: (v.colour.r, v.colour.g, v.colour.b),
+				u: v.tex_x,
+				v: v.tex_y,
+			}).collect(),
+
+			textured,
+			raw_texture,
+			tex_page: (self.tex_page.x_base, self.tex_page.y_base),
+			tex_depth: match self.tex_page.bit_depth {
+				TexBitDepth::FourBit => 4,
+				TexBitDepth::EightBit => 8,
+				TexBitDepth::FiveteenBit => 16,
+			},
+			clut: (clut.x as u32, clut.y as u32),
+
+			semi_transparent,
+			semi_transparency_mode: self.tex_page.transp_type as u32,
+			force_mask_bit: self.force_mask_bit,
+			check_mask_bit: self.check_mask_bit,
+
+			event_index: 0,
+		};
+
+		if let Some(frame) = &mut self.debug_frame {
+			frame.draws.push(DrawCall { event_index: frame.dump.events.len(), ..draw });
+		}
+	}
+
+	// corners of a vram area, clockwise from the top left
+	fn log_area(&mut self, kind: DrawCallKind, x: u32, y: u32, width: u32, height: u32, colour: Colour) {
+		let (x, y) = (x as i32, y as i32);
+		let (right, bottom) = (x + width as i32 - 1, y + height as i32 - 1);
+
+		let corners = [(x, y), (right, y), (right, bottom), (x, bottom)]
+			.map(|(x, y)| Vertex { colour, ..Vertex::new(x, y) });
+
+		self.log_draw(kind, &corners, false, false, Vertex::default(), false);
+	}
+
+	// the commands that bring a reset gpu to the current register state
+	fn state_commands(&self) -> Vec<GpuDumpEvent> {
+		let draw_mode = (self.tex_page.x_base / 64)
+			| (self.tex_page.y_base / 256) << 4
+			| (self.tex_page.transp_type as u32) << 5
+			| (self.tex_page.bit_depth as u32) << 7
+			| (self.tex_page.dithering as u32) << 9
+			| (self.tex_page.allow_drawing_to_display_area as u32) << 10
+			| (self.tex_page.texture_disable as u32) << 11
+			| (self.tex_page.flip_x as u32) << 12
+			| (self.tex_page.flip_y as u32) << 13;
+
+		let tex_window = (self.tex_window.mask.x as u32 / 8)
+			| (self.tex_window.mask.y as u32 / 8) << 5
+			| (self.tex_window.offset.x as u32 / 8) << 10
+			| (self.tex_window.offset.y as u32 / 8) << 15;
+
+		let display_mode = (self.horizontal_res as u32)
+			| (self.vertical_res as u32) << 2
+			| (self.video_mode as u32) << 3
+			| (self.display_colour_depth as u32) << 4
+			| (self.vertical_interlace as u32) << 5
+			| (self.force_h368 as u32) << 6
+			| (self.flip_screen as u32) << 7;
+
+		let mut commands = vec![
+			GpuDumpEvent::Gp1(0x09000000 | self.texture_disable_allowed as u32),
+			GpuDumpEvent::Gp1(0x03000000 | !self.display_enabled as u32),
+			GpuDumpEvent::Gp1(0x04000000 | self.dma_direction as u32),
+			GpuDumpEvent::Gp1(0x05000000 | (self.display_start.x as u32) | (self.display_start.y as u32) << 10),
+			GpuDumpEvent::Gp1(0x06000000 | self.horizontal_display_range.0 | self.horizontal_display_range.1 << 12),
+			GpuDumpEvent::Gp1(0x07000000 | self.vertical_display_range.0 | self.vertical_display_range.1 << 10),
+			GpuDumpEvent::Gp1(0x08000000 | display_mode),
+			GpuDumpEvent::Gp0(0xE1000000 | draw_mode),
+			GpuDumpEvent::Gp0(0xE2000000 | tex_window),
+			GpuDumpEvent::Gp0(0xE3000000 | (self.draw_area_top_left.x as u32) | (self.draw_area_top_left.y as u32) << 10),
+			GpuDumpEvent::Gp0(0xE4000000 | (self.draw_area_bottom_right.x as u32) | (self.draw_area_bottom_right.y as u32) << 10),
+			GpuDumpEvent::Gp0(0xE5000000 | (self.drawing_offset.x as u32 & 0x7FF) | (self.drawing_offset.y as u32 & 0x7FF) << 11),
+			GpuDumpEvent::Gp0(0xE6000000 | self.force_mask_bit as u32 | (self.check_mask_bit as u32) << 1),
+		];
+
+		if self.irq {
+			commands.push(GpuDumpEvent::Gp0(0x1F000000));
+		}
+
+		commands
+	}
+
 	pub fn read32(&mut self, addr: u32) -> u32 {
 		match addr {
 			0x1F801810 => {
+				self.record(GpuDumpEvent::GpuRead);
+
 				if let Some(reg) = self.internal_reg {
 					self.reg_gpuread = reg;
 					self.internal_reg = None;
@@ -440,58 +932,81 @@ impl Gpu {
 	}
 
 	fn gpustat(&mut self) -> u32 {
+		// using the non-stubbed value seems to break more things
+		if !self.accurate_gpustat {
+			return 0x1C000000;
+		}
+
+		// cleared while a command still waits for parameters, polyline vertices or vram words
 		let ready_to_recv_cmd = matches!(self.gp0_state, GP0State::WaitingForNextCmd);
-		let ready_to_recv_vram = matches!(self.gp0_state, GP0State::WaitingForNextCmd | GP0State::SendData(..));
-		let ready_to_recv_dma = matches!(
-			self.gp0_state, 
-			GP0State::WaitingForNextCmd
-				| GP0State::RecvData(..)
-				| GP0State::SendData(..)
-		);
+		let ready_to_send_vram = matches!(self.gp0_state, GP0State::SendData(..));
+		let ready_to_recv_dma = self.ready_to_recv_dma();
 
-		//debug!("cmd: {ready_to_recv_cmd} vram: {ready_to_recv_vram} dma: {ready_to_recv_dma} state: {:?}", self.gp0_state);
+		let dma_request = self.dma_request() as u32;
 
-		let dma_request = match self.dma_direction {
-			DmaDirection::Off => 0,
-			DmaDirection::Fifo => 1,
-			DmaDirection::CpuToGp0 => ready_to_recv_dma as u32,
-			DmaDirection::GpureadToCpu => ready_to_recv_vram as u32,
-		};
+		let interlaced = self.vertical_interlace && matches!(self.vertical_res, VerticalRes::V480);
 
-		let _result = (self.tex_page.x_base)
-			| (self.tex_page.y_base) << 4
+		// always set when not interlaced
+		let interlace_field = !self.vertical_interlace || self.interlace_field;
+		// odd lines, only tracked per field
+		let odd_lines = interlaced && self.interlace_field;
+
+		let result = (self.tex_page.x_base / 64)
+			| (self.tex_page.y_base / 256) << 4
 			| (self.tex_page.transp_type as u32) << 5
 			| (self.tex_page.bit_depth as u32) << 7
 			| (self.tex_page.dithering as u32) << 9
 			| (self.tex_page.allow_drawing_to_display_area as u32) << 10
 			| (self.force_mask_bit as u32) << 11
 			| (self.check_mask_bit as u32) << 12
-			| (0) << 13 // TODO interlace field
+			| (interlace_field as u32) << 13
 			| (self.flip_screen as u32) << 14
+			| (self.tex_page.texture_disable as u32) << 15
 			| (self.force_h368 as u32) << 16
 			| (self.horizontal_res as u32) << 17
-			| (0) << 19
+			| (self.vertical_res as u32) << 19
 			| (self.video_mode as u32) << 20
 			| (self.display_colour_depth as u32) << 21
 			| (self.vertical_interlace as u32) << 22
-			| (self.display_enabled as u32) << 23
+			| (!self.display_enabled as u32) << 23
 			| (self.irq as u32) << 24
 			| (dma_request) << 25
 			| (ready_to_recv_cmd as u32) << 26
-			| (ready_to_recv_vram as u32) << 27 // DMA via GPUREAD
+			| (ready_to_send_vram as u32) << 27 // DMA via GPUREAD
 			| (ready_to_recv_dma as u32) << 28  // DMA via GP0
 			| (self.dma_direction as u32) << 29
-			| (0) << 31
-			| 0x1C000000; // TODO Drawing even/odd lines in interlace mode
+			| (odd_lines as u32) << 31;
+
+		trace!("gpustat: 0x{result:X}");
+
+		result
+	}
 
-		//debug!("gpustat: 0x{result:X}");
+	// GPUSTAT bit 28. commands run as soon as their last word arrives so the fifo never fills up,
+	// parameters and vram words are taken straight away. only a vram to cpu copy that wasn't
+	// read out yet keeps GP0 from taking more
+	fn ready_to_recv_dma(&self) -> bool {
+		!matches!(self.gp0_state, GP0State::SendData(..))
+	}
+
+	// GPUSTAT bit 25, DMA2 only moves data while it's set
+	pub fn dma_request(&self) -> bool {
+		match self.dma_direction {
+			DmaDirection::Off => false,
+			DmaDirection::Fifo | DmaDirection::CpuToGp0 => self.ready_to_recv_dma(),
+			DmaDirection::GpureadToCpu => matches!(self.gp0_state, GP0State::SendData(..)),
+		}
+	}
 
-		// TODO using the non-stubbed value seems to break more things
-		0x1C000000
-		//_result
+	// precise position of the vertex in the next GP0 word, if it came from the GTE
+	pub fn set_next_word_precise(&mut self, vertex: Option<PreciseVertex>) {
+		self.next_word_precise = vertex;
 	}
 
 	pub fn gp0_cmd(&mut self, word: u32) {
+		let precise = self.next_word_precise.take();
+
+		self.record(GpuDumpEvent::Gp0(word));
 
 		trace!("GP0: 0x{word:X} state: {:?}", self.gp0_state);
 
@@ -506,8 +1021,8 @@ impl Gpu {
 						GP0State::WaitingForNextCmd
 					},
 					0x01 => {
-						// not emulating the texture cache, this does nothing
 						trace!("clear texture cache");
+						self.tex_cache.invalidate();
 
 						GP0State::WaitingForNextCmd
 					},
@@ -521,7 +1036,7 @@ impl Gpu {
 						GP0State::WaitingForNextCmd
 					}
 
-					_ => todo!("Misc cmd 0x{word:X}")
+					_ => unreachable!()
 				}
 
 				// draw polygon
@@ -602,6 +1117,7 @@ impl Gpu {
 						self.tex_page.bit_depth = TexBitDepth::from_bits((word >> 7) & 3);
 						self.tex_page.dithering = (word >> 9) & 1 != 0;
 						self.tex_page.allow_drawing_to_display_area = (word >> 10) & 1 != 0;
+						self.tex_page.texture_disable = self.texture_disable_allowed && (word >> 11) & 1 != 0;
 						self.tex_page.flip_x = (word >> 12) & 1 != 0;
 						self.tex_page.flip_y = (word >> 13) & 1 != 0;
 
@@ -666,9 +1182,11 @@ impl Gpu {
 
 				if index == 0 {
 					self.gp0_params = vec![0; 16];
+					self.gp0_precise = vec![None; 16];
 				}
 
 				self.gp0_params[index as usize] = word;
+				self.gp0_precise[index as usize] = precise;
 				trace!("(write 0x{word:X}) words left {}", words_left - 1);
 
 				if words_left == 1 {
@@ -696,11 +1214,15 @@ impl Gpu {
 	}
 
 	fn gp1_cmd(&mut self, word: u32) {
+		self.record(GpuDumpEvent::Gp1(word));
+
 		self.gp1_state = match self.gp1_state {
-			GP1State::WaitingForNextCmd => match word >> 24 {
+			// 40h-FFh mirror 00h-3Fh
+			GP1State::WaitingForNextCmd => match (word >> 24) & 0x3F {
 				// Reset GPU
 				0x0 => {
 					trace!("reset gpu");
+					self.reset();
 
 					GP1State::WaitingForNextCmd
 				},
@@ -753,9 +1275,10 @@ impl Gpu {
 				},
 				// Vertical Display range (on screen)
 				0x7 => {
-					self.vertical_display_range = (word & 0xFFF, (word >> 12) & 0xFFF);
+					// 10 bit lines, unlike the 12 bit horizontal range
+					self.vertical_display_range = (word & 0x3FF, (word >> 10) & 0x3FF);
 
-					trace!("set horizontal display range: {:X?}", self.vertical_display_range);
+					trace!("set vertical display range: {:X?}", self.vertical_display_range);
 
 					GP1State::WaitingForNextCmd
 				},
@@ -767,35 +1290,67 @@ impl Gpu {
 					self.display_colour_depth = ColourDepth::from_bit((word >> 4) & 1 != 0);
 					self.vertical_interlace = (word >> 5) & 1 != 0;
 					self.force_h368 = (word >> 6) & 1 != 0;
-					self.flip_screen = (word >> 7) & 1 != 0;
+					// invalid on v2 gpus
+					self.flip_screen = self.model == GpuModel::V0 && (word >> 7) & 1 != 0;
 
 					trace!("set display mode");
 
 					GP1State::WaitingForNextCmd
 				},
+				// New Texture Disable, v2 only
+				0x9 => {
+					if self.model == GpuModel::V2 {
+						self.texture_disable_allowed = word & 1 != 0;
+					}
+
+					trace!("allow texture disable: {}", self.texture_disable_allowed);
+
+					GP1State::WaitingForNextCmd
+				},
 				// Read GPU internel register
-				0x10 | 0x11..=0x1F => {
-					let index = word & 7;
-
-					self.internal_reg = match index {
-						// nothing
-						0 ..= 1 => None,
-						// TODO texture window
-						2 => Some(0),
+				0x10..=0x1F => {
+					// v0 gpus only decode 3 bits of the index, 8-F mirror 0-7
+					let index = match self.model {
+						GpuModel::V0 => word & 7,
+						GpuModel::V2 => word & 0xF,
+					};
+
+					let reg = match index {
+						// texture window
+						2 => Some(
+							(self.tex_window.mask.x as u32 / 8)
+							| (self.tex_window.mask.y as u32 / 8) << 5
+							| (self.tex_window.offset.x as u32 / 8) << 10
+							| (self.tex_window.offset.y as u32 / 8) << 15
+						),
 						// draw area top left
 						3 => Some((self.draw_area_top_left.x as u32) | ((self.draw_area_top_left.y as u32) << 10)),
 						// draw area bottom right
 						4 => Some((self.draw_area_bottom_right.x as u32) | ((self.draw_area_bottom_right.y as u32) << 10)),
-						// TODO drawing offset
-						5 => Some(0),
-						// nothing on v0 gpus
-						6 ..= 7 => None,
-						_ => unreachable!(),
+						// drawing offset
+						5 => Some((self.drawing_offset.x as u32 & 0x7FF) | ((self.drawing_offset.y as u32 & 0x7FF) << 11)),
+						// gpu type
+						7 if self.model == GpuModel::V2 => Some(2),
+						// unknown, always 0
+						8 if self.model == GpuModel::V2 => Some(0),
+						// nothing, GPUREAD keeps its old value
+						_ => None,
 					};
 
+					if reg.is_some() {
+						self.internal_reg = reg;
+					}
+
+					trace!("read gpu info {index}: {reg:X?}");
+
 					GP1State::WaitingForNextCmd
 				}
-				_ => unimplemented!("unimplemented GP1 command: 0x{:X}", word >> 24),
+				// 0Ah-0Fh and 20h-3Fh are unused or only exist on prototype / arcade gpus
+				_ => {
+					debug!("unused GP1 command: 0x{:X}", word >> 24);
+
+					GP1State::WaitingForNextCmd
+				},
 			}
 		}
 	}
@@ -820,6 +1375,66 @@ impl Gpu {
 		(width, height)
 	}
 
+	// where the beam draws a pixel of the displayed picture, video clock ticks from hsync and
+	// scanlines from vsync. None outside the picture, which is what a light gun sees
+	pub fn beam_position(&self, x: f32, y: f32) -> Option<(u32, u32)> {
+		let (width, height) = self.get_display_res();
+
+		if !self.display_enabled || x < 0.0 || y < 0.0 || x >= width as f32 || y >= height as f32 {
+			return None;
+		}
+
+		// both fields of an interlaced picture are drawn on the same lines
+		let line = match self.vertical_res {
+			VerticalRes::V480 if self.vertical_interlace => y as u32 / 2,
+			_ => y as u32,
+		};
+
+		Some((self.horizontal_display_range.0 + (x * self.dot_clock_divider() as f32) as u32, self.vertical_display_range.0 + line))
+	}
+
+	// video clock ticks per pixel
+	fn dot_clock_divider(&self) -> u32 {
+		if self.force_h368 {
+			7
+		} else {
+			match self.horizontal_res {
+				HorizontalRes::H256 => 10,
+				HorizontalRes::H320 => 8,
+				HorizontalRes::H512 => 5,
+				HorizontalRes::H640 => 4,
+			}
+		}
+	}
+
+	// pixels actually shown, from the GP1(06h)/GP1(07h) display range. games shorten or widen the
+	// picture with it, the resolution mode only sets the dot clock. the width is rounded to 4 pixels
+	pub fn get_display_area(&self) -> (usize, usize) {
+		let (x1, x2) = self.horizontal_display_range;
+		let (y1, y2) = self.vertical_display_range;
+
+		let width = ((x2.saturating_sub(x1) / self.dot_clock_divider() + 2) & !3) as usize;
+		let lines = y2.saturating_sub(y1) as usize;
+
+		// both fields together
+		let height = match self.vertical_res {
+			VerticalRes::V480 if self.vertical_interlace => lines * 2,
+			_ => lines,
+		};
+
+		match width == 0 || height == 0 {
+			true => self.get_display_res(),
+			false => (width, height),
+		}
+	}
+
+	pub fn video_clock(&self) -> u64 {
+		match self.video_mode {
+			VideoMode::Ntsc => 53_693_175,
+			VideoMode::Pal => 53_203_425,
+		}
+	}
+
 	pub fn is_display_24bit(&self) -> bool {
 		if self.display_colour_depth == ColourDepth::TwentyFourBit {
 			true
@@ -832,6 +1447,63 @@ impl Gpu {
 		(self.display_start.x as usize, self.display_start.y as usize)
 	}
 
+	// the displayed area of vram as 24 bit rgb at native resolution, black while the display is off
+	pub fn get_display_frame(&self) -> (usize, usize, Vec<u8>) {
+		let (width, height) = self.get_display_area();
+		let (start_x, start_y) = self.get_display_start();
+
+		let mut frame = vec![0; width * height * 3];
+
+		if !self.display_enabled {
+			return (width, height, frame);
+		}
+
+		let vram_at = |x: usize, y: usize| self.vram[1024 * (y & 0x1FF) + (x & 0x3FF)];
+
+		for y in 0..height {
+			for x in 0..width {
+				let rgb = if self.is_display_24bit() {
+					// 2 pixels every 3 halfwords
+					let addr = start_x + x * 3 / 2;
+					let (first, second) = (vram_at(addr, start_y + y), vram_at(addr + 1, start_y + y));
+
+					if x % 2 == 0 {
+						[first as u8, (first >> 8) as u8, second as u8]
+					} else {
+						[(first >> 8) as u8, second as u8, (second >> 8) as u8]
+					}
+				} else {
+					let pixel = vram_at(start_x + x, start_y + y);
+
+					[((pixel & 0x1F) << 3) as u8, (((pixel >> 5) & 0x1F) << 3) as u8, (((pixel >> 10) & 0x1F) << 3) as u8]
+				};
+
+				frame[(y * width + x) * 3..][..3].copy_from_slice(&rgb);
+			}
+		}
+
+		(width, height, frame)
+	}
+
+	// top left and bottom right, inclusive
+	pub fn get_draw_area(&self) -> ((usize, usize), (usize, usize)) {
+		(
+			(self.draw_area_top_left.x as usize, self.draw_area_top_left.y as usize),
+			(self.draw_area_bottom_right.x as usize, self.draw_area_bottom_right.y as usize),
+		)
+	}
+
+	// x, y and bits per texel of the active texture page
+	pub fn get_texture_page(&self) -> (usize, usize, usize) {
+		let depth = match self.tex_page.bit_depth {
+			TexBitDepth::FourBit => 4,
+			TexBitDepth::EightBit => 8,
+			TexBitDepth::FiveteenBit => 16,
+		};
+
+		(self.tex_page.x_base as usize, self.tex_page.y_base as usize, depth)
+	}
+
 	pub fn get_dotclock_divider(&self) -> u64 {
 		if self.force_h368 {
 			7
@@ -871,6 +1543,8 @@ impl Gpu {
 				trace!("Start VRAM->CPU DMA");
 				let info = self.init_dma();
 
+				self.log_area(DrawCallKind::VramToCpu, info.dest_x.into(), info.dest_y.into(), info.width.into(), info.height.into(), Colour::default());
+
 				GP0State::SendData(info)
 			},
 			DrawCommand::VramVramDma => {
@@ -898,6 +1572,7 @@ impl Gpu {
 					v1.colour = cmd.colour;
 				}
 
+				self.log_draw(DrawCallKind::Line, &[v0, v1], false, false, Vertex::default(), cmd.semi_transparent);
 				self.draw_line(v0, v1, cmd.semi_transparent);
 
 				GP0State::WaitingForNextCmd
@@ -957,12 +1632,17 @@ impl Gpu {
 			// wrap from 1023 to 0
 			let vram_col = ((info.dest_x + info.current_col) & 0x3FF) as u32;
 
-			self.draw_pixel_15bit(halfword, vram_col, vram_row, false, false);
+			if self.draw_pixel_15bit(halfword, vram_col, vram_row, false, false) && self.resolution_scale > 1 {
+				let colour = self.vram[coord_to_vram_index(vram_col, vram_row) as usize];
+				self.fill_hires_block(vram_col, vram_row, colour);
+			}
 
 			info.current_col += 1;
 			info.halfwords_left -= 1;
 
 			if info.halfwords_left == 0 {
+				self.log_area(DrawCallKind::CpuToVram, info.dest_x.into(), info.dest_y.into(), info.width.into(), info.height.into(), Colour::default());
+
 				return GP0State::WaitingForNextCmd;
 			}
 
@@ -1033,6 +1713,8 @@ impl Gpu {
 
 		trace!("[VRAM-VRAM DMA] src: (0x{src_x:X}, 0x{src_y:X}) dest: (0x{dest_x:X}, 0x{dest_y:X}) size: ({width}, {height})");
 
+		self.log_area(DrawCallKind::VramCopy, dest_x.into(), dest_y.into(), width.into(), height.into(), Colour::default());
+
 		for y_offset in 0..height {
 			for x_offset in 0..width {
 				let src_addr = coord_to_vram_index(((src_x + x_offset) & 0x3FF) as u32, ((src_y + y_offset) & 0x1FF) as u32) as usize;
@@ -1040,6 +1722,13 @@ impl Gpu {
 				let src = self.vram[src_addr];
 				//trace!("[VRAM-VRAM DMA] draw pixel 0x{src:X} at ({}, {})", (dest_x + x_offset) as u32, (dest_y + y_offset) as u32);
 				self.draw_pixel_15bit(src, ((dest_x + x_offset) & 0x3FF) as u32, ((dest_y + y_offset) & 0x1FF) as u32, false, false);
+
+				if self.resolution_scale > 1 {
+					self.copy_hires_block(
+						((src_x + x_offset) & 0x3FF) as u32, ((src_y + y_offset) & 0x1FF) as u32,
+						((dest_x + x_offset) & 0x3FF) as u32, ((dest_y + y_offset) & 0x1FF) as u32
+					);
+				}
 			}
 		}
 	}
@@ -1056,6 +1745,8 @@ impl Gpu {
 				})
 				.collect();
 
+			self.log_draw(DrawCallKind::Polyline, &verts, false, false, Vertex::default(), params.semi_transparent);
+
 			verts
 				.windows(2)
 				.map(|v| (&v[0], &v[1]))
@@ -1069,6 +1760,8 @@ impl Gpu {
 			})
 			.collect();
 
+			self.log_draw(DrawCallKind::Polyline, &verts, false, false, Vertex::default(), params.semi_transparent);
+
 			verts
 				.windows(2)
 				.map(|v| (&v[0], &v[1]))
@@ -1081,6 +1774,17 @@ impl Gpu {
 			return;
 		}
 
+		self.rasterize_line(v0, v1, semi_transparent, 1);
+
+		if self.resolution_scale > 1 {
+			let scale = self.resolution_scale as i32;
+			self.rasterize_line(v0.scaled(scale), v1.scaled(scale), semi_transparent, scale);
+		}
+	}
+
+	fn rasterize_line(&mut self, v0: Vertex, v1: Vertex, semi_transparent: bool, scale: i32) {
+		let (area_min, area_max) = self.scaled_draw_area(scale);
+
 		let dx = v1.x - v0.x;
 		let dy = v1.y - v0.y;
 
@@ -1118,14 +1822,13 @@ impl Gpu {
 			let mut colour = Colour::from_rgb888(r as u8, g as u8, b as u8);
 
 			// ensure pixel is within the drawing area
-			if (self.draw_area_top_left.x..=self.draw_area_bottom_right.x).contains(&vertex.x)
-            	&& (self.draw_area_top_left.y..=self.draw_area_bottom_right.y).contains(&vertex.y) {
+			if (area_min.x..=area_max.x).contains(&vertex.x) && (area_min.y..=area_max.y).contains(&vertex.y) {
 
 				if self.tex_page.dithering {
-					colour = apply_dithering(colour, vertex)
+					colour = apply_dithering(colour, vertex.unscaled(scale))
 				}
 
-				self.draw_pixel_15bit(colour.truncate_to_15bit(), vertex.x as u32, vertex.y as u32, semi_transparent, false);
+				self.write_pixel(vertex.x, vertex.y, scale, ShadedPixel { colour: colour.truncate_to_15bit(), semi_transparent, mask_bit: false });
 
 			}
 
@@ -1160,47 +1863,74 @@ impl Gpu {
 			RectSize::Sprite16x16 => Vertex::new(16, 16),
 		};
 
-		let min_x = cmd.position.x + self.drawing_offset.x;
-		let max_x = cmd.position.x + self.drawing_offset.x + cmd.size.x - 1;
-		let min_y = cmd.position.y + self.drawing_offset.y;
-		let max_y = cmd.position.y + self.drawing_offset.y + cmd.size.y - 1;
+		cmd.textured &= !self.tex_page.texture_disable;
 
-		for y in min_y..=max_y {
+		let (x, y) = (cmd.position.x + self.drawing_offset.x, cmd.position.y + self.drawing_offset.y);
+		let (right, bottom) = (cmd.size.x - 1, cmd.size.y - 1);
 
-			if y < self.draw_area_top_left.y || y > self.draw_area_bottom_right.y {
-            	continue;
-        	}
+		let corners = [(0, 0), (right, 0), (right, bottom), (0, bottom)].map(|(dx, dy)| Vertex {
+			tex_x: cmd.position.tex_x + dx,
+			tex_y: cmd.position.tex_y + dy,
+			colour: cmd.colour,
+			..Vertex::new(x + dx, y + dy)
+		});
 
-			for x in min_x..=max_x {
+		self.log_draw(DrawCallKind::Rect, &corners, cmd.textured, cmd.raw_texture, cmd.clut, cmd.semi_transparent);
 
-				if x < self.draw_area_top_left.x || x > self.draw_area_bottom_right.x {
-                	continue;
-            	}
+		if cmd.textured {
+			self.load_clut_cache(cmd.clut);
+		}
 
-				let (draw_colour, mask_bit) = if cmd.textured {
-					let tex_colour_u16 = self.sample_texture(Vertex::new(cmd.position.tex_x + (x - min_x), cmd.position.tex_y + (y - min_y)), cmd.clut);
-					let tex_colour =  Colour::rgb555_to_rgb888(tex_colour_u16);
+		// the upscaled pass goes first so it samples the same texels as the native pass
+		if self.resolution_scale > 1 {
+			self.rasterize_rect(&cmd, self.resolution_scale as i32);
+		}
 
-					if tex_colour_u16 == 0 {
-						continue;
-					}
+		self.rasterize_rect(&cmd, 1);
+	}
 
-					let mask_bit = tex_colour_u16 & 0x8000 != 0;
+	fn rasterize_rect(&mut self, cmd: &RectCmdParams, scale: i32) {
+		let min_x = (cmd.position.x + self.drawing_offset.x) * scale;
+		let max_x = min_x + cmd.size.x * scale - 1;
+		let min_y = (cmd.position.y + self.drawing_offset.y) * scale;
+		let max_y = min_y + cmd.size.y * scale - 1;
 
-					if !cmd.raw_texture {
-						(apply_modulation(tex_colour, cmd.colour), mask_bit)
-					} else {
-						(tex_colour, mask_bit)
-					}
-				} else {
-					(cmd.colour, false)
-				};
+		// constrain to drawing area
+		let (area_min, area_max) = self.scaled_draw_area(scale);
+
+		let min = Vertex::new(cmp::max(min_x, area_min.x), cmp::max(min_y, area_min.y));
+		let max = Vertex::new(cmp::min(max_x, area_max.x), cmp::min(max_y, area_max.y));
 
-				let semi_transparent = cmd.semi_transparent && (!cmd.textured || mask_bit);
+		// texture coords aren't wrapped for rects, they can reach past the texture page
+		let texture = cmd.textured.then(|| (Vertex::new(cmd.position.tex_x + cmd.size.x, cmd.position.tex_y + cmd.size.y), cmd.clut));
+
+		self.rasterize(min, max, scale, texture, |gpu, x, y| gpu.shade_rect_pixel(x, y, min_x, min_y, cmd, scale));
+	}
 
-				self.draw_pixel_15bit(u16::from(draw_colour.truncate_to_15bit()), x as u32, y as u32, semi_transparent, mask_bit);
+	fn shade_rect_pixel(&self, x: i32, y: i32, min_x: i32, min_y: i32, cmd: &RectCmdParams, scale: i32) -> Option<ShadedPixel> {
+		let (draw_colour, mask_bit) = if cmd.textured {
+			let tex_coords = Vertex::new(cmd.position.tex_x + (x - min_x) / scale, cmd.position.tex_y + (y - min_y) / scale);
+			let tex_colour_u16 = self.sample_texture(tex_coords, cmd.clut);
+			let tex_colour =  Colour::rgb555_to_rgb888(tex_colour_u16);
+
+			if tex_colour_u16 == 0 {
+				return None;
 			}
-		}
+
+			let mask_bit = tex_colour_u16 & 0x8000 != 0;
+
+			if !cmd.raw_texture {
+				(apply_modulation(tex_colour, cmd.colour), mask_bit)
+			} else {
+				(tex_colour, mask_bit)
+			}
+		} else {
+			(cmd.colour, false)
+		};
+
+		let semi_transparent = cmd.semi_transparent && (!cmd.textured || mask_bit);
+
+		Some(ShadedPixel { colour: draw_colour.truncate_to_15bit(), semi_transparent, mask_bit })
 	}
 
 	fn quick_fill(&mut self, cmd: u32) {
@@ -1219,12 +1949,20 @@ impl Gpu {
 
 		//debug!("quick fill at ({x}, {y}) of size ({width}, {height}) cmd: ${cmd:X} r:{r} g:{g} b{b} colour: ${colour:X}");
 
+		if width != 0 && height != 0 {
+			self.log_area(DrawCallKind::QuickFill, x, y, width, height, Colour::from_packet(cmd));
+		}
+
 		for y_offset in 0..height {
 			for x_offset in 0..width {
 				let index = coord_to_vram_index((x + x_offset) & 0x3FF, (y + y_offset) & 0x1FF);
 
 				// quick fill doesn't check mask bit
 				self.vram[index as usize] = colour;
+
+				if self.resolution_scale > 1 {
+					self.fill_hires_block((x + x_offset) & 0x3FF, (y + y_offset) & 0x1FF, colour);
+				}
 			}
 		}
 
@@ -1232,18 +1970,31 @@ impl Gpu {
 
 	fn draw_polygon(&mut self, mut cmd: PolygonCmdParams) {
 		let mut v = [Vertex::default(); 4];
+		let mut precise = self.gp0_precise.drain(..);
 
 		for i in 0..cmd.vertices as usize {
 			if i != 0 && cmd.shaded {
 				v[i].colour = Colour::from_packet(self.gp0_params.remove(0));
+				precise.next();
 			}
 			
-			let vertex = Vertex::from_packet(self.gp0_params.remove(0)).plus_offset(self.drawing_offset);
+			let raw_vertex = Vertex::from_packet(self.gp0_params.remove(0));
+			let vertex = raw_vertex.plus_offset(self.drawing_offset);
 			v[i].x = vertex.x;
 			v[i].y = vertex.y;
 
+			// the cpu may have moved the vertex after the GTE projected it
+			v[i].precise = precise.next().flatten()
+				.filter(|p| p.x.floor() as i32 == raw_vertex.x && p.y.floor() as i32 == raw_vertex.y)
+				.map(|p| PreciseVertex {
+					x: p.x + f64::from(self.drawing_offset.x),
+					y: p.y + f64::from(self.drawing_offset.y),
+					z: p.z,
+				});
+
 			if cmd.textured {
 				let tex_word = self.gp0_params.remove(0);
+				precise.next();
 
 				match i {
 					0 => cmd.clut = Vertex::new((((tex_word >> 16) & 0x3F) as u16 as i32) * 16, ((tex_word >> 22) & 0x1FF) as u16 as i32),
@@ -1254,6 +2005,7 @@ impl Gpu {
 							x_base: 64 * (tex_page & 0xF),
 							y_base: 256 * ((tex_page >> 4) & 1),
 							bit_depth: TexBitDepth::from_bits((tex_page >> 7) & 3),
+							texture_disable: self.texture_disable_allowed && (tex_page >> 11) & 1 != 0,
 							..self.tex_page
 						};
 					},
@@ -1266,7 +2018,14 @@ impl Gpu {
 
 		}
 
+		drop(precise);
+		self.gp0_precise = vec![None; 16];
+
+		// textured polygons are drawn with their colour alone
+		cmd.textured &= !self.tex_page.texture_disable;
+
 		v[0].colour = cmd.colour;
+		self.log_draw(DrawCallKind::Polygon, &v[..cmd.vertices as usize], cmd.textured, cmd.raw_texture, cmd.clut, cmd.semi_transparent);
 
 		ensure_vertex_order(&mut v);
 		self.draw_triangle(v[0], v[1], v[2], cmd);
@@ -1280,81 +2039,214 @@ impl Gpu {
 	}
 
 	fn draw_triangle(&mut self, v0: Vertex, v1: Vertex, v2: Vertex, cmd: PolygonCmdParams) {
+		if !vertices_valid(v0, v1) || !vertices_valid(v1, v2) || !vertices_valid(v2, v0) {
+			return;
+		}
+
+		if cmd.textured {
+			self.load_clut_cache(cmd.clut);
+		}
+
+		// the upscaled pass goes first so it samples the same texels as the native pass
+		if self.resolution_scale > 1 {
+			let scale = self.resolution_scale as i32;
+
+			// precise positions can flip the winding of very thin triangles
+			let mut scaled = [v0.scaled(scale), v1.scaled(scale), v2.scaled(scale)];
+			ensure_vertex_order(&mut scaled);
+
+			self.rasterize_triangle(scaled[0], scaled[1], scaled[2], &cmd, scale);
+		}
+
+		self.rasterize_triangle(v0, v1, v2, &cmd, 1);
+	}
+
+	fn rasterize_triangle(&mut self, v0: Vertex, v1: Vertex, v2: Vertex, cmd: &PolygonCmdParams, scale: i32) {
 		// compute polygon bounding box
-		let mut min_x = cmp::min(v0.x, cmp::min(v1.x, v2.x));
-		let mut max_x = cmp::max(v0.x, cmp::max(v1.x, v2.x));
-		let mut min_y = cmp::min(v0.y, cmp::min(v1.y, v2.y));
-		let mut max_y = cmp::max(v0.y, cmp::max(v1.y, v2.y));
+		let min_x = cmp::min(v0.x, cmp::min(v1.x, v2.x));
+		let max_x = cmp::max(v0.x, cmp::max(v1.x, v2.x));
+		let min_y = cmp::min(v0.y, cmp::min(v1.y, v2.y));
+		let max_y = cmp::max(v0.y, cmp::max(v1.y, v2.y));
 
 		// constrain bounding box to drawing area
-		min_x = cmp::max(min_x, self.draw_area_top_left.x);
-		max_x = cmp::min(max_x, self.draw_area_bottom_right.x);
-		min_y = cmp::max(min_y, self.draw_area_top_left.y);
-		max_y = cmp::min(max_y, self.draw_area_bottom_right.y);
+		let (area_min, area_max) = self.scaled_draw_area(scale);
+
+		let min = Vertex::new(cmp::max(min_x, area_min.x), cmp::max(min_y, area_min.y));
+		let max = Vertex::new(cmp::min(max_x, area_max.x), cmp::min(max_y, area_max.y));
+
+		let texture = cmd.textured.then(|| (Vertex::new(256, 256), cmd.clut));
 
-		if min_x > max_x || min_y > max_y {
-			//return;
+		self.rasterize(min, max, scale, texture, |gpu, x, y| gpu.shade_triangle_pixel(Vertex::new(x, y), v0, v1, v2, cmd, scale));
+	}
+
+	fn shade_triangle_pixel(&self, p: Vertex, v0: Vertex, v1: Vertex, v2: Vertex, cmd: &PolygonCmdParams, scale: i32) -> Option<ShadedPixel> {
+		if !is_inside_triangle(p, v0, v1, v2) {
+			return None;
 		}
 
-		if !vertices_valid(v0, v1) || !vertices_valid(v1, v2) || !vertices_valid(v2, v0) {
+		let shaded_colour = if cmd.shaded {
+			let coords = compute_barycentric_coords(p, v0, v1, v2);
+			interpolate_colour(coords, [v0.colour, v1.colour, v2.colour])
+		} else {
+			cmd.colour
+		};
+
+		let (textured_colour, mask_bit) = if cmd.textured {
+			
+			let coords = compute_barycentric_coords(p, v0, v1, v2);
+
+			let interpolated_coords = match (v0.precise, v1.precise, v2.precise) {
+				(Some(p0), Some(p1), Some(p2)) => interpolate_uv_perspective(coords, [v0, v1, v2], [p0.z, p1.z, p2.z]),
+				_ => interpolate_uv(coords, [v0, v1, v2]),
+			};
+
+			let tex_colour_u16 = self.sample_texture(interpolated_coords, cmd.clut);
+			let tex_colour = Colour::rgb555_to_rgb888(tex_colour_u16);
+
+			// black is transparent in textures
+			if tex_colour_u16 == 0 {
+				return None;
+			}
+
+			let final_tex_colour = match cmd.raw_texture {
+				true => tex_colour,
+				false => apply_modulation(tex_colour, shaded_colour),
+			};
+
+			(final_tex_colour, tex_colour_u16 & 0x8000 != 0)
+
+		} else {
+			(shaded_colour, false)
+		};
+
+		// dithering is applied on gourad shaded polygons and modulated texture polygons
+		let dithered_colour = if self.tex_page.dithering && (cmd.shaded || (cmd.textured && !cmd.raw_texture)) {
+			apply_dithering(textured_colour, p.unscaled(scale))
+		} else {
+			textured_colour
+		};
+
+		let semi_transparent = cmd.semi_transparent && (!cmd.textured || mask_bit);
+
+		Some(ShadedPixel { colour: dithered_colour.truncate_to_15bit(), semi_transparent, mask_bit })
+	}
+
+	// shades every pixel in [min, max] and writes it to vram, or to the upscaled vram when scale > 1
+	fn rasterize<F>(&mut self, min: Vertex, max: Vertex, scale: i32, texture: Option<(Vertex, Vertex)>, shade: F)
+	where
+		F: Fn(&Gpu, i32, i32) -> Option<ShadedPixel> + Sync
+	{
+		if min.x > max.x || min.y > max.y {
 			return;
 		}
 
-		for y in min_y..=max_y {
-			for x in min_x..=max_x {
+		// textures are always sampled from native vram, the upscaled pass can't read its own output
+		let texture = if scale == 1 || self.tex_cache.enabled { texture } else { None };
 
-				let p = Vertex::new(x, y);
+		if self.use_parallel_path(min, max, texture) {
+			self.rasterize_parallel(min, max, scale, shade);
+		} else {
+			let saved_cache = (scale != 1 && texture.is_some() && self.tex_cache.enabled).then(|| self.tex_cache.save());
 
-				if is_inside_triangle(p, v0, v1, v2) {
-					let shaded_colour = if cmd.shaded {
-						let coords = compute_barycentric_coords(p, v0, v1, v2);
-						let colour = interpolate_colour(coords, [v0.colour, v1.colour, v2.colour]);
+			for y in min.y..=max.y {
+				for x in min.x..=max.x {
+					if let Some(pixel) = shade(self, x, y) {
+						self.write_pixel(x, y, scale, pixel);
+					}
+				}
+			}
 
-						colour
-					} else {
-						cmd.colour
-					};
+			if let Some(saved_cache) = saved_cache {
+				self.tex_cache.restore(saved_cache);
+			}
+		}
+	}
 
-					let (textured_colour, mask_bit) = if cmd.textured {
-						
-						let coords = compute_barycentric_coords(p, v0, v1, v2);
-						let interpolated_coords = interpolate_uv(coords, [v0, v1, v2]);
+	// texture is the extent of the texture coords used and the clut position
+	fn use_parallel_path(&self, min: Vertex, max: Vertex, texture: Option<(Vertex, Vertex)>) -> bool {
+		if self.render_threads < 2 || (max.x - min.x + 1) * (max.y - min.y + 1) < PARALLEL_MIN_PIXELS {
+			return false;
+		}
 
-						let tex_colour_u16 = self.sample_texture(interpolated_coords, cmd.clut);
-						let tex_colour = Colour::rgb555_to_rgb888(tex_colour_u16);
+		// a primitive sampling texels it draws over depends on its own output,
+		// only the serial path reproduces that ordering
+		match texture {
+			// loading cache lines depends on the order texels are sampled in
+			Some(_) if self.tex_cache.enabled => false,
+			Some((extent, clut)) => !self.texture_overlaps(min, max, extent, clut),
+			None => true,
+		}
+	}
 
-						// black is transparent in textures
-						if tex_colour_u16 == 0 {
-							continue;
-						}
+	fn texture_overlaps(&self, min: Vertex, max: Vertex, extent: Vertex, clut: Vertex) -> bool {
+		// the texture window can still set the low 8 bits of the coords
+		let extent = Vertex::new(cmp::max(extent.x, 256), cmp::max(extent.y, 256));
 
-						let final_tex_colour = match cmd.raw_texture {
-							true => tex_colour,
-							false => apply_modulation(tex_colour, shaded_colour),
-						};
+		let (texels_per_halfword, clut_width) = match self.tex_page.bit_depth {
+			TexBitDepth::FourBit => (4, 16),
+			TexBitDepth::EightBit => (2, 256),
+			TexBitDepth::FiveteenBit => (1, 0),
+		};
 
-						(final_tex_colour, tex_colour_u16 & 0x8000 != 0)
+		let page_width = (extent.x + texels_per_halfword - 1) / texels_per_halfword;
 
-					} else {
-						(shaded_colour, false)
-					};
+		let page_overlaps = ranges_overlap(self.tex_page.x_base as i32, page_width, 1024, min.x, max.x)
+			&& ranges_overlap(self.tex_page.y_base as i32, extent.y, 512, min.y, max.y);
 
-					// dithering is applied on gourad shaded polygons and modulated texture polygons
-					let dithered_colour = if self.tex_page.dithering && (cmd.shaded || (cmd.textured && !cmd.raw_texture)) {
-						apply_dithering(textured_colour, p)
-					} else {
-						textured_colour
-					};
+		let clut_overlaps = clut_width != 0
+			&& ranges_overlap(clut.x, clut_width, 1024, min.x, max.x)
+			&& ranges_overlap(clut.y, 1, 512, min.y, max.y);
 
-					let semi_transparent = cmd.semi_transparent && (!cmd.textured || mask_bit);
+		page_overlaps || clut_overlaps
+	}
 
-					self.draw_pixel_15bit(dithered_colour.truncate_to_15bit(), x as u32, y as u32, semi_transparent, mask_bit);
-				}
+	// shades the area in horizontal bands on worker threads, then writes the results in the
+	// same order as the serial path so the output is identical
+	fn rasterize_parallel<F>(&mut self, min: Vertex, max: Vertex, scale: i32, shade: F)
+	where
+		F: Fn(&Gpu, i32, i32) -> Option<ShadedPixel> + Sync
+	{
+		let (min_x, max_x, min_y, max_y) = (min.x, max.x, min.y, max.y);
+		let rows = max_y - min_y + 1;
+		let threads = cmp::min(self.render_threads as i32, rows);
+		let band_height = (rows + threads - 1) / threads;
+
+		let bands: Vec<Vec<Option<ShadedPixel>>> = std::thread::scope(|s| {
+			let gpu: &Gpu = self;
+			let shade = &shade;
+
+			let workers: Vec<_> = (0..threads)
+				.map(|band| {
+					let start_y = min_y + band * band_height;
+					let end_y = cmp::min(start_y + band_height - 1, max_y);
+
+					s.spawn(move || {
+						let mut pixels = Vec::with_capacity(((end_y - start_y + 1) * (max_x - min_x + 1)) as usize);
+
+						for y in start_y..=end_y {
+							for x in min_x..=max_x {
+								pixels.push(shade(gpu, x, y));
+							}
+						}
+
+						pixels
+					})
+				})
+				.collect();
+
+			workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+		});
+
+		let coords = (min_y..=max_y).flat_map(|y| (min_x..=max_x).map(move |x| (x, y)));
+
+		for ((x, y), pixel) in coords.zip(bands.into_iter().flatten()) {
+			if let Some(pixel) = pixel {
+				self.write_pixel(x, y, scale, pixel);
 			}
 		}
 	}
 
-	fn sample_texture(&mut self, tex_coords: Vertex, clut: Vertex) -> u16 {
+	fn sample_texture(&self, tex_coords: Vertex, clut: Vertex) -> u16 {
 
 		let masked_coords = Vertex::new(
 			tex_coords.x & (!(self.tex_window.mask.x)) | (self.tex_window.offset.x & self.tex_window.mask.x),
@@ -1366,47 +2258,159 @@ impl Gpu {
 				let tex_x = self.tex_page.x_base + ((masked_coords.x as u32) / 4);
 				let tex_y = self.tex_page.y_base + masked_coords.y as u32;
 
-				let texel = self.vram[coord_to_vram_index(tex_x, tex_y) as usize];
+				let texel = self.read_texel(tex_x, tex_y, masked_coords);
 				let clut_index = ((texel >> (masked_coords.x % 4) * 4) & 0xF) as u32;
 
-				self.vram[coord_to_vram_index(clut.x as u32 + clut_index, clut.y as u32) as usize]
+				self.read_clut(clut, clut_index)
 			},
 			TexBitDepth::EightBit => {
 				let tex_x = self.tex_page.x_base + ((masked_coords.x as u32) / 2);
 				let tex_y = self.tex_page.y_base + masked_coords.y as u32;
 
-				let texel = self.vram[coord_to_vram_index(tex_x, tex_y) as usize];
+				let texel = self.read_texel(tex_x, tex_y, masked_coords);
 				let clut_index = ((texel >> (masked_coords.x % 2) * 8) & 0xFF) as u32;
 
-				self.vram[coord_to_vram_index(clut.x as u32 + clut_index, clut.y as u32) as usize]
+				self.read_clut(clut, clut_index)
 			}
 			TexBitDepth::FiveteenBit => {
 				let tex_x = self.tex_page.x_base + (masked_coords.x as u32);
 				let tex_y = self.tex_page.y_base + (masked_coords.y as u32);
-				let texel = self.vram[coord_to_vram_index(tex_x, tex_y) as usize];
-
-				texel
+				self.read_texel(tex_x, tex_y, masked_coords)
 			},
 		}
 
 	}
 
-	fn draw_pixel_15bit(&mut self, colour: u16, x: u32, y: u32, semi_transparent: bool, mask_bit: bool) {
-		let mask = u16::from(mask_bit || self.force_mask_bit) << 15;
+	// masked_coords are the texel coords inside the texture page, they pick the cache line
+	fn read_texel(&self, tex_x: u32, tex_y: u32, masked_coords: Vertex) -> u16 {
+		if !self.tex_cache.enabled {
+			return self.vram[coord_to_vram_index(tex_x, tex_y) as usize];
+		}
 
-		let old_pixel = self.vram[coord_to_vram_index(x, y) as usize];
+		let (u, v) = (masked_coords.x as usize, masked_coords.y as usize);
 
-		if self.check_mask_bit && old_pixel & 0x8000 != 0 {
+		let line_index = match self.tex_page.bit_depth {
+			TexBitDepth::FourBit => ((v & 0x3F) << 2) | ((u >> 4) & 3),
+			TexBitDepth::EightBit => ((v & 0x1F) << 3) | ((u >> 3) & 7),
+			TexBitDepth::FiveteenBit => ((v & 0x1F) << 3) | ((u >> 2) & 7),
+		};
+
+		self.tex_cache.read(&self.vram, tex_x, tex_y, line_index, self.tex_page.bit_depth)
+	}
+
+	fn read_clut(&self, clut: Vertex, index: u32) -> u16 {
+		if self.tex_cache.enabled {
+			self.tex_cache.clut[index as usize]
+		} else {
+			self.vram[coord_to_vram_index(clut.x as u32 + index, clut.y as u32) as usize]
+		}
+	}
+
+	fn load_clut_cache(&mut self, clut: Vertex) {
+		let bit_depth = self.tex_page.bit_depth;
+
+		let entries = match bit_depth {
+			TexBitDepth::FourBit => 16,
+			TexBitDepth::EightBit => 256,
+			TexBitDepth::FiveteenBit => return,
+		};
+
+		// an 8bit clut already holds the 16 entries of a 4bit one at the same position
+		let cached = match self.tex_cache.clut_tag {
+			Some((x, y, depth)) => x == clut.x && y == clut.y && (depth == TexBitDepth::EightBit || bit_depth == TexBitDepth::FourBit),
+			None => false,
+		};
+
+		if !self.tex_cache.enabled || cached {
 			return;
 		}
 
+		for i in 0..entries {
+			self.tex_cache.clut[i as usize] = self.vram[coord_to_vram_index(clut.x as u32 + i, clut.y as u32) as usize];
+		}
+
+		self.tex_cache.clut_tag = Some((clut.x, clut.y, bit_depth));
+	}
+
+	fn write_pixel(&mut self, x: i32, y: i32, scale: i32, pixel: ShadedPixel) {
+		if scale == 1 {
+			self.draw_pixel_15bit(pixel.colour, x as u32, y as u32, pixel.semi_transparent, pixel.mask_bit);
+		} else {
+			let index = self.hires_index(x as u32, y as u32);
+
+			if let Some(new_pixel) = self.blend_pixel(self.hires_vram[index], pixel.colour, pixel.semi_transparent, pixel.mask_bit) {
+				self.hires_vram[index] = new_pixel;
+			}
+		}
+	}
+
+	// returns false if the pixel was masked
+	fn draw_pixel_15bit(&mut self, colour: u16, x: u32, y: u32, semi_transparent: bool, mask_bit: bool) -> bool {
+		let index = coord_to_vram_index(x, y) as usize;
+
+		match self.blend_pixel(self.vram[index], colour, semi_transparent, mask_bit) {
+			Some(new_pixel) => {
+				self.vram[index] = new_pixel;
+				true
+			},
+			None => false,
+		}
+	}
+
+	fn blend_pixel(&self, old_pixel: u16, colour: u16, semi_transparent: bool, mask_bit: bool) -> Option<u16> {
+		let mask = u16::from(mask_bit || self.force_mask_bit) << 15;
+
+		if self.check_mask_bit && old_pixel & 0x8000 != 0 {
+			return None;
+		}
+
 		let draw_pixel = if semi_transparent {
 			self.apply_semi_transparency(old_pixel, colour)
 		} else {
 			colour
 		};
 
-		self.vram[coord_to_vram_index(x, y) as usize] = draw_pixel | mask;
+		Some(draw_pixel | mask)
+	}
+
+	fn scaled_draw_area(&self, scale: i32) -> (Vertex, Vertex) {
+		(
+			Vertex::new(self.draw_area_top_left.x * scale, self.draw_area_top_left.y * scale),
+			Vertex::new((self.draw_area_bottom_right.x + 1) * scale - 1, (self.draw_area_bottom_right.y + 1) * scale - 1),
+		)
+	}
+
+	fn hires_index(&self, x: u32, y: u32) -> usize {
+		let scale = self.resolution_scale;
+
+		((1024 * scale) * (y & (512 * scale - 1)) + (x & (1024 * scale - 1))) as usize
+	}
+
+	// writes a native pixel to every upscaled pixel it covers
+	fn fill_hires_block(&mut self, x: u32, y: u32, colour: u16) {
+		let scale = self.resolution_scale;
+
+		for sub_y in 0..scale {
+			for sub_x in 0..scale {
+				let index = self.hires_index(x * scale + sub_x, y * scale + sub_y);
+				self.hires_vram[index] = colour;
+			}
+		}
+	}
+
+	fn copy_hires_block(&mut self, src_x: u32, src_y: u32, dest_x: u32, dest_y: u32) {
+		let scale = self.resolution_scale;
+
+		for sub_y in 0..scale {
+			for sub_x in 0..scale {
+				let src = self.hires_vram[self.hires_index(src_x * scale + sub_x, src_y * scale + sub_y)];
+				let dest_index = self.hires_index(dest_x * scale + sub_x, dest_y * scale + sub_y);
+
+				if let Some(new_pixel) = self.blend_pixel(self.hires_vram[dest_index], src, false, false) {
+					self.hires_vram[dest_index] = new_pixel;
+				}
+			}
+		}
 	}
 
 	fn apply_semi_transparency(&self, background: u16, foreground: u16) -> u16 {
@@ -1445,6 +2449,17 @@ fn coord_to_vram_index(x: u32, y: u32) -> u32 {
 	(1024 * (y & 0x1FF)).wrapping_add(x & 0x3FF)
 }
 
+// checks if [start, start + len), wrapping at size, intersects [min, max]
+fn ranges_overlap(start: i32, len: i32, size: i32, min: i32, max: i32) -> bool {
+	if len >= size {
+		return true;
+	}
+
+	let end = start + len - 1;
+
+	(min <= end && max >= start) || (end >= size && min <= end - size)
+}
+
 fn cross_product_z(v0: Vertex, v1: Vertex, v2: Vertex) -> i32 {
 	let result = (v1.x - v0.x) * (v2.y - v0.y) - (v1.y - v0.y) * (v2.x - v0.x);
 	
@@ -1528,6 +2543,29 @@ fn interpolate_uv(lambda: [f64; 3], tex_coords: [Vertex; 3]) -> Vertex {
 	Vertex::new(u, v)
 }
 
+// interpolates u/z and v/z, needs the depth of every vertex
+fn interpolate_uv_perspective(lambda: [f64; 3], tex_coords: [Vertex; 3], z: [f64; 3]) -> Vertex {
+	let weights = [lambda[0] / z[0], lambda[1] / z[1], lambda[2] / z[2]];
+	let total = weights[0] + weights[1] + weights[2];
+
+	if total == 0.0 {
+		return interpolate_uv(lambda, tex_coords);
+	}
+
+	let u = (weights[0] * tex_coords[0].tex_x as f64 + weights[1] * tex_coords[1].tex_x as f64 + weights[2] * tex_coords[2].tex_x as f64) / total;
+	let v = (weights[0] * tex_coords[0].tex_y as f64 + weights[1] * tex_coords[1].tex_y as f64 + weights[2] * tex_coords[2].tex_y as f64) / total;
+
+	// stay inside the range affine interpolation would produce
+	let clamp = |value: f64, coords: [i32; 3]| {
+		value.round().clamp(f64::from(*coords.iter().min().unwrap()), f64::from(*coords.iter().max().unwrap())) as i32
+	};
+
+	Vertex::new(
+		clamp(u, tex_coords.map(|coord| coord.tex_x)),
+		clamp(v, tex_coords.map(|coord| coord.tex_y)),
+	)
+}
+
 fn apply_dithering(colour: Colour, p: Vertex) -> Colour {
     let offset = DITHERING_TABLE[(p.y & 3) as usize][(p.x & 3) as usize];
 
@@ -1548,4 +2586,70 @@ fn apply_modulation(tex_colour: Colour, shaded_colour: Colour) -> Colour {
 
 fn vertices_valid(v0: Vertex, v1: Vertex) -> bool {
 	(v0.x - v1.x).abs() < 1024 && (v0.y - v1.y).abs() < 512
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const GP0: u32 = 0x1F801810;
+	const GP1: u32 = 0x1F801814;
+
+	#[test]
+	fn clut_cache_is_only_reloaded_for_a_new_clut() {
+		let mut gpu = Gpu::new();
+		let clut = Vertex::new(0, 480);
+
+		gpu.vram[1024 * 480] = 0x1234;
+		gpu.tex_page.bit_depth = TexBitDepth::EightBit;
+		gpu.load_clut_cache(clut);
+
+		// vram writes and dropping to 4bit at the same position keep the cached entries
+		gpu.vram[1024 * 480] = 0x5678;
+		gpu.tex_page.bit_depth = TexBitDepth::FourBit;
+		gpu.load_clut_cache(clut);
+		assert_eq!(gpu.read_clut(clut, 0), 0x1234);
+
+		gpu.write32(GP0, 0x01000000);
+		gpu.load_clut_cache(clut);
+		assert_eq!(gpu.read_clut(clut, 0), 0x5678);
+	}
+
+	#[test]
+	fn display_frame_uses_the_display_range() {
+		let mut gpu = Gpu::new();
+
+		// 320 wide mode, 8 video clock ticks per pixel, display on and starting at (16, 8)
+		gpu.write32(GP1, 0x08000001);
+		gpu.write32(GP1, 0x03000000);
+		gpu.write32(GP1, 0x05000000 | 16 | 8 << 10);
+
+		// 256 pixels from hsync 0x260 and 200 lines from line 0x20
+		gpu.write32(GP1, 0x06000000 | 0x260 | (0x260 + 256 * 8) << 12);
+		gpu.write32(GP1, 0x07000000 | 0x20 | (0x20 + 200) << 10);
+
+		gpu.vram[1024 * 10 + 17] = 0x001F;
+
+		let (width, height, frame) = gpu.get_display_frame();
+
+		assert_eq!((width, height), (256, 200));
+		assert_eq!(frame.len(), 256 * 200 * 3);
+		assert_eq!(frame[(2 * 256 + 1) * 3..][..3], [248, 0, 0]);
+	}
+
+	#[test]
+	fn display_area_of_an_interlaced_picture() {
+		let mut gpu = Gpu::new();
+
+		// 640x480 interlaced, 4 ticks per pixel, the range counts lines of one field
+		gpu.write32(GP1, 0x08000000 | 3 | 1 << 2 | 1 << 5);
+		gpu.write32(GP1, 0x06000000 | 0x260 | (0x260 + 2560) << 12);
+		gpu.write32(GP1, 0x07000000 | 0x10 | (0x10 + 240) << 10);
+
+		assert_eq!(gpu.get_display_area(), (640, 480));
+
+		// an empty range falls back to the resolution mode
+		gpu.write32(GP1, 0x06000000);
+		assert_eq!(gpu.get_display_area(), (640, 480));
+	}
+}
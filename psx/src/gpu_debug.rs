@@ -0,0 +1,101 @@
+use crate::gpu_dump::{GpuDump, GpuDumpPlayer};
+
+// draw calls of a single frame for the gpu debugger, the frame is also kept as a gpu dump
+// so vram can be rebuilt up to any of its draws
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawCallKind {
+	Polygon,
+	Rect,
+	Line,
+	Polyline,
+	QuickFill,
+	VramCopy,
+	CpuToVram,
+	VramToCpu,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DrawVertex {
+	pub x: i32,
+	pub y: i32,
+	pub colour: (u8, u8, u8),
+	pub u: i32,
+	pub v: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct DrawCall {
+	pub kind: DrawCallKind,
+	// screen positions with the drawing offset applied, corners of the area for fills and transfers
+	pub vertices: Vec<DrawVertex>,
+
+	pub textured: bool,
+	pub raw_texture: bool,
+	pub tex_page: (u32, u32),
+	// 4, 8 or 16
+	pub tex_depth: u32,
+	pub clut: (u32, u32),
+
+	pub semi_transparent: bool,
+	// 0: B/2+F/2 1: B+F 2: B-F 3: B+F/4
+	pub semi_transparency_mode: u32,
+	pub force_mask_bit: bool,
+	pub check_mask_bit: bool,
+
+	// number of events in the frame dump up to and including this draw
+	pub event_index: usize,
+}
+
+impl DrawCall {
+	// vram area the draw can touch, (min x, min y, max x, max y)
+	pub fn bounds(&self) -> (i32, i32, i32, i32) {
+		self.vertices.iter().fold((i32::MAX, i32::MAX, i32::MIN, i32::MIN), |(min_x, min_y, max_x, max_y), v| {
+			(min_x.min(v.x), min_y.min(v.y), max_x.max(v.x), max_y.max(v.y))
+		})
+	}
+}
+
+#[derive(Clone)]
+pub struct FrameCapture {
+	pub dump: GpuDump,
+	pub draws: Vec<DrawCall>,
+}
+
+impl FrameCapture {
+	// vram right after the draw at index ran
+	pub fn vram_after(&self, index: usize) -> Vec<u16> {
+		let mut player = GpuDumpPlayer::new(self.dump.clone());
+		player.run_events(self.draws[index].event_index);
+
+		player.get_vram().to_vec()
+	}
+}
+
+// the 256x256 texels a textured draw samples from, as 15 bit colours looked up through the clut
+pub fn decode_texture_page(vram: &[u16], draw: &DrawCall) -> Vec<u16> {
+	let (page_x, page_y) = draw.tex_page;
+	let (clut_x, clut_y) = draw.clut;
+
+	let vram_at = |x: u32, y: u32| vram[(1024 * (y & 0x1FF) + (x & 0x3FF)) as usize];
+
+	let mut texels = Vec::with_capacity(256 * 256);
+
+	for v in 0..256 {
+		for u in 0..256 {
+			texels.push(match draw.tex_depth {
+				4 => {
+					let index = (vram_at(page_x + u / 4, page_y + v) >> ((u % 4) * 4)) & 0xF;
+					vram_at(clut_x + u32::from(index), clut_y)
+				},
+				8 => {
+					let index = (vram_at(page_x + u / 2, page_y + v) >> ((u % 2) * 8)) & 0xFF;
+					vram_at(clut_x + u32::from(index), clut_y)
+				},
+				_ => vram_at(page_x + u, page_y + v),
+			});
+		}
+	}
+
+	texels
+}
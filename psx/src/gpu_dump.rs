@@ -0,0 +1,288 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::gpu::{Gpu, GpuModel};
+
+// GPU dumps, every word written to GP0/GP1 after a snapshot of vram and the gpu registers.
+// the registers are stored as the GP0/GP1 commands that set them, so a fresh gpu gets
+// back to the same state by replaying the dump from the start. state no command can set,
+// the texture cache and the interlace field, is stored as is
+
+const MAGIC: &[u8; 8] = b"PSXGPUD2";
+
+const GP0_ADDR: u32 = 0x1F801810;
+const GP1_ADDR: u32 = 0x1F801814;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpuDumpEvent {
+	Gp0(u32),
+	Gp1(u32),
+	// GPUREAD read, needed to advance VRAM to CPU transfers
+	GpuRead,
+	// end of a frame
+	Vblank,
+}
+
+// texture cache contents, lines can be stale against vram so they aren't reloaded from it
+#[derive(Clone)]
+pub struct TextureCacheDump {
+	// tag and halfwords of each of the 256 lines
+	pub lines: Vec<(u32, u64)>,
+	// clut x, y and bit depth the clut was loaded for
+	pub clut_tag: Option<(u16, u16, u8)>,
+	pub clut: Vec<u16>,
+}
+
+#[derive(Clone)]
+pub struct GpuDump {
+	pub model: GpuModel,
+	pub vram: Vec<u16>,
+	pub interlace_field: bool,
+	// only when the cache is enabled
+	pub tex_cache: Option<TextureCacheDump>,
+	pub events: Vec<GpuDumpEvent>,
+}
+
+impl GpuDump {
+	pub fn frames(&self) -> usize {
+		self.events.iter().filter(|ev| **ev == GpuDumpEvent::Vblank).count()
+	}
+
+	pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+		let mut file = BufWriter::new(File::create(path)?);
+
+		file.write_all(MAGIC)?;
+		file.write_all(&[match self.model { GpuModel::V0 => 0, GpuModel::V2 => 2 }])?;
+
+		// vram is run length encoded, most of it is usually a few solid colours
+		let mut runs: Vec<(u16, u16)> = Vec::new();
+
+		for &pixel in self.vram.iter() {
+			match runs.last_mut() {
+				Some((len, value)) if *value == pixel && *len < u16::MAX => *len += 1,
+				_ => runs.push((1, pixel)),
+			}
+		}
+
+		file.write_all(&(runs.len() as u32).to_le_bytes())?;
+
+		for (len, value) in runs {
+			file.write_all(&len.to_le_bytes())?;
+			file.write_all(&value.to_le_bytes())?;
+		}
+
+		file.write_all(&[self.interlace_field as u8, self.tex_cache.is_some() as u8])?;
+
+		if let Some(cache) = &self.tex_cache {
+			for (tag, line) in cache.lines.iter() {
+				file.write_all(&tag.to_le_bytes())?;
+				file.write_all(&line.to_le_bytes())?;
+			}
+
+			match cache.clut_tag {
+				Some((x, y, depth)) => {
+					file.write_all(&[1])?;
+					file.write_all(&x.to_le_bytes())?;
+					file.write_all(&y.to_le_bytes())?;
+					file.write_all(&[depth])?;
+				},
+				None => file.write_all(&[0])?,
+			}
+
+			for entry in cache.clut.iter() {
+				file.write_all(&entry.to_le_bytes())?;
+			}
+		}
+
+		file.write_all(&(self.events.len() as u32).to_le_bytes())?;
+
+		for event in self.events.iter() {
+			match *event {
+				GpuDumpEvent::Gp0(word) => { file.write_all(&[0])?; file.write_all(&word.to_le_bytes())?; },
+				GpuDumpEvent::Gp1(word) => { file.write_all(&[1])?; file.write_all(&word.to_le_bytes())?; },
+				GpuDumpEvent::GpuRead => file.write_all(&[2])?,
+				GpuDumpEvent::Vblank => file.write_all(&[3])?,
+			}
+		}
+
+		file.flush()
+	}
+
+	pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+		let mut file = BufReader::new(File::open(path)?);
+
+		let mut magic = [0; 8];
+		file.read_exact(&mut magic)?;
+
+		if &magic != MAGIC {
+			return Err(invalid_data("not a gpu dump"));
+		}
+
+		let model = match read_u8(&mut file)? {
+			0 => GpuModel::V0,
+			2 => GpuModel::V2,
+			model => return Err(invalid_data(&format!("unknown gpu model {model}"))),
+		};
+
+		let mut vram = Vec::with_capacity(1024 * 512);
+
+		for _ in 0..read_u32(&mut file)? {
+			let len = read_u16(&mut file)?;
+			let value = read_u16(&mut file)?;
+
+			vram.extend(std::iter::repeat_n(value, len as usize));
+		}
+
+		if vram.len() != 1024 * 512 {
+			return Err(invalid_data("wrong vram size"));
+		}
+
+		let interlace_field = read_u8(&mut file)? != 0;
+		let mut tex_cache = None;
+
+		if read_u8(&mut file)? != 0 {
+			let mut lines = Vec::with_capacity(256);
+
+			for _ in 0..256 {
+				lines.push((read_u32(&mut file)?, read_u64(&mut file)?));
+			}
+
+			let clut_tag = match read_u8(&mut file)? {
+				0 => None,
+				_ => Some((read_u16(&mut file)?, read_u16(&mut file)?, read_u8(&mut file)?)),
+			};
+
+			let clut = (0..256).map(|_| read_u16(&mut file)).collect::<io::Result<_>>()?;
+
+			tex_cache = Some(TextureCacheDump { lines, clut_tag, clut });
+		}
+
+		let event_count = read_u32(&mut file)? as usize;
+		let mut events = Vec::with_capacity(event_count);
+
+		for _ in 0..event_count {
+			events.push(match read_u8(&mut file)? {
+				0 => GpuDumpEvent::Gp0(read_u32(&mut file)?),
+				1 => GpuDumpEvent::Gp1(read_u32(&mut file)?),
+				2 => GpuDumpEvent::GpuRead,
+				3 => GpuDumpEvent::Vblank,
+				kind => return Err(invalid_data(&format!("unknown event {kind}"))),
+			});
+		}
+
+		Ok(Self { model, vram, interlace_field, tex_cache, events })
+	}
+}
+
+// replays a dump into a fresh gpu, no cpu or disc involved
+pub struct GpuDumpPlayer {
+	gpu: Gpu,
+	events: Vec<GpuDumpEvent>,
+	position: usize,
+	frame: usize,
+}
+
+impl GpuDumpPlayer {
+	pub fn new(dump: GpuDump) -> Self {
+		let mut gpu = Gpu::new();
+
+		gpu.load_snapshot(&dump);
+
+		Self {
+			gpu,
+			events: dump.events,
+			position: 0,
+			frame: 0,
+		}
+	}
+
+	// runs until the end of the next frame, false once the dump is over
+	pub fn step_frame(&mut self) -> bool {
+		while let Some(event) = self.step_event() {
+			if event == GpuDumpEvent::Vblank {
+				return true;
+			}
+		}
+
+		false
+	}
+
+	// runs the events before position, used to stop right after a draw
+	pub fn run_events(&mut self, position: usize) {
+		while self.position < position && self.step_event().is_some() {}
+	}
+
+	fn step_event(&mut self) -> Option<GpuDumpEvent> {
+		let event = *self.events.get(self.position)?;
+		self.position += 1;
+
+		match event {
+			GpuDumpEvent::Gp0(word) => self.gpu.write32(GP0_ADDR, word),
+			GpuDumpEvent::Gp1(word) => self.gpu.write32(GP1_ADDR, word),
+			GpuDumpEvent::GpuRead => { self.gpu.read32(GP0_ADDR); },
+			GpuDumpEvent::Vblank => {
+				self.gpu.vblank();
+				self.frame += 1;
+			}
+		}
+
+		Some(event)
+	}
+
+	pub fn get_frame(&self) -> usize {
+		self.frame
+	}
+
+	pub fn is_finished(&self) -> bool {
+		self.position >= self.events.len()
+	}
+
+	pub fn get_vram(&self) -> &[u16] {
+		&self.gpu.vram
+	}
+
+	pub fn get_display_res(&self) -> (usize, usize) {
+		self.gpu.get_display_res()
+	}
+
+	pub fn get_display_start(&self) -> (usize, usize) {
+		self.gpu.get_display_start()
+	}
+
+	pub fn is_display_24bit(&self) -> bool {
+		self.gpu.is_display_24bit()
+	}
+
+	pub fn set_render_threads(&mut self, threads: usize) {
+		self.gpu.set_render_threads(threads);
+	}
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+	let mut buf = [0; 1];
+	reader.read_exact(&mut buf)?;
+	Ok(buf[0])
+}
+
+fn read_u16(reader: &mut impl Read) -> io::Result<u16> {
+	let mut buf = [0; 2];
+	reader.read_exact(&mut buf)?;
+	Ok(u16::from_le_bytes(buf))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+	let mut buf = [0; 4];
+	reader.read_exact(&mut buf)?;
+	Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+	let mut buf = [0; 8];
+	reader.read_exact(&mut buf)?;
+	Ok(u64::from_le_bytes(buf))
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, msg)
+}
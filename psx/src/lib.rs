@@ -1,19 +1,37 @@
 use cpu::R3000;
 use bus::Bus;
-use scheduler::{EventType, Scheduler, SchedulerEvent};
+use scheduler::{EventType, Scheduler, SchedulerEvent, SPU_TICK_CYCLES, VBLANK_CYCLES};
 use cdrom::disc::Disc;
 
+pub use gpu::GpuModel;
+pub use spu::{AdsrPhase, VoiceInfo, WAVEFORM_LEN};
+pub use sio0::DeviceType;
+pub use peripherals::{GunConInput, MouseInput, NeGconInput};
+use sio0::Device;
+use gpu_dump::GpuDump;
+use gpu_debug::FrameCapture;
+use capture::AvCapture;
+
 pub mod cpu;
 mod gpu;
+pub mod gpu_dump;
+pub mod gpu_debug;
+pub mod capture;
+pub mod sample_export;
+pub mod str_decoder;
 mod dma;
 pub mod cdrom;
 mod interrupts;
 mod timers;
 pub mod sio0;
+pub mod peripherals;
+mod sio1;
+pub mod serial;
 mod kernel;
 mod spu;
 mod mdec;
 mod scheduler;
+mod pgxp;
 pub mod bus;
 
 pub struct PSXEmulator {
@@ -25,6 +43,7 @@ pub struct PSXEmulator {
 	pub breakpoint_hit: bool,
 
 	out_vram: Box<[u16]>,
+	out_hires_vram: Box<[u16]>,
 }
 
 impl PSXEmulator {
@@ -38,11 +57,12 @@ impl PSXEmulator {
 			breakpoint_hit: false,
 
 			out_vram: vec![0; 512 * 2048].into_boxed_slice().try_into().unwrap(),
+			out_hires_vram: Box::new([]),
 		};
 
 
-		psx.scheduler.schedule_event(SchedulerEvent::new(scheduler::EventType::Vblank), 571212);
-		psx.scheduler.schedule_event(SchedulerEvent::new(EventType::SpuTick), 768);
+		psx.scheduler.schedule_event(SchedulerEvent::new(scheduler::EventType::Vblank), VBLANK_CYCLES);
+		psx.scheduler.schedule_event(SchedulerEvent::new(EventType::SpuTick), SPU_TICK_CYCLES);
 
 		psx
 	}
@@ -56,7 +76,7 @@ impl PSXEmulator {
 			self.scheduler.handle_event(event.clone(), &mut self.bus);
 
 			if event.event_type == EventType::Vblank {
-				self.out_vram = self.bus.gpu.vram.clone();
+				self.update_output();
 			}
 		}
 
@@ -95,16 +115,70 @@ impl PSXEmulator {
 
 		}
 
-		self.out_vram = self.bus.gpu.vram.clone();
+		self.update_output();
+	}
+
+	// what the display shows, so it never gets a half drawn frame
+	fn update_output(&mut self) {
+		self.out_vram.clone_from(&self.bus.gpu.vram);
+
+		let hires_vram = self.bus.gpu.get_hires_vram();
+
+		if self.out_hires_vram.len() == hires_vram.len() {
+			self.out_hires_vram.copy_from_slice(hires_vram);
+		} else {
+			self.out_hires_vram = hires_vram.into();
+		}
 	}
 
 	pub fn load_disc(&mut self, disc: Disc) {
 		self.bus.cdrom.load_disc(disc);
 	}
 
-	pub fn update_input(&mut self, new_state: crate::sio0::InputState, analog_enabled: bool) {
-		self.bus.sio0.controller_state.update_input(new_state);
-		self.bus.sio0.controller_state.set_analog_enabled(analog_enabled);
+	// port 0/1, slot 0 unless a multitap is connected to the port
+	pub fn update_input(&mut self, port: usize, slot: usize, new_state: crate::sio0::InputState, analog_enabled: bool) {
+		if let Some(controller) = self.bus.sio0.controller_mut(port, slot) {
+			controller.update_input(new_state);
+			controller.set_analog_enabled(analog_enabled);
+		}
+	}
+
+	pub fn update_mouse(&mut self, port: usize, slot: usize, input: MouseInput) {
+		if let Device::Mouse(mouse) = self.bus.sio0.device_mut(port, slot) {
+			mouse.update_input(input);
+		}
+	}
+
+	pub fn update_negcon(&mut self, port: usize, slot: usize, input: NeGconInput) {
+		if let Device::NeGcon(negcon) = self.bus.sio0.device_mut(port, slot) {
+			negcon.update_input(input);
+		}
+	}
+
+	// the aim is a pixel of the displayed picture, turned into where the beam draws it
+	pub fn update_guncon(&mut self, port: usize, slot: usize, input: GunConInput) {
+		let beam_position = input.aim.and_then(|(x, y)| self.bus.gpu.beam_position(x, y));
+		let video_clock = self.bus.gpu.video_clock();
+
+		if let Device::GunCon(guncon) = self.bus.sio0.device_mut(port, slot) {
+			guncon.update_input(input, beam_position, video_clock);
+		}
+	}
+
+	pub fn set_device(&mut self, port: usize, slot: usize, device_type: DeviceType) {
+		self.bus.sio0.set_device(port, slot, device_type);
+	}
+
+	pub fn device_type(&self, port: usize, slot: usize) -> DeviceType {
+		self.bus.sio0.device_type(port, slot)
+	}
+
+	pub fn set_multitap(&mut self, port: usize, connected: bool) {
+		self.bus.sio0.set_multitap(port, connected);
+	}
+
+	pub fn is_multitap(&self, port: usize) -> bool {
+		self.bus.sio0.is_multitap(port)
 	}
 
 	pub fn get_display_res(&self) -> (usize, usize) {
@@ -119,10 +193,143 @@ impl PSXEmulator {
 		self.bus.gpu.get_display_start()
 	}
 
+	pub fn get_draw_area(&self) -> ((usize, usize), (usize, usize)) {
+		self.bus.gpu.get_draw_area()
+	}
+
+	pub fn get_texture_page(&self) -> (usize, usize, usize) {
+		self.bus.gpu.get_texture_page()
+	}
+
+	// rasterize large primitives on multiple threads, output is identical to a single thread
+	pub fn set_gpu_threads(&mut self, threads: usize) {
+		self.bus.gpu.set_render_threads(threads);
+	}
+
+	// sub-pixel vertex precision and perspective correct texturing, off for accuracy testing
+	pub fn set_pgxp_enabled(&mut self, enabled: bool) {
+		self.bus.pgxp.set_enabled(enabled);
+	}
+
+	pub fn is_pgxp_enabled(&self) -> bool {
+		self.bus.pgxp.enabled
+	}
+
+	// 3D output is squeezed to 3/4 width, the frontend should present the display at 16:9
+	pub fn set_widescreen(&mut self, enabled: bool) {
+		self.cpu.set_widescreen_hack(enabled);
+	}
+
+	pub fn is_widescreen(&self) -> bool {
+		self.cpu.is_widescreen_hack()
+	}
+
 	pub fn get_vram(&self) -> &Box<[u16]> {
 		&self.out_vram
 	}
 
+	// v0 is the 160-pin gpu of early consoles, v2 the 208-pin one, changes GPUSTAT and GP1(10h)
+	pub fn set_gpu_model(&mut self, model: GpuModel) {
+		self.bus.gpu.set_model(model);
+	}
+
+	pub fn get_gpu_model(&self) -> GpuModel {
+		self.bus.gpu.get_model()
+	}
+
+	// GPUSTAT from the gpu state, games mostly boot with the fixed value this replaces
+	pub fn set_accurate_gpustat(&mut self, enabled: bool) {
+		self.bus.gpu.set_accurate_gpustat(enabled);
+	}
+
+	pub fn is_accurate_gpustat(&self) -> bool {
+		self.bus.gpu.is_accurate_gpustat()
+	}
+
+	// captures GP0/GP1 traffic from the next command boundary on, for replaying with GpuDumpPlayer
+	pub fn start_gpu_recording(&mut self) {
+		self.bus.gpu.start_recording();
+	}
+
+	pub fn stop_gpu_recording(&mut self) -> Option<GpuDump> {
+		self.bus.gpu.stop_recording()
+	}
+
+	pub fn is_gpu_recording(&self) -> bool {
+		self.bus.gpu.is_recording()
+	}
+
+	// keeps the draw calls of the last frame for the gpu debugger
+	pub fn set_gpu_debug_capture(&mut self, enabled: bool) {
+		self.bus.gpu.set_debug_capture(enabled);
+	}
+
+	pub fn get_last_frame_capture(&self) -> Option<&FrameCapture> {
+		self.bus.gpu.get_last_frame_capture()
+	}
+
+	// replaces any running capture, the old one is finished first
+	pub fn start_capture(&mut self, capture: AvCapture) -> std::io::Result<()> {
+		let old = self.scheduler.capture.replace(capture);
+
+		old.map_or(Ok(()), AvCapture::finish)
+	}
+
+	pub fn stop_capture(&mut self) -> std::io::Result<()> {
+		self.scheduler.capture.take().map_or(Ok(()), AvCapture::finish)
+	}
+
+	pub fn is_capturing(&self) -> bool {
+		self.scheduler.capture.is_some()
+	}
+
+	pub fn get_capture(&self) -> Option<&AvCapture> {
+		self.scheduler.capture.as_ref()
+	}
+
+	// 1, 2, 4 or 8, the upscaled image is only drawn for 15-bit display modes
+	pub fn set_resolution_scale(&mut self, scale: u32) {
+		self.bus.gpu.set_resolution_scale(scale);
+		self.update_output();
+	}
+
+	// stale texels are drawn until the game flushes the cache with GP0(01h), like on hardware
+	pub fn set_texture_cache_enabled(&mut self, enabled: bool) {
+		self.bus.gpu.set_texture_cache_enabled(enabled);
+	}
+
+	pub fn is_texture_cache_enabled(&self) -> bool {
+		self.bus.gpu.is_texture_cache_enabled()
+	}
+
+	// what the serial port is connected to, None unplugs it
+	pub fn set_serial_backend(&mut self, backend: Option<Box<dyn serial::SerialBackend>>) {
+		self.bus.sio1.set_backend(backend);
+	}
+
+	// to keep a link cable connected across a reset
+	pub fn take_serial_backend(&mut self) -> Option<Box<dyn serial::SerialBackend>> {
+		self.bus.sio1.take_backend()
+	}
+
+	// constant seek and spin up times, faster loading but timing sensitive games can break
+	pub fn set_fast_cd(&mut self, enabled: bool) {
+		self.bus.cdrom.set_fast_cd(enabled);
+	}
+
+	pub fn is_fast_cd(&self) -> bool {
+		self.bus.cdrom.is_fast_cd()
+	}
+
+	pub fn get_resolution_scale(&self) -> u32 {
+		self.bus.gpu.get_resolution_scale()
+	}
+
+	// upscaled vram as of the last vblank, (1024 * scale) x (512 * scale) halfwords
+	pub fn get_hires_vram(&self) -> &[u16] {
+		&self.out_hires_vram
+	}
+
 	pub fn get_tty_buf(&mut self) -> String {
 		let old_buf = self.cpu.tty_buf.clone();
 
@@ -131,12 +338,12 @@ impl PSXEmulator {
 		old_buf
 	}
 
-	pub fn get_rumble(&self) -> (u8, u8) {
-		self.bus.sio0.controller_state.get_rumble()
+	pub fn get_rumble(&self, port: usize, slot: usize) -> (u8, u8) {
+		self.bus.sio0.controller(port, slot).map_or((0, 0), |controller| controller.get_rumble())
 	}
 
-	pub fn is_analog_locked(&self) -> bool {
-		self.bus.sio0.controller_state.analog_locked
+	pub fn is_analog_locked(&self, port: usize, slot: usize) -> bool {
+		self.bus.sio0.controller(port, slot).is_some_and(|controller| controller.analog_locked)
 	}
 
 	// from https://jsgroth.dev/blog/posts/ps1-sideloading/
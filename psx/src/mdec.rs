@@ -1,7 +1,9 @@
 use std::{collections::VecDeque, mem, usize};
 use log::*;
 
-const ZAGZIG: [usize; 64] = [
+use crate::scheduler::{EventType, Scheduler, SchedulerEvent};
+
+pub(crate) const ZAGZIG: [usize; 64] = [
 	00, 01, 08, 16, 09, 02, 03, 10,
     17, 24, 32, 25, 18, 11, 04, 05,
     12, 19, 26, 33, 40, 48, 41, 34,
@@ -12,6 +14,17 @@ const ZAGZIG: [usize; 64] = [
     53, 60, 61, 54, 47, 55, 62, 63
 ];
 
+// both fifos hold 32 words
+const INPUT_FIFO_LEN: usize = 32 * 2;
+const OUTPUT_FIFO_LEN: usize = 32 * 4;
+
+// idct and colour conversion of a single 8x8 block
+const BLOCK_CYCLES: u64 = 448;
+
+// colour macroblocks come in as Cr, Cb, Y1, Y2, Y3, Y4. this is the block number shown in the
+// status register for each of them, mono blocks always show 4
+const COLOUR_BLOCK_STATUS: [u32; 6] = [4, 5, 0, 1, 2, 3];
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum CmdState {
 	WaitingForNextCmd,
@@ -20,7 +33,6 @@ enum CmdState {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum MdecCmd {
-	Nop,
 	DecodeMacroblock,
 	SetQuant(bool),
 	SetScale,
@@ -45,10 +57,83 @@ impl OutputDepth {
 			_ => unimplemented!()
 		}
 	}
+
+	fn is_colour(&self) -> bool {
+		matches!(self, Self::BPP15 | Self::BPP24)
+	}
+}
+
+// run length decoding of a block, fed one halfword at a time as it leaves the input fifo
+struct RleDecoder {
+	block: [i32; 64],
+	k: usize,
+	quant_scale: u16,
+	started: bool,
+}
+
+impl RleDecoder {
+	fn new() -> Self {
+		Self {
+			block: [0; 64],
+			k: 0,
+			quant_scale: 0,
+			started: false,
+		}
+	}
+
+	// true once the block is complete
+	fn push(&mut self, n: u16, quant_table: &[u8; 64]) -> bool {
+		if !self.started {
+			if n == 0xFE00 {
+				trace!("Skip padding");
+				return false;
+			}
+
+			self.block.fill(0);
+			self.started = true;
+			self.quant_scale = n >> 10;
+			self.k = 0;
+
+			self.store(n, i10(n & 0x3FF) * i32::from(quant_table[0]));
+
+			return false;
+		}
+
+		self.k += usize::from(n >> 10) + 1;
+
+		if self.k >= 64 {
+			self.started = false;
+			return true;
+		}
+
+		let value = (i10(n & 0x3FF) * i32::from(quant_table[self.k]) * i32::from(self.quant_scale) + 4) / 8;
+		self.store(n, value);
+
+		// avoids off-by-one error for index of next block
+		if self.k == 63 {
+			self.started = false;
+			return true;
+		}
+
+		false
+	}
+
+	fn store(&mut self, n: u16, value: i32) {
+		let value = if self.quant_scale == 0 { i10(n & 0x3FF) * 2 } else { value };
+		let value = value.clamp(-0x400, 0x3FF);
+
+		if self.quant_scale > 0 {
+			self.block[ZAGZIG[self.k]] = value;
+		} else {
+			self.block[self.k] = value;
+		}
+	}
 }
 
 pub struct Mdec {
 	cmd_state: CmdState,
+	// status bits 0-15 between commands, Nop leaves its own value there
+	idle_status_words: u16,
 
 	input_fifo: VecDeque<u16>,
 	output_fifo: VecDeque<u8>,
@@ -57,7 +142,7 @@ pub struct Mdec {
 	output_signed: bool,
 	output_bit15: bool,
 
-	// reflected in stat but otherwise unused
+	// gate the data-in/out requests DMA0 and DMA1 wait on
 	dma0_enable: bool,
 	dma1_enable: bool,
 
@@ -65,15 +150,25 @@ pub struct Mdec {
 	colour_quant_table: [u8; 64],
 	scale_table: [i16; 64],
 
+	rle: RleDecoder,
+	// block out of the run length decoder waiting for the idct, nothing more is taken from the
+	// input fifo until it's done
+	pending_block: Option<[i32; 64]>,
+	idct_busy: bool,
+	// position in the macroblock, Cr, Cb, Y1..Y4 for colour
+	current_block: usize,
+
 	cr_block: [i32; 64],
 	cb_block: [i32; 64],
 	y_block: [i32; 64],
+	colour_out: [u8; 0x300],
 }
 
 impl Mdec {
 	pub fn new() -> Self {
 		Self {
 			cmd_state: CmdState::WaitingForNextCmd,
+			idle_status_words: 0xFFFF,
 
 			input_fifo: VecDeque::new(),
 			output_fifo: VecDeque::new(),
@@ -89,13 +184,19 @@ impl Mdec {
 			colour_quant_table: [0; 64],
 			scale_table: [0; 64],
 
+			rle: RleDecoder::new(),
+			pending_block: None,
+			idct_busy: false,
+			current_block: 0,
+
 			cr_block: [0; 64],
 			cb_block: [0; 64],
 			y_block: [0; 64],
+			colour_out: [0; 0x300],
 		}
 	}
 
-	pub fn read32(&mut self, addr: u32) -> u32 {
+	pub fn read32(&mut self, addr: u32, scheduler: &mut Scheduler) -> u32 {
 		match addr {
 			0x1F801820 => {
 				let mut bytes = [0; 4];
@@ -105,6 +206,9 @@ impl Mdec {
 
 				trace!("read output: 0x{:X}", u32::from_le_bytes(bytes));
 
+				// room in the output fifo can let the decoder continue
+				self.process(scheduler);
+
 				u32::from_le_bytes(bytes)
 			},
 			0x1F801824 => self.read_stat(),
@@ -113,76 +217,100 @@ impl Mdec {
 		}
 	}
 
-	pub fn write32(&mut self, addr: u32, write: u32) {
+	pub fn write32(&mut self, addr: u32, write: u32, scheduler: &mut Scheduler) {
 		match addr {
-			0x1F801820 => self.write_cmd(write),
-			0x1F801824 => self.write_ctrl(write),
+			0x1F801820 => self.write_cmd(write, scheduler),
+			0x1F801824 => self.write_ctrl(write, scheduler),
 
 			_ => unimplemented!("[0x{addr:X}] Invalid MDEC read32"),
 		}
 	}
 
 	fn read_stat(&self) -> u32 {
-		let words_left: u32 = match self.cmd_state {
-			CmdState::WaitingForNextCmd => 0xFFFF,
-			CmdState::WaitingForParams { cmd, words_left } => {
-				if cmd == MdecCmd::Nop {
-					words_left as u32
-				} else {
-					words_left as u32 - 1
-				}
-			}
+		let words_left = match self.cmd_state {
+			CmdState::WaitingForNextCmd => self.idle_status_words,
+			CmdState::WaitingForParams { words_left, .. } => words_left.wrapping_sub(1),
 		};
 
-		words_left 
-			| (0) << 16 // Current Block (0..3=Y1..Y4, 4=Cr, 5=Cb) (or for mono: always 4=Y)
+		let current_block = match self.output_depth.is_colour() {
+			true => COLOUR_BLOCK_STATUS[self.current_block],
+			false => 4,
+		};
+
+		let last_word_received = matches!(self.cmd_state, CmdState::WaitingForParams { words_left: 0, .. });
+
+		u32::from(words_left)
+			| current_block << 16 // Current Block (0..3=Y1..Y4, 4=Cr, 5=Cb) (or for mono: always 4=Y)
 			| (self.output_bit15 as u32) << 23
 			| (self.output_signed as u32) << 24
 			| (self.output_depth as u32) << 25
-			| (self.dma1_enable as u32) << 27 // Data-Out Request (set when DMA1 enabled and ready to send data)
-			| (self.dma0_enable as u32) << 28 // Data-In Request  (set when DMA0 enabled and ready to receive data)
+			| (self.dma_out_request() as u32) << 27 // Data-Out Request (set when DMA1 enabled and ready to send data)
+			| (self.dma_in_request() as u32) << 28 // Data-In Request  (set when DMA0 enabled and ready to receive data)
 			| (matches!(self.cmd_state, CmdState::WaitingForParams { .. }) as u32) << 29 // Command Busy  (0=Ready, 1=Busy receiving or processing parameters)
-			| (!matches!(self.cmd_state, CmdState::WaitingForParams { .. }) as u32) << 30 // Data-In Fifo Full (0=No, 1=Full, or Last word received)
+			| ((self.input_fifo.len() >= INPUT_FIFO_LEN || last_word_received) as u32) << 30 // Data-In Fifo Full (0=No, 1=Full, or Last word received)
 			| (self.output_fifo.is_empty() as u32) << 31 // Data-Out Fifo Empty (0=No, 1=Empty)
 	}
 
-	fn write_ctrl(&mut self, write: u32) {
+	// what DMA0 and DMA1 wait on
+	pub fn dma_in_request(&self) -> bool {
+		match self.cmd_state {
+			CmdState::WaitingForParams { words_left: 0, .. } | CmdState::WaitingForNextCmd => false,
+			// waits for the decoder to drain the fifo so a whole dma block fits
+			CmdState::WaitingForParams { cmd: MdecCmd::DecodeMacroblock, .. } => self.dma0_enable && self.input_fifo.is_empty(),
+			// tables fit in the fifo
+			CmdState::WaitingForParams { .. } => self.dma0_enable,
+		}
+	}
+
+	// a full fifo, or whatever is left once the command is done
+	pub fn dma_out_request(&self) -> bool {
+		self.dma1_enable && (self.output_fifo.len() >= OUTPUT_FIFO_LEN || (!self.output_fifo.is_empty() && self.cmd_state == CmdState::WaitingForNextCmd))
+	}
+
+	fn write_ctrl(&mut self, write: u32, scheduler: &mut Scheduler) {
 		self.dma0_enable = (write >> 30) & 1 != 0;
 		self.dma1_enable = (write >> 29) & 1 != 0;
 
 		// Reset MDEC
 		if (write >> 31) & 1 != 0 {
 			self.cmd_state = CmdState::WaitingForNextCmd;
+			self.idle_status_words = 0xFFFF;
 
 			self.output_bit15 = false;
 			self.output_signed = false;
 			self.output_depth = OutputDepth::BPP4;
 
 			self.input_fifo.clear();
+			self.output_fifo.clear();
+
+			self.rle.started = false;
+			self.pending_block = None;
+			self.idct_busy = false;
+			self.current_block = 0;
+
+			scheduler.remove_event(EventType::MdecDecode);
 		}
 	}
 
-	fn write_cmd(&mut self, write: u32) {
+	fn write_cmd(&mut self, write: u32, scheduler: &mut Scheduler) {
 		self.cmd_state = match self.cmd_state {
 			CmdState::WaitingForNextCmd => {
+				// These arent part of every command but are always copied to the status register
+				self.output_depth = OutputDepth::from_bits((write >> 27) & 3);
+				self.output_signed = ((write >> 26) & 1) != 0;
+				self.output_bit15 = ((write >> 25) & 1) != 0;
+
 				match write >> 29 {
 					// Decode Macroblock
 					1 => {
-						self.output_depth = OutputDepth::from_bits((write >> 27) & 3);
-						self.output_signed = ((write >> 26) & 1) != 0;
-						self.output_bit15 = ((write >> 25) & 1) != 0;
-
 						debug!("DecodeMacroblock depth: {:?} signed: {} bit15: {} len: {} halfwords", self.output_depth, self.output_signed, self.output_bit15, (write & 0xFFFF) * 2);
 
+						self.current_block = 0;
+
 						CmdState::WaitingForParams { cmd: MdecCmd::DecodeMacroblock, words_left: (write & 0xFFFF) as u16 }
 					},
 					// Set Quant Table
 					2 => {
-						// These arent part of the command but are still copied to the status register
-						self.output_depth = OutputDepth::from_bits((write >> 27) & 3);
-						self.output_signed = ((write >> 26) & 1) != 0;
-						self.output_bit15 = ((write >> 25) & 1) != 0;
-
 						let recv_colour_table = (write & 1) != 0;
 
 						// 64 bytes for luminance, 64 bytes for colour (if enabled)
@@ -196,53 +324,146 @@ impl Mdec {
 					3 => {
 						debug!("SetScale");
 
-						// These arent part of the command but are still copied to the status register
-						self.output_depth = OutputDepth::from_bits((write >> 27) & 3);
-						self.output_signed = ((write >> 26) & 1) != 0;
-						self.output_bit15 = ((write >> 25) & 1) != 0;
-
 						CmdState::WaitingForParams { cmd: MdecCmd::SetScale, words_left: 64 / 2 }
 					},
-					_ => unimplemented!("MDEC cmd {}", write >> 29)
+					// Nop, 4..7 act the same. the low bits show up in the status register without
+					// the minus one and no parameters follow
+					cmd => {
+						debug!("MDEC cmd {cmd} (nop) 0x{write:X}");
+
+						self.idle_status_words = write as u16;
+
+						CmdState::WaitingForNextCmd
+					},
 				}
 			},
+			CmdState::WaitingForParams { cmd, words_left: 0 } => {
+				// still decoding, the word has nowhere to go
+				warn!("[{cmd:?}] MDEC write 0x{write:X} with no parameters left");
+
+				self.cmd_state
+			},
 			CmdState::WaitingForParams { cmd, words_left } => {
 
+				if self.input_fifo.len() >= INPUT_FIFO_LEN {
+					debug!("MDEC input fifo overflow");
+				}
+
 				self.input_fifo.push_back(write as u16);
 				self.input_fifo.push_back((write >> 16) as u16);
 
 				trace!("[{cmd:?}] write param 0x{write:X} (words left: {words_left}");
 
-				if words_left == 1 {
-					trace!("Exec cmd {cmd:?}");
+				match cmd {
+					MdecCmd::DecodeMacroblock => CmdState::WaitingForParams { cmd, words_left: words_left - 1 },
+					_ if words_left == 1 => {
+						trace!("Exec cmd {cmd:?}");
+
+						match cmd {
+							MdecCmd::SetQuant(recv_colour_table) => self.set_quant_table(recv_colour_table),
+							MdecCmd::SetScale => self.set_scale_table(),
+							MdecCmd::DecodeMacroblock => unreachable!(),
+						}
 
-					self.exec_cmd(cmd);
-					self.input_fifo.clear();
+						self.input_fifo.clear();
+						self.idle_status_words = 0xFFFF;
 
-					CmdState::WaitingForNextCmd
-				} else {
-					CmdState::WaitingForParams { cmd: cmd, words_left: words_left - 1 }
+						CmdState::WaitingForNextCmd
+					},
+					_ => CmdState::WaitingForParams { cmd, words_left: words_left - 1 },
 				}
 			}
+		};
+
+		self.process(scheduler);
+	}
+
+	// runs the input through the run length decoder until a block is ready, then starts the idct
+	// for it once there's room for the output. the decode command ends when all of its parameters
+	// have been decoded
+	fn process(&mut self, scheduler: &mut Scheduler) {
+		let CmdState::WaitingForParams { cmd: MdecCmd::DecodeMacroblock, words_left } = self.cmd_state else {
+			return;
+		};
+
+		if self.idct_busy {
+			return;
+		}
+
+		while self.pending_block.is_none() {
+			let quant_table = match self.output_depth.is_colour() && self.current_block < 2 {
+				true => &self.colour_quant_table,
+				false => &self.luminance_quant_table,
+			};
+
+			let n = match self.input_fifo.pop_front() {
+				Some(n) => n,
+				// end of the data finishes off a partial block
+				None if words_left == 0 && self.rle.started => 0xFE00,
+				None if words_left == 0 => {
+					debug!("finished DecodeMacroblock");
+
+					self.cmd_state = CmdState::WaitingForNextCmd;
+					self.idle_status_words = 0xFFFF;
+					self.current_block = 0;
+
+					return;
+				},
+				None => return,
+			};
+
+			if self.rle.push(n, quant_table) {
+				self.pending_block = Some(self.rle.block);
+			}
+		}
+
+		if self.output_fifo.len() < OUTPUT_FIFO_LEN {
+			self.idct_busy = true;
+			scheduler.schedule_event(SchedulerEvent::new(EventType::MdecDecode), BLOCK_CYCLES);
 		}
 	}
 
-	fn exec_cmd(&mut self, cmd: MdecCmd) {
-		match cmd {
-			MdecCmd::Nop => {},
-			MdecCmd::DecodeMacroblock => self.decode_macroblock(),
-			MdecCmd::SetQuant(recv_colour_table) => self.set_quant_table(recv_colour_table),
-			MdecCmd::SetScale => self.set_scale_table(),
+	pub fn decode_event(&mut self, scheduler: &mut Scheduler) {
+		self.idct_busy = false;
+
+		let Some(mut block) = self.pending_block.take() else {
+			return;
+		};
+
+		idct_core(&mut block, &self.scale_table);
+
+		if self.output_depth.is_colour() {
+			match self.current_block {
+				0 => self.cr_block = block,
+				1 => self.cb_block = block,
+				n => {
+					self.y_block = block;
+
+					let (xx, yy) = [(0, 0), (8, 0), (0, 8), (8, 8)][n - 2];
+					self.yuv_to_rgb(xx, yy);
+				},
+			}
+
+			self.current_block += 1;
+
+			if self.current_block == 6 {
+				self.current_block = 0;
+				self.push_colour_macroblock();
+			}
+		} else {
+			self.y_block = block;
+			self.push_monochrome_block();
 		}
+
+		self.process(scheduler);
 	}
 
-	
 	fn set_quant_table(&mut self, recv_colour_table: bool) {
 		for i in 0..64 / 2 {
 			let halfword = self.input_fifo.pop_front().expect("luminance table halfwords");
 			self.luminance_quant_table[2 * i..2 * (i + 1)].copy_from_slice(&halfword.to_le_bytes());
 		}
-		
+
 		if recv_colour_table {
 			for i in 0..64 / 2 {
 				let halfword = self.input_fifo.pop_front().expect("colour table halfwords");
@@ -253,7 +474,7 @@ impl Mdec {
 		trace!("set luminance table: {:X?}", self.luminance_quant_table);
 		trace!("set colour table: {:X?}", self.colour_quant_table);
 	}
-	
+
 	fn set_scale_table(&mut self) {
 		for (i, &halfword) in self.input_fifo.iter().enumerate() {
 			self.scale_table[i] =  halfword as i16;
@@ -262,18 +483,7 @@ impl Mdec {
 		trace!("set scale table: {:X?}", self.scale_table)
 	}
 
-	fn decode_macroblock(&mut self) {
-		match self.output_depth {
-			OutputDepth::BPP15 | OutputDepth::BPP24 => self.decode_colour_macroblock(),
-			OutputDepth::BPP4 | OutputDepth::BPP8 => self.decode_monochrome_macroblock(),
-		}
-	}
-
-	fn decode_monochrome_macroblock(&mut self) {
-		self.output_fifo.clear();
-
-		decode_block(&mut self.input_fifo, &mut self.y_block, &self.luminance_quant_table, &self.scale_table);
-
+	fn push_monochrome_block(&mut self) {
 		// y_to_mono
 		let mut mono_out = [0; 64];
 		for (i, &y) in self.y_block.iter().enumerate() {
@@ -308,67 +518,22 @@ impl Mdec {
 		trace!("pushed {} bytes to fifo", self.output_fifo.len());
 	}
 
-	fn decode_colour_macroblock(&mut self) {
-		let mut count = 0;
-
-		loop {
-			// Cr
-			if !decode_block(&mut self.input_fifo, &mut self.cr_block, &self.colour_quant_table, &self.scale_table) {
-				break;
-			}
-
-			// Cb
-			decode_block(&mut self.input_fifo, &mut self.cb_block, &self.colour_quant_table, &self.scale_table);
-
-			let mut colour_out = [0; 0x300];
-
-			// Y1
-			decode_block(&mut self.input_fifo, &mut self.y_block, &self.luminance_quant_table, &self.scale_table);
-			self.yuv_to_rgb(0, 0, &mut colour_out);
-			// Y2
-			decode_block(&mut self.input_fifo, &mut self.y_block, &self.luminance_quant_table, &self.scale_table);
-			self.yuv_to_rgb(8, 0, &mut colour_out);
-			// Y3
-			decode_block(&mut self.input_fifo, &mut self.y_block, &self.luminance_quant_table, &self.scale_table);
-			self.yuv_to_rgb(0, 8, &mut colour_out);
-			// Y4
-			decode_block(&mut self.input_fifo, &mut self.y_block, &self.luminance_quant_table, &self.scale_table);
-			self.yuv_to_rgb(8, 8, &mut colour_out);
-			
-			// push 16x16 output to fifo
-			match self.output_depth {
-				OutputDepth::BPP15 => {
-					for y in 0..16 {
-						for x in 0..16 {
-							let rgb555_addr = (16 * y + x) * 2;
-
-							for i in rgb555_addr ..= rgb555_addr + 1 {
-								self.output_fifo.push_back(colour_out[i]);
-							}
-						}
-					}
-				},
-				OutputDepth::BPP24 => {
-					for y in 0..16 {
-						for x in 0..16 {
-							let rgb888_addr = (16 * y + x) * 3;
-
-							for i in rgb888_addr ..= rgb888_addr + 2 {
-								self.output_fifo.push_back(colour_out[i]);
-							}
-						}
-					}
-				},
-				_ => unreachable!(),
-			}
+	// push 16x16 output to fifo
+	fn push_colour_macroblock(&mut self) {
+		let len = match self.output_depth {
+			OutputDepth::BPP15 => 16 * 16 * 2,
+			OutputDepth::BPP24 => 16 * 16 * 3,
+			_ => unreachable!(),
+		};
 
-			count += 1;
-		}
+		self.output_fifo.extend(&self.colour_out[..len]);
 
-		debug!("finished decode macroblocks: {count}");
+		trace!("pushed macroblock, {} bytes in fifo", self.output_fifo.len());
 	}
 
-	fn yuv_to_rgb(&mut self, xx: usize, yy: usize, colour_out: &mut [u8; 0x300]) {
+	fn yuv_to_rgb(&mut self, xx: usize, yy: usize) {
+		let colour_out = &mut self.colour_out;
+
 		for y in 0..8 {
 			for x in 0..8 {
 				let mut r = self.cr_block[(((x + xx) / 2) + ((y + yy) / 2) * 8) as usize];
@@ -416,74 +581,202 @@ impl Mdec {
 
 }
 
-fn decode_block(src: &mut VecDeque<u16>, block: &mut [i32; 64], quant_table: &[u8; 64], scale_table: &[i16; 64]) -> bool {
-	block.fill(0);
+fn idct_core(block: &mut [i32; 64], scale_table: &[i16; 64]) {
+	let mut buf: [i32; 64] = [0; 64];
 
-	while src.front().copied() == Some(0xFE00) {
-		trace!("Skip padding");
-		src.pop_front();
+	for _ in 0..2 {
+		for x in 0..8 {
+			for y in 0..8 {
+				let mut sum = 0;
+
+				for z in 0..8 {
+					sum += block[y + z * 8] as i32 * (scale_table[x + z * 8] / 8) as i32;
+				}
+
+				buf[x + y * 8] = (sum + 0xFFF) / 0x2000;
+			}
+		}
+		mem::swap(&mut buf, block);
 	}
+}
 
-	let Some(mut n) = src.pop_front() else { return false };
+fn i10(value: u16) -> i32 {
+	(((value as i16) << 6) >> 6) as i32
+}
 
-	let quant_scale = n >> 10;
-	
-	let mut value = i10(n & 0x3FF) * i32::from(quant_table[0]);
-	let mut k = 0;
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::str_decoder::{QUANT_MATRIX, SCALE_TABLE};
 
-	while k < 64 {
-		if quant_scale == 0 {
-			value = i10(n & 0x3FF) * 2;
-		}
+	const MDEC_DATA: u32 = 0x1F801820;
+	const MDEC_CTRL: u32 = 0x1F801824;
 
-		value = value.clamp(-0x400, 0x3FF);
+	// the same tables for luminance and colour, uploaded as they are
+	fn mdec_with_tables(scheduler: &mut Scheduler) -> Mdec {
+		let mut mdec = Mdec::new();
 
-		if quant_scale > 0 {
-			block[ZAGZIG[k as usize]] = value;
-		} else {
-			block[k as usize] = value;
+		mdec.write32(MDEC_DATA, 0x4000_0001, scheduler);
+
+		for _ in 0..2 {
+			for bytes in QUANT_MATRIX.chunks_exact(4) {
+				mdec.write32(MDEC_DATA, u32::from_le_bytes(bytes.try_into().unwrap()), scheduler);
+			}
 		}
 
-		// avoids off-by-one error for index of next block
-		if k == 63 {
-			break;
+		mdec.write32(MDEC_DATA, 0x6000_0000, scheduler);
+
+		for pair in SCALE_TABLE.chunks_exact(2) {
+			mdec.write32(MDEC_DATA, u32::from(pair[0]) | u32::from(pair[1]) << 16, scheduler);
 		}
 
-		n = src.pop_front().unwrap_or(0xFE00);
+		mdec
+	}
 
-		k += (n >> 10) + 1;
+	fn words(halfwords: &[u16]) -> Vec<u32> {
+		halfwords.chunks_exact(2).map(|pair| u32::from(pair[0]) | u32::from(pair[1]) << 16).collect()
+	}
 
-		if k >= 64 {
-			break;
+	// runs the pending idct, false if there's none
+	fn run_decode_event(mdec: &mut Mdec, scheduler: &mut Scheduler) -> bool {
+		if scheduler.get_event(EventType::MdecDecode).is_none() {
+			return false;
 		}
 
-		value = (i10(n & 0x3FF) * i32::from(quant_table[k as usize]) * i32::from(quant_scale) + 4) / 8;
+		scheduler.remove_event(EventType::MdecDecode);
+		mdec.decode_event(scheduler);
+
+		true
 	}
 
-	idct_core(block, scale_table);
+	fn read_output(mdec: &mut Mdec, scheduler: &mut Scheduler, out: &mut Vec<u8>) {
+		while mdec.read32(MDEC_CTRL, scheduler) & (1 << 31) == 0 {
+			out.extend(mdec.read32(MDEC_DATA, scheduler).to_le_bytes());
+		}
+	}
 
-	return true;
-}
+	// the command and its parameters, then decodes until it's done
+	fn decode(command: u32, halfwords: &[u16]) -> Vec<u8> {
+		let mut scheduler = Scheduler::new(Box::new(|_| {}));
+		let mut mdec = mdec_with_tables(&mut scheduler);
+		let params = words(halfwords);
 
-fn idct_core(block: &mut [i32; 64], scale_table: &[i16; 64]) {
-	let mut buf: [i32; 64] = [0; 64];
+		mdec.write32(MDEC_DATA, command | params.len() as u32, &mut scheduler);
 
-	for _ in 0..2 {
-		for x in 0..8 {
-			for y in 0..8 {
-				let mut sum = 0;
+		for word in params {
+			mdec.write32(MDEC_DATA, word, &mut scheduler);
+		}
 
-				for z in 0..8 {
-					sum += block[y + z * 8] as i32 * (scale_table[x + z * 8] / 8) as i32;
-				}
+		let mut out = Vec::new();
 
-				buf[x + y * 8] = (sum + 0xFFF) / 0x2000;
+		loop {
+			read_output(&mut mdec, &mut scheduler, &mut out);
+
+			if !run_decode_event(&mut mdec, &mut scheduler) {
+				break;
 			}
 		}
-		mem::swap(&mut buf, block);
+
+		assert_eq!(mdec.read32(MDEC_CTRL, &mut scheduler) & (1 << 29), 0, "command still busy");
+
+		out
 	}
-}
 
-fn i10(value: u16) -> i32 {
-	(((value as i16) << 6) >> 6) as i32
-}
\ No newline at end of file
+	#[test]
+	fn monochrome_block() {
+		// dc 0x40 and two ac values, 8 bit unsigned. the output matches the decoder before it
+		// was made incremental
+		let out = decode(0x2000_0000 | 1 << 27, &[0x0440, 0x0010, 0x0BF8, 0xFE00]);
+
+		assert_eq!(out, [
+			144, 144, 144, 144, 144, 144, 144, 144,
+			144, 144, 144, 144, 144, 144, 143, 143,
+			146, 146, 145, 144, 144, 143, 142, 142,
+			148, 148, 146, 145, 143, 141, 140, 139,
+			151, 150, 148, 145, 143, 140, 138, 137,
+			153, 151, 149, 146, 142, 139, 136, 135,
+			155, 153, 150, 146, 142, 138, 135, 133,
+			156, 154, 151, 146, 142, 137, 134, 132,
+		]);
+	}
+
+	#[test]
+	fn colour_macroblock() {
+		// only dc values, Cr, Cb then a different Y for each 8x8 quarter, 24 bit unsigned
+		let out = decode(0x2000_0000 | 2 << 27, &[
+			0x0420, 0xFE00, 0x07E0, 0xFE00,
+			0x0400, 0xFE00, 0x0450, 0xFE00, 0x07B0, 0xFE00, 0x0500, 0xFE00,
+		]);
+
+		assert_eq!(out.len(), 16 * 16 * 3);
+
+		for (i, pixel) in out.chunks_exact(3).enumerate() {
+			let expected = match (i % 16 >= 8, i / 16 >= 8) {
+				(false, false) => [139, 125, 116],
+				(true, false) => [159, 145, 136],
+				(false, true) => [120, 106, 97],
+				(true, true) => [203, 189, 180],
+			};
+
+			assert_eq!(pixel, expected, "pixel {i}");
+		}
+	}
+
+	#[test]
+	fn dma_requests_across_stalls() {
+		let mut scheduler = Scheduler::new(Box::new(|_| {}));
+		let mut mdec = mdec_with_tables(&mut scheduler);
+
+		// two dc only colour macroblocks, 24 bit with both dma requests enabled
+		let macroblock = [0x0420, 0xFE00, 0x07E0, 0xFE00, 0x0400, 0xFE00, 0x0450, 0xFE00, 0x07B0, 0xFE00, 0x0500, 0xFE00];
+		let params = words(&macroblock.repeat(2));
+
+		mdec.write32(MDEC_CTRL, 0x6000_0000, &mut scheduler);
+		mdec.write32(MDEC_DATA, 0x2000_0000 | 2 << 27 | params.len() as u32, &mut scheduler);
+
+		assert!(mdec.dma_in_request());
+		assert!(!mdec.dma_out_request());
+
+		// the first block goes straight to the idct, the second waits in the fifo behind it
+		mdec.write32(MDEC_DATA, params[0], &mut scheduler);
+		mdec.write32(MDEC_DATA, params[1], &mut scheduler);
+		assert!(!mdec.dma_in_request());
+
+		assert!(run_decode_event(&mut mdec, &mut scheduler));
+		assert!(mdec.dma_in_request());
+
+		for &word in &params[2..] {
+			mdec.write32(MDEC_DATA, word, &mut scheduler);
+		}
+
+		// every parameter is in
+		assert!(!mdec.dma_in_request());
+
+		// the first macroblock fills the output fifo past its size and the decoder stops
+		while run_decode_event(&mut mdec, &mut scheduler) {}
+
+		assert_eq!(mdec.output_fifo.len(), 16 * 16 * 3);
+		assert!(mdec.dma_out_request());
+
+		// reading below a full fifo lets the decoder carry on, the request drops until the
+		// fifo is full again or the command is done
+		while mdec.output_fifo.len() >= OUTPUT_FIFO_LEN {
+			assert!(scheduler.get_event(EventType::MdecDecode).is_none());
+			mdec.read32(MDEC_DATA, &mut scheduler);
+		}
+
+		assert!(!mdec.dma_out_request());
+		assert!(scheduler.get_event(EventType::MdecDecode).is_some());
+
+		while run_decode_event(&mut mdec, &mut scheduler) {}
+
+		assert!(mdec.dma_out_request());
+
+		let mut out = Vec::new();
+		read_output(&mut mdec, &mut scheduler, &mut out);
+
+		assert_eq!(out.len(), OUTPUT_FIFO_LEN - 4 + 16 * 16 * 3);
+		assert!(!mdec.dma_out_request());
+		assert!(!mdec.dma_in_request());
+	}
+}
@@ -0,0 +1,258 @@
+use log::*;
+
+// sio0 devices other than the standard pads. they only know the read command (0x42),
+// index 0 is that command and each replies with its id, 0x5A and the data
+
+const MOUSE_ID: u8 = 0x12;
+const NEGCON_ID: u8 = 0x23;
+const GUNCON_ID: u8 = 0x63;
+
+// the guncon counts in 8MHz ticks from hsync and in scanlines from vsync
+const GUNCON_CLOCK: u64 = 8_000_000;
+// what it reports when it sees no light
+const GUNCON_OFFSCREEN: (u16, u16) = (0x01, 0x0A);
+
+#[derive(Default, Clone, Copy)]
+pub struct MouseInput {
+	// motion since the last update
+	pub delta_x: i32,
+	pub delta_y: i32,
+
+	pub btn_left: bool,
+	pub btn_right: bool,
+}
+
+#[derive(Clone, Copy)]
+pub struct NeGconInput {
+	pub btn_up: bool,
+	pub btn_down: bool,
+	pub btn_left: bool,
+	pub btn_right: bool,
+
+	pub btn_start: bool,
+	pub btn_a: bool,
+	pub btn_b: bool,
+	pub btn_r: bool,
+
+	// 0x00 twisted fully left, 0x80 centered, 0xFF fully right
+	pub twist: u8,
+
+	// analog buttons, 0 released to 0xFF fully pressed
+	pub btn_i: u8,
+	pub btn_ii: u8,
+	pub btn_l: u8,
+}
+
+impl NeGconInput {
+	pub fn new() -> Self {
+		Self {
+			btn_up: false,
+			btn_down: false,
+			btn_left: false,
+			btn_right: false,
+
+			btn_start: false,
+			btn_a: false,
+			btn_b: false,
+			btn_r: false,
+
+			twist: 0x80,
+
+			btn_i: 0,
+			btn_ii: 0,
+			btn_l: 0,
+		}
+	}
+}
+
+impl Default for NeGconInput {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct GunConInput {
+	pub btn_trigger: bool,
+	pub btn_a: bool,
+	pub btn_b: bool,
+
+	// pixel of the displayed picture the gun points at, None when it's off screen
+	pub aim: Option<(f32, f32)>,
+}
+
+pub struct MouseState {
+	btn_left: bool,
+	btn_right: bool,
+
+	// motion that wasn't sent yet, a read sends at most -128..127
+	delta_x: i32,
+	delta_y: i32,
+}
+
+impl MouseState {
+	pub fn new() -> Self {
+		Self {
+			btn_left: false,
+			btn_right: false,
+
+			delta_x: 0,
+			delta_y: 0,
+		}
+	}
+
+	pub fn update_input(&mut self, input: MouseInput) {
+		self.btn_left = input.btn_left;
+		self.btn_right = input.btn_right;
+
+		self.delta_x = self.delta_x.saturating_add(input.delta_x);
+		self.delta_y = self.delta_y.saturating_add(input.delta_y);
+	}
+
+	pub fn tx_reply(&mut self, index: u8, tx: u8) -> Option<(u8, bool)> {
+		let reply = match index {
+			0 if tx != 0x42 => return None,
+			0 => MOUSE_ID,
+			1 => 0x5A,
+			2 => 0xFF,
+			// buttons are 0 when pressed, bits 0-1 always 0
+			3 => 0xFC ^ (u8::from(self.btn_right) << 2 | u8::from(self.btn_left) << 3),
+			4 => take_motion(&mut self.delta_x),
+			5 => take_motion(&mut self.delta_y),
+			_ => 0,
+		};
+
+		trace!("mouse reply 0x{reply:X} (index: {index})");
+
+		Some((reply, index < 5))
+	}
+}
+
+impl Default for MouseState {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+fn take_motion(delta: &mut i32) -> u8 {
+	let motion = (*delta).clamp(i8::MIN.into(), i8::MAX.into());
+	*delta -= motion;
+
+	motion as i8 as u8
+}
+
+pub struct NeGconState {
+	input: NeGconInput,
+}
+
+impl NeGconState {
+	pub fn new() -> Self {
+		Self {
+			input: NeGconInput::new(),
+		}
+	}
+
+	pub fn update_input(&mut self, input: NeGconInput) {
+		self.input = input;
+	}
+
+	pub fn tx_reply(&mut self, index: u8, tx: u8) -> Option<(u8, bool)> {
+		let input = &self.input;
+
+		let reply = match index {
+			0 if tx != 0x42 => return None,
+			0 => NEGCON_ID,
+			1 => 0x5A,
+			// invert inputs (0=Pressed, 1=Released)
+			2 => !(
+				u8::from(input.btn_start) << 3
+					| u8::from(input.btn_up) << 4
+					| u8::from(input.btn_right) << 5
+					| u8::from(input.btn_down) << 6
+					| u8::from(input.btn_left) << 7
+			),
+			3 => !(
+				u8::from(input.btn_r) << 3
+					| u8::from(input.btn_b) << 4
+					| u8::from(input.btn_a) << 5
+			),
+			4 => input.twist,
+			5 => input.btn_i,
+			6 => input.btn_ii,
+			7 => input.btn_l,
+			_ => 0,
+		};
+
+		trace!("negcon reply 0x{reply:X} (index: {index})");
+
+		Some((reply, index < 7))
+	}
+}
+
+impl Default for NeGconState {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+pub struct GunConState {
+	btn_trigger: bool,
+	btn_a: bool,
+	btn_b: bool,
+
+	// where the beam was when the gun saw it
+	position: (u16, u16),
+}
+
+impl GunConState {
+	pub fn new() -> Self {
+		Self {
+			btn_trigger: false,
+			btn_a: false,
+			btn_b: false,
+
+			position: GUNCON_OFFSCREEN,
+		}
+	}
+
+	// beam position is in video clock ticks from hsync and scanlines from vsync, None when
+	// the gun doesn't point at the picture
+	pub fn update_input(&mut self, input: GunConInput, beam_position: Option<(u32, u32)>, video_clock: u64) {
+		self.btn_trigger = input.btn_trigger;
+		self.btn_a = input.btn_a;
+		self.btn_b = input.btn_b;
+
+		self.position = match beam_position {
+			Some((ticks, line)) => ((u64::from(ticks) * GUNCON_CLOCK / video_clock) as u16, line as u16),
+			None => GUNCON_OFFSCREEN,
+		};
+	}
+
+	pub fn tx_reply(&mut self, index: u8, tx: u8) -> Option<(u8, bool)> {
+		let (x, y) = self.position;
+
+		let reply = match index {
+			0 if tx != 0x42 => return None,
+			0 => GUNCON_ID,
+			1 => 0x5A,
+			// invert inputs (0=Pressed, 1=Released)
+			2 => !(u8::from(self.btn_a) << 3),
+			3 => !(u8::from(self.btn_trigger) << 5 | u8::from(self.btn_b) << 6),
+			4 => x as u8,
+			5 => (x >> 8) as u8,
+			6 => y as u8,
+			7 => (y >> 8) as u8,
+			_ => 0,
+		};
+
+		trace!("guncon reply 0x{reply:X} (index: {index})");
+
+		Some((reply, index < 7))
+	}
+}
+
+impl Default for GunConState {
+	fn default() -> Self {
+		Self::new()
+	}
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use crate::bus::Bus;
+
+// PGXP-style sub-pixel precision between the GTE and GPU
+// the GTE keeps the unrounded screen position of every projected vertex, the position follows
+// the integer SXY word through cpu registers and ram and the GPU looks it up when that word
+// reaches GP0. a tag is only trusted while the raw word it was made for is still there
+
+const GP0_ADDR: u32 = 0x1F801810;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PreciseVertex {
+	pub x: f64,
+	pub y: f64,
+	// projected depth (SZ3), used for perspective correct texturing
+	pub z: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Tag {
+	raw: u32,
+	vertex: PreciseVertex,
+}
+
+pub struct Pgxp {
+	pub enabled: bool,
+
+	memory: HashMap<u32, Tag>,
+	registers: [Option<Tag>; 32],
+}
+
+impl Pgxp {
+	pub fn new() -> Self {
+		Self {
+			enabled: false,
+
+			memory: HashMap::new(),
+			registers: [None; 32],
+		}
+	}
+
+	pub fn set_enabled(&mut self, enabled: bool) {
+		self.enabled = enabled;
+
+		if !enabled {
+			self.memory.clear();
+			self.registers = [None; 32];
+		}
+	}
+
+	pub fn tag_register(&mut self, reg: u32, raw: u32, vertex: Option<PreciseVertex>) {
+		if reg != 0 {
+			self.registers[reg as usize] = vertex.map(|vertex| Tag { raw, vertex });
+		}
+	}
+
+	pub fn tag_memory(&mut self, addr: u32, raw: u32, vertex: Option<PreciseVertex>) {
+		match vertex {
+			Some(vertex) => { self.memory.insert(memory_key(addr), Tag { raw, vertex }); },
+			None => { self.memory.remove(&memory_key(addr)); },
+		}
+	}
+
+	pub fn lookup_register(&self, reg: u32, raw: u32) -> Option<PreciseVertex> {
+		self.registers[reg as usize]
+			.filter(|tag| tag.raw == raw)
+			.map(|tag| tag.vertex)
+	}
+
+	pub fn lookup_memory(&self, addr: u32, raw: u32) -> Option<PreciseVertex> {
+		self.memory.get(&memory_key(addr))
+			.filter(|tag| tag.raw == raw)
+			.map(|tag| tag.vertex)
+	}
+
+	// lw, the loaded register inherits the tag of the word
+	pub fn load_register(&mut self, reg: u32, addr: u32, raw: u32) {
+		let vertex = self.lookup_memory(addr, raw);
+		self.tag_register(reg, raw, vertex);
+	}
+}
+
+impl Bus {
+	// word stores from the cpu, tags ram or hands the position to the GPU for GP0 writes
+	pub fn pgxp_store(&mut self, addr: u32, raw: u32, vertex: Option<PreciseVertex>) {
+		if addr & 0x1FFFFFFF == GP0_ADDR {
+			self.gpu.set_next_word_precise(vertex);
+		} else {
+			self.pgxp.tag_memory(addr, raw, vertex);
+		}
+	}
+}
+
+// ram mirrors map to the same word
+fn memory_key(addr: u32) -> u32 {
+	let phys = addr & 0x1FFFFFFC;
+
+	if phys < 0x800000 {
+		phys & 0x1FFFFC
+	} else {
+		phys
+	}
+}
@@ -0,0 +1,218 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use log::*;
+
+use crate::capture::wav_header;
+use crate::cdrom::disc::Disc;
+use crate::spu::decode_adpcm_block;
+
+// adpcm sample extraction, from sound ram or from VAG/VAB files on disc, decoded the same way the
+// voices decode them so the output can be compared against what the spu plays
+
+const VAG_MAGIC: &[u8; 4] = b"VAGp";
+const VAB_MAGIC: &[u8; 4] = b"pBAV";
+
+const VAG_HEADER_LEN: usize = 0x30;
+const VAB_HEADER_LEN: usize = 0x20;
+const VAB_PROGRAMS_LEN: usize = 128 * 16;
+const VAB_TONES_PER_PROGRAM_LEN: usize = 16 * 32;
+const VAB_VAG_TABLE_LEN: usize = 256 * 2;
+
+// vab files don't store a rate, the pitch of the tone playing a sample decides it
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+#[derive(Debug, Clone)]
+pub struct AdpcmSample {
+	pub name: String,
+	pub sample_rate: u32,
+	pub samples: Vec<i16>,
+	// sample the loop jumps back to, None for one-shot samples
+	pub loop_start: Option<usize>,
+}
+
+impl AdpcmSample {
+	// decodes blocks up to the first one with the loop end flag
+	pub fn decode(name: String, sample_rate: u32, data: &[u8]) -> Self {
+		let mut samples = Vec::new();
+		let mut loop_start = None;
+		let mut looped = false;
+
+		let (mut old, mut older) = (0, 0);
+		let mut block_samples = [0; 28];
+
+		for block in data.chunks_exact(16) {
+			decode_adpcm_block(block, &mut block_samples, &mut old, &mut older);
+
+			let flags = block[1];
+
+			if flags & 4 != 0 {
+				loop_start = Some(samples.len());
+			}
+
+			samples.extend_from_slice(&block_samples);
+
+			if flags & 1 != 0 {
+				looped = flags & 2 != 0;
+				break;
+			}
+		}
+
+		Self {
+			name,
+			sample_rate,
+			samples,
+			// without a loop start flag the voice repeats from wherever the repeat address was left
+			loop_start: looped.then(|| loop_start.unwrap_or(0)),
+		}
+	}
+
+	// mono 16 bit wav, looped samples get a smpl chunk with the loop points
+	pub fn save_wav(&self, path: impl AsRef<Path>) -> io::Result<()> {
+		let data_len = (self.samples.len() * 2) as u32;
+
+		let mut smpl = Vec::new();
+
+		if let Some(loop_start) = self.loop_start {
+			let fields = [
+				0,									// manufacturer
+				0,									// product
+				1_000_000_000 / self.sample_rate,	// sample period in ns
+				60,									// unity note
+				0,									// pitch fraction
+				0,									// smpte format
+				0,									// smpte offset
+				1,									// loops
+				0,									// sampler data
+				0,									// cue point id
+				0,									// forward loop
+				loop_start as u32,
+				self.samples.len() as u32 - 1,		// loop end, inclusive
+				0,									// fraction
+				0,									// play count, infinite
+			];
+
+			smpl.extend_from_slice(b"smpl");
+			smpl.extend_from_slice(&(fields.len() as u32 * 4).to_le_bytes());
+			smpl.extend(fields.iter().flat_map(|field: &u32| field.to_le_bytes()));
+		}
+
+		let mut wav = wav_header(1, self.sample_rate, data_len, smpl.len() as u32);
+		wav.extend(self.samples.iter().flat_map(|sample| sample.to_le_bytes()));
+		wav.extend_from_slice(&smpl);
+
+		fs::write(path, wav)
+	}
+}
+
+// a single sample with its VAG header, all header fields are big endian
+pub fn parse_vag(name: &str, data: &[u8]) -> Option<AdpcmSample> {
+	if data.len() < VAG_HEADER_LEN || &data[0..4] != VAG_MAGIC {
+		return None;
+	}
+
+	let data_len = u32::from_be_bytes(data[0x0C..0x10].try_into().unwrap()) as usize;
+	let sample_rate = u32::from_be_bytes(data[0x10..0x14].try_into().unwrap());
+
+	if sample_rate == 0 || sample_rate > 96000 {
+		return None;
+	}
+
+	let end = (VAG_HEADER_LEN + data_len).min(data.len());
+
+	Some(AdpcmSample::decode(name.to_string(), sample_rate, &data[VAG_HEADER_LEN..end]))
+}
+
+// sample bank, the header (.VH) describes the programs and tones and the body (.VB) holds the
+// samples back to back. in a single .VAB file the body follows the header
+pub fn parse_vab(name: &str, header: &[u8], body: &[u8]) -> Option<Vec<AdpcmSample>> {
+	if header.len() < VAB_HEADER_LEN || &header[0..4] != VAB_MAGIC {
+		return None;
+	}
+
+	let programs = u16::from_le_bytes([header[0x12], header[0x13]]) as usize;
+	let vags = u16::from_le_bytes([header[0x16], header[0x17]]) as usize;
+
+	if programs > 128 || vags > 254 {
+		return None;
+	}
+
+	let table_start = VAB_HEADER_LEN + VAB_PROGRAMS_LEN + programs * VAB_TONES_PER_PROGRAM_LEN;
+	let table = header.get(table_start..table_start + VAB_VAG_TABLE_LEN)?;
+
+	let mut samples = Vec::with_capacity(vags);
+	let mut offset = 0;
+
+	// entry 0 is unused, sizes are stored divided by 8
+	for vag in 1..=vags {
+		let len = u16::from_le_bytes([table[vag * 2], table[vag * 2 + 1]]) as usize * 8;
+		let data = body.get(offset..offset + len)?;
+
+		samples.push(AdpcmSample::decode(format!("{name}_{vag:03}"), DEFAULT_SAMPLE_RATE, data));
+		offset += len;
+	}
+
+	Some(samples)
+}
+
+// size of a vab header, the body starts right after it
+pub fn vab_header_len(header: &[u8]) -> Option<usize> {
+	let programs = u16::from_le_bytes([*header.get(0x12)?, *header.get(0x13)?]) as usize;
+
+	Some(VAB_HEADER_LEN + VAB_PROGRAMS_LEN + programs * VAB_TONES_PER_PROGRAM_LEN + VAB_VAG_TABLE_LEN)
+}
+
+// every VAG and VAB in the disc filesystem, including ones packed inside other files
+pub fn find_disc_samples(disc: &Disc) -> Vec<AdpcmSample> {
+	let files = disc.iso_files();
+	let mut samples = Vec::new();
+
+	for file in files.iter().filter(|file| !file.is_dir) {
+		let Some(data) = disc.read_iso_extent(file.lba, file.len) else {
+			continue;
+		};
+
+		let stem = file.path.rsplit_once('.').map_or(file.path.as_str(), |(stem, _)| stem);
+		let name = file.path.replace('/', "_");
+
+		// split banks keep the body in a .VB next to the .VH
+		let split_body = || {
+			let vb_path = format!("{stem}.VB");
+			let vb = files.iter().find(|file| file.path.eq_ignore_ascii_case(&vb_path))?;
+
+			disc.read_iso_extent(vb.lba, vb.len)
+		};
+
+		let mut offset = 0;
+
+		while offset + 4 <= data.len() {
+			let magic = &data[offset..offset + 4];
+
+			if magic == VAG_MAGIC {
+				if let Some(sample) = parse_vag(&format!("{name}_{offset:X}"), &data[offset..]) {
+					debug!("VAG in {} at {offset:X}", file.path);
+					samples.push(sample);
+				}
+			} else if magic == VAB_MAGIC {
+				let header = &data[offset..];
+				let body_start = vab_header_len(header).map_or(data.len(), |len| offset + len);
+
+				let bank = if body_start < data.len() {
+					parse_vab(&format!("{name}_{offset:X}"), header, &data[body_start..])
+				} else {
+					split_body().and_then(|body| parse_vab(&format!("{name}_{offset:X}"), header, &body))
+				};
+
+				if let Some(bank) = bank {
+					debug!("VAB in {} at {offset:X}, {} samples", file.path, bank.len());
+					samples.extend(bank);
+				}
+			}
+
+			offset += 4;
+		}
+	}
+
+	samples
+}
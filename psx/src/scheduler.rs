@@ -1,9 +1,12 @@
 use std::{collections::BinaryHeap, i16};
 
-use crate::{bus::Bus, interrupts::InterruptFlag, cdrom::CmdResponse};
+use crate::{bus::Bus, capture::AvCapture, interrupts::InterruptFlag, cdrom::CmdResponse};
 
 const AUDIO_BUFFER_LEN: usize = 735 * 2 * 2; // 44100hz / 60hz * two channels (stereo)
 
+pub const VBLANK_CYCLES: u64 = 571212;
+pub const SPU_TICK_CYCLES: u64 = 768; // 44100hz
+
 #[derive(Clone, PartialEq)]
 pub enum EventType {
 	Vblank,
@@ -12,8 +15,12 @@ pub enum EventType {
 	TimerOverflow(u8),
 	Sio0Irq,
 	Sio0Rx(u8, bool),
+	Sio1Transfer,
 	CdromCmd(CmdResponse),
-	DmaIrq(u8),
+	DmaTransfer,
+	SpuTransfer,
+	SpuIrq,
+	MdecDecode,
 }
 
 #[derive(Clone)]
@@ -58,6 +65,8 @@ pub struct Scheduler {
 	pub buffer_full: bool,
 	audio_buffer: Vec<f32>,
 	audio_callback: Box<dyn Fn(Vec<f32>)>,
+
+	pub capture: Option<AvCapture>,
 }
 
 impl Scheduler {
@@ -69,6 +78,8 @@ impl Scheduler {
 			audio_buffer: Vec::new(),
 			buffer_full: false,
 			audio_callback: audio_callback,
+
+			capture: None,
 		}
 	}
 
@@ -121,15 +132,24 @@ impl Scheduler {
 			EventType::Vblank => {
 				//log::info!("VBlank");
 				bus.interrupts.raise_interrupt(InterruptFlag::Vblank);
+				bus.gpu.vblank();
+
+				if let Some(capture) = &mut self.capture {
+					capture.vblank(&bus.gpu);
+				}
 
 				//log::info!("triggered: {}", bus.interrupts.triggered());
 
-				self.schedule_event(SchedulerEvent::new(EventType::Vblank), 571212);
+				self.schedule_event(SchedulerEvent::new(EventType::Vblank), VBLANK_CYCLES);
 			},
 			EventType::SpuTick => {
 				let cd_sample = bus.cdrom.get_audio_sample();
 				let (sample_l, sample_r) = bus.spu.tick(&mut bus.interrupts, cd_sample);
 
+				if let Some(capture) = &mut self.capture {
+					capture.audio_sample((sample_l, sample_r));
+				}
+
 				// convert to f32 PCM sample in [-1, 1] range
 				self.audio_buffer.push(f32::from(sample_l) / f32::from(i16::MAX));
 				self.audio_buffer.push(f32::from(sample_r) / f32::from(i16::MAX));
@@ -141,7 +161,7 @@ impl Scheduler {
 					self.buffer_full = true;
 				}
 
-				self.schedule_event(SchedulerEvent::new(EventType::SpuTick), 768);
+				self.schedule_event(SchedulerEvent::new(EventType::SpuTick), SPU_TICK_CYCLES);
 			}
 			EventType::TimerTarget(timer) => {
 				bus.timers.target_event(timer, self, &mut bus.interrupts, &bus.gpu);
@@ -155,12 +175,24 @@ impl Scheduler {
 			EventType::Sio0Rx(value, interrupt) => {
 				bus.sio0.rx_event(self, value, interrupt);
 			}
+			EventType::Sio1Transfer => {
+				bus.sio1.transfer_event(self, &mut bus.interrupts);
+			},
 			EventType::CdromCmd(response) => {
 				bus.cdrom.handle_cmd_response(response, self, &mut bus.interrupts);
 			},
-			EventType::DmaIrq(channel) => {
-				bus.dma.raise_int(channel, &mut bus.interrupts);
+			EventType::DmaTransfer => {
+				bus.dma_transfer(self);
 			}
+			EventType::SpuTransfer => {
+				bus.spu.transfer_done();
+			},
+			EventType::SpuIrq => {
+				bus.spu.transfer_irq(&mut bus.interrupts);
+			},
+			EventType::MdecDecode => {
+				bus.mdec.decode_event(self);
+			},
 		}
 	}
 
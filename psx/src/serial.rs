@@ -0,0 +1,421 @@
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use log::*;
+
+// what's on the other end of the sio1 port. the handshake lines are the same for every backend,
+// this side's DTR/RTS outputs are the other side's DSR/CTS inputs
+pub trait SerialBackend {
+	fn send(&mut self, byte: u8);
+
+	// one byte if anything arrived, never blocks
+	fn receive(&mut self) -> Option<u8>;
+
+	fn set_output_lines(&mut self, dtr: bool, rts: bool);
+
+	// DSR and CTS
+	fn input_lines(&mut self) -> (bool, bool);
+}
+
+// backend from a short description, for the command line and settings
+//   loopback                  tx is wired to rx, DTR to DSR and RTS to CTS
+//   tcp-listen:<addr:port>    link cable, waits for the other emulator to connect
+//   tcp:<addr:port>           link cable to an emulator that's listening
+//   unix-listen:<path>        the same over a unix socket
+//   unix:<path>
+//   console                   raw bytes to stdout and from stdin
+//   console:<path>            raw bytes to and from a serial device or pty, e.g. /dev/ttyUSB0
+pub fn open_backend(spec: &str) -> io::Result<Box<dyn SerialBackend>> {
+	let (kind, arg) = spec.split_once(':').unwrap_or((spec, ""));
+
+	Ok(match kind {
+		"loopback" => Box::new(Loopback::new()),
+		"tcp-listen" => Box::new(LinkCable::listen_tcp(arg)?),
+		"tcp" => Box::new(LinkCable::connect_tcp(arg)?),
+		#[cfg(unix)]
+		"unix-listen" => Box::new(LinkCable::listen_unix(arg)?),
+		#[cfg(unix)]
+		"unix" => Box::new(LinkCable::connect_unix(arg)?),
+		"console" if arg.is_empty() => Box::new(SerialConsole::stdio()),
+		"console" => Box::new(SerialConsole::open(arg)?),
+		_ => return Err(io::Error::new(ErrorKind::InvalidInput, format!("unknown serial backend {spec}"))),
+	})
+}
+
+pub struct Loopback {
+	data: VecDeque<u8>,
+	dtr: bool,
+	rts: bool,
+}
+
+impl Loopback {
+	pub fn new() -> Self {
+		Self {
+			data: VecDeque::new(),
+			dtr: false,
+			rts: false,
+		}
+	}
+}
+
+impl Default for Loopback {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl SerialBackend for Loopback {
+	fn send(&mut self, byte: u8) {
+		self.data.push_back(byte);
+	}
+
+	fn receive(&mut self) -> Option<u8> {
+		self.data.pop_front()
+	}
+
+	fn set_output_lines(&mut self, dtr: bool, rts: bool) {
+		self.dtr = dtr;
+		self.rts = rts;
+	}
+
+	fn input_lines(&mut self) -> (bool, bool) {
+		(self.dtr, self.rts)
+	}
+}
+
+// messages between two emulators are 2 bytes, a kind and its value
+const LINK_DATA: u8 = 0;
+// bit 0 DTR, bit 1 RTS
+const LINK_LINES: u8 = 1;
+
+enum LinkStream {
+	Tcp(TcpStream),
+	#[cfg(unix)]
+	Unix(UnixStream),
+}
+
+impl LinkStream {
+	fn set_nonblocking(&self) -> io::Result<()> {
+		match self {
+			LinkStream::Tcp(stream) => {
+				stream.set_nodelay(true)?;
+				stream.set_nonblocking(true)
+			},
+			#[cfg(unix)]
+			LinkStream::Unix(stream) => stream.set_nonblocking(true),
+		}
+	}
+}
+
+impl Read for LinkStream {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		match self {
+			LinkStream::Tcp(stream) => stream.read(buf),
+			#[cfg(unix)]
+			LinkStream::Unix(stream) => stream.read(buf),
+		}
+	}
+}
+
+impl Write for LinkStream {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		match self {
+			LinkStream::Tcp(stream) => stream.write(buf),
+			#[cfg(unix)]
+			LinkStream::Unix(stream) => stream.write(buf),
+		}
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		match self {
+			LinkStream::Tcp(stream) => stream.flush(),
+			#[cfg(unix)]
+			LinkStream::Unix(stream) => stream.flush(),
+		}
+	}
+}
+
+enum LinkListener {
+	Tcp(TcpListener),
+	#[cfg(unix)]
+	Unix(UnixListener),
+}
+
+impl LinkListener {
+	fn accept(&self) -> io::Result<LinkStream> {
+		match self {
+			LinkListener::Tcp(listener) => listener.accept().map(|(stream, _)| LinkStream::Tcp(stream)),
+			#[cfg(unix)]
+			LinkListener::Unix(listener) => listener.accept().map(|(stream, _)| LinkStream::Unix(stream)),
+		}
+	}
+}
+
+enum LinkState {
+	Listening(LinkListener),
+	Connected(LinkStream),
+	Closed,
+}
+
+// link cable to another emulator on the same machine. the listening side keeps running
+// unconnected until the other one shows up
+//
+// the two emulators aren't synced in emulated time, bytes arrive whenever the other side's
+// wall clock gets there. fine for games that wait on DSR/CTS between bytes, but ones that
+// expect a reply within a fixed number of cycles can time out if either side runs slow
+pub struct LinkCable {
+	state: LinkState,
+	// socket file made by unix-listen, removed again on drop
+	socket_path: Option<PathBuf>,
+
+	received: VecDeque<u8>,
+	// partial messages in and what the socket didn't take yet out
+	read_buf: Vec<u8>,
+	write_buf: Vec<u8>,
+
+	output_lines: u8,
+	input_lines: u8,
+}
+
+impl LinkCable {
+	fn new(state: LinkState) -> Self {
+		Self {
+			state,
+			socket_path: None,
+
+			received: VecDeque::new(),
+			read_buf: Vec::new(),
+			write_buf: Vec::new(),
+
+			output_lines: 0,
+			input_lines: 0,
+		}
+	}
+
+	pub fn listen_tcp(addr: &str) -> io::Result<Self> {
+		let listener = TcpListener::bind(addr)?;
+		listener.set_nonblocking(true)?;
+
+		info!("link cable listening on {addr}");
+
+		Ok(Self::new(LinkState::Listening(LinkListener::Tcp(listener))))
+	}
+
+	pub fn connect_tcp(addr: &str) -> io::Result<Self> {
+		Self::connected(LinkStream::Tcp(TcpStream::connect(addr)?))
+	}
+
+	#[cfg(unix)]
+	pub fn listen_unix(path: &str) -> io::Result<Self> {
+		// left behind by a run that didn't exit cleanly, bind fails while it exists
+		if fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+			fs::remove_file(path)?;
+		}
+
+		let listener = UnixListener::bind(path)?;
+		listener.set_nonblocking(true)?;
+
+		info!("link cable listening on {path}");
+
+		let mut cable = Self::new(LinkState::Listening(LinkListener::Unix(listener)));
+		cable.socket_path = Some(PathBuf::from(path));
+
+		Ok(cable)
+	}
+
+	#[cfg(unix)]
+	pub fn connect_unix(path: &str) -> io::Result<Self> {
+		Self::connected(LinkStream::Unix(UnixStream::connect(path)?))
+	}
+
+	fn connected(stream: LinkStream) -> io::Result<Self> {
+		stream.set_nonblocking()?;
+
+		info!("link cable connected");
+
+		Ok(Self::new(LinkState::Connected(stream)))
+	}
+
+	pub fn is_connected(&self) -> bool {
+		matches!(self.state, LinkState::Connected(_))
+	}
+
+	// accepts the other side, sends what's queued and reads what arrived
+	fn poll(&mut self) {
+		if let LinkState::Listening(listener) = &self.state {
+			match listener.accept() {
+				Ok(stream) => match stream.set_nonblocking() {
+					Ok(()) => {
+						info!("link cable connected");
+
+						self.state = LinkState::Connected(stream);
+						self.write_buf.extend([LINK_LINES, self.output_lines]);
+					},
+					Err(err) => warn!("link cable connection failed: {err}"),
+				},
+				Err(err) if err.kind() == ErrorKind::WouldBlock => {},
+				Err(err) => warn!("link cable accept failed: {err}"),
+			}
+		}
+
+		let LinkState::Connected(stream) = &mut self.state else {
+			return;
+		};
+
+		if let Err(err) = write_nonblocking(stream, &mut self.write_buf).and_then(|_| read_nonblocking(stream, &mut self.read_buf)) {
+			warn!("link cable disconnected: {err}");
+
+			self.state = LinkState::Closed;
+			self.input_lines = 0;
+			return;
+		}
+
+		for message in self.read_buf.chunks_exact(2) {
+			match message[0] {
+				LINK_DATA => self.received.push_back(message[1]),
+				LINK_LINES => self.input_lines = message[1],
+				kind => warn!("unknown link cable message {kind}"),
+			}
+		}
+
+		let used = self.read_buf.len() & !1;
+		self.read_buf.drain(..used);
+	}
+}
+
+impl Drop for LinkCable {
+	fn drop(&mut self) {
+		if let Some(path) = &self.socket_path {
+			if let Err(err) = fs::remove_file(path) {
+				warn!("unable to remove link cable socket {}: {err}", path.display());
+			}
+		}
+	}
+}
+
+impl SerialBackend for LinkCable {
+	fn send(&mut self, byte: u8) {
+		// bytes sent with nobody on the other end are lost, same as with a real cable
+		if self.is_connected() {
+			self.write_buf.extend([LINK_DATA, byte]);
+		}
+
+		self.poll();
+	}
+
+	fn receive(&mut self) -> Option<u8> {
+		self.poll();
+		self.received.pop_front()
+	}
+
+	fn set_output_lines(&mut self, dtr: bool, rts: bool) {
+		let lines = u8::from(dtr) | u8::from(rts) << 1;
+
+		if lines != self.output_lines {
+			self.output_lines = lines;
+
+			if self.is_connected() {
+				self.write_buf.extend([LINK_LINES, lines]);
+			}
+		}
+
+		self.poll();
+	}
+
+	fn input_lines(&mut self) -> (bool, bool) {
+		self.poll();
+		(self.input_lines & 1 != 0, self.input_lines & 2 != 0)
+	}
+}
+
+fn write_nonblocking(stream: &mut impl Write, buf: &mut Vec<u8>) -> io::Result<()> {
+	while !buf.is_empty() {
+		match stream.write(buf) {
+			Ok(0) => return Err(ErrorKind::WriteZero.into()),
+			Ok(written) => { buf.drain(..written); },
+			Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+			Err(err) => return Err(err),
+		}
+	}
+
+	Ok(())
+}
+
+fn read_nonblocking(stream: &mut impl Read, buf: &mut Vec<u8>) -> io::Result<()> {
+	let mut chunk = [0; 256];
+
+	loop {
+		match stream.read(&mut chunk) {
+			Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+			Ok(len) => buf.extend_from_slice(&chunk[..len]),
+			Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(()),
+			Err(err) => return Err(err),
+		}
+	}
+}
+
+// raw bytes for serial dev tools and consoles, no framing and the handshake lines always on.
+// reads happen on a thread so a quiet console never blocks the emulator
+pub struct SerialConsole {
+	output: Box<dyn Write>,
+	input: Receiver<u8>,
+}
+
+impl SerialConsole {
+	pub fn new(mut input: impl Read + Send + 'static, output: impl Write + 'static) -> Self {
+		let (sender, receiver) = mpsc::channel();
+
+		thread::spawn(move || {
+			let mut chunk = [0; 256];
+
+			// stops at the end of the input or when the console is dropped
+			while let Ok(len @ 1..) = input.read(&mut chunk) {
+				if chunk[..len].iter().any(|&byte| sender.send(byte).is_err()) {
+					break;
+				}
+			}
+		});
+
+		Self {
+			output: Box::new(output),
+			input: receiver,
+		}
+	}
+
+	pub fn stdio() -> Self {
+		Self::new(io::stdin(), io::stdout())
+	}
+
+	pub fn open(path: &str) -> io::Result<Self> {
+		let device = OpenOptions::new().read(true).write(true).open(path)?;
+
+		Ok(Self::new(device.try_clone()?, device))
+	}
+}
+
+impl SerialBackend for SerialConsole {
+	fn send(&mut self, byte: u8) {
+		if let Err(err) = self.output.write_all(&[byte]).and_then(|_| self.output.flush()) {
+			warn!("serial console write failed: {err}");
+		}
+	}
+
+	fn receive(&mut self) -> Option<u8> {
+		self.input.try_recv().ok()
+	}
+
+	fn set_output_lines(&mut self, _dtr: bool, _rts: bool) {}
+
+	fn input_lines(&mut self) -> (bool, bool) {
+		(true, true)
+	}
+}
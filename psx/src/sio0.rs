@@ -3,6 +3,7 @@ use std::collections::VecDeque;
 use log::*;
 
 use crate::{interrupts::Interrupts, scheduler::{EventType, Scheduler, SchedulerEvent}};
+use crate::peripherals::{GunConState, MouseState, NeGconState};
 
 /*
 serial words:
@@ -23,6 +24,12 @@ Serial transfer loop:
 const CONTROLLER_ADDR: usize = 0x1;
 const MEMCARD_ADDR: usize = 0x81;
 
+// slot A is the one used without a multitap
+const MULTITAP_SLOTS: usize = 4;
+const MULTITAP_ID: u8 = 0x80;
+// every slot answers with 8 bytes when reading all of them, id low, 0x5A and 6 data bytes
+const MULTITAP_SLOT_BYTES: u8 = 8;
+
 #[derive(PartialEq, Clone, Copy, Debug)]
 enum TxState {
 	Disabled,
@@ -385,8 +392,136 @@ impl ControllerState {
 	}
 }
 
+// what can be plugged into a port or a multitap slot
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum DeviceType {
+	None,
+	Controller,
+	Mouse,
+	NeGcon,
+	GunCon,
+}
+
+pub enum Device {
+	None,
+	Controller(ControllerState),
+	Mouse(MouseState),
+	NeGcon(NeGconState),
+	GunCon(GunConState),
+}
+
+impl Device {
+	fn new(device_type: DeviceType) -> Self {
+		match device_type {
+			DeviceType::None => Device::None,
+			DeviceType::Controller => Device::Controller(ControllerState::new()),
+			DeviceType::Mouse => Device::Mouse(MouseState::new()),
+			DeviceType::NeGcon => Device::NeGcon(NeGconState::new()),
+			DeviceType::GunCon => Device::GunCon(GunConState::new()),
+		}
+	}
+
+	fn device_type(&self) -> DeviceType {
+		match self {
+			Device::None => DeviceType::None,
+			Device::Controller(_) => DeviceType::Controller,
+			Device::Mouse(_) => DeviceType::Mouse,
+			Device::NeGcon(_) => DeviceType::NeGcon,
+			Device::GunCon(_) => DeviceType::GunCon,
+		}
+	}
+
+	// index 0 is the command after the address byte, None when nothing answers
+	fn tx_reply(&mut self, index: u8, tx: u8) -> Option<(u8, bool)> {
+		match self {
+			Device::None => None,
+			// digital pads only know the read command
+			Device::Controller(controller) if index == 0 && !controller.analog_enabled && tx != 0x42 => None,
+			Device::Controller(controller) => Some(controller.tx_reply(index, tx)),
+			Device::Mouse(mouse) => mouse.tx_reply(index, tx),
+			Device::NeGcon(negcon) => negcon.tx_reply(index, tx),
+			Device::GunCon(guncon) => guncon.tx_reply(index, tx),
+		}
+	}
+}
+
+// one of the two controller ports, holding a device or a multitap with one in each slot
+struct Port {
+	slots: [Device; MULTITAP_SLOTS],
+	multitap: bool,
+
+	// a read with the tap byte set makes the next transfer go through every slot
+	read_all_slots: bool,
+	read_all_slots_next: bool,
+	command: u8,
+	slot_finished: bool,
+}
+
+impl Port {
+	fn new(device_type: DeviceType) -> Self {
+		Self {
+			slots: [Device::new(device_type), Device::new(DeviceType::None), Device::new(DeviceType::None), Device::new(DeviceType::None)],
+			multitap: false,
+
+			read_all_slots: false,
+			read_all_slots_next: false,
+			command: 0,
+			slot_finished: false,
+		}
+	}
+
+	// after the address byte, false if nothing is connected
+	fn begin_transfer(&mut self) -> bool {
+		self.read_all_slots = self.multitap && self.read_all_slots_next;
+
+		self.multitap || self.slots[0].device_type() != DeviceType::None
+	}
+
+	fn tx_reply(&mut self, index: u8, tx: u8) -> Option<(u8, bool)> {
+		match index {
+			0 => self.command = tx,
+			1 if self.multitap && self.command == 0x42 => self.read_all_slots_next = tx & 1 != 0,
+			_ => {},
+		}
+
+		if !self.read_all_slots {
+			return self.slots[0].tx_reply(index, tx);
+		}
+
+		let reply = match index {
+			0 => MULTITAP_ID,
+			1 => 0x5A,
+			_ => {
+				let slot = ((index - 2) / MULTITAP_SLOT_BYTES) as usize;
+				let slot_index = (index - 2) % MULTITAP_SLOT_BYTES;
+
+				if slot_index == 0 {
+					self.slot_finished = false;
+				}
+
+				// missing pads and bytes past the end of a short reply read as 0xFF
+				match self.slot_finished {
+					true => 0xFF,
+					false => match self.slots[slot].tx_reply(slot_index, tx) {
+						Some((reply, ack)) => {
+							self.slot_finished = !ack;
+							reply
+						},
+						None => {
+							self.slot_finished = true;
+							0xFF
+						},
+					},
+				}
+			},
+		};
+
+		Some((reply, index < 1 + MULTITAP_SLOTS as u8 * MULTITAP_SLOT_BYTES))
+	}
+}
+
 pub struct Sio0 {
-	pub controller_state: ControllerState,
+	ports: [Port; 2],
 
 	rx_fifo: VecDeque<u8>,
 	tx_state: TxState,
@@ -413,7 +548,7 @@ pub struct Sio0 {
 impl Sio0 {
 	pub fn new() -> Self {
 		Self {
-			controller_state: ControllerState::new(),
+			ports: [Port::new(DeviceType::Controller), Port::new(DeviceType::None)],
 
 			rx_fifo: VecDeque::new(),
 			tx_state: TxState::Disabled,
@@ -437,6 +572,53 @@ impl Sio0 {
 		}
 	}
 
+	pub fn set_device(&mut self, port: usize, slot: usize, device_type: DeviceType) {
+		let device = &mut self.ports[port].slots[slot];
+
+		if device.device_type() != device_type {
+			*device = Device::new(device_type);
+		}
+	}
+
+	pub fn device_type(&self, port: usize, slot: usize) -> DeviceType {
+		self.ports[port].slots[slot].device_type()
+	}
+
+	pub fn set_multitap(&mut self, port: usize, connected: bool) {
+		self.ports[port].multitap = connected;
+
+		if !connected {
+			self.ports[port].read_all_slots_next = false;
+		}
+	}
+
+	pub fn is_multitap(&self, port: usize) -> bool {
+		self.ports[port].multitap
+	}
+
+	pub fn device(&self, port: usize, slot: usize) -> &Device {
+		&self.ports[port].slots[slot]
+	}
+
+	pub fn device_mut(&mut self, port: usize, slot: usize) -> &mut Device {
+		&mut self.ports[port].slots[slot]
+	}
+
+	// slots past A are only reachable through a multitap
+	pub fn controller(&self, port: usize, slot: usize) -> Option<&ControllerState> {
+		match self.device(port, slot) {
+			Device::Controller(controller) => Some(controller),
+			_ => None,
+		}
+	}
+
+	pub fn controller_mut(&mut self, port: usize, slot: usize) -> Option<&mut ControllerState> {
+		match self.device_mut(port, slot) {
+			Device::Controller(controller) => Some(controller),
+			_ => None,
+		}
+	}
+
 	pub fn read32(&mut self, addr: u32) -> u32 {
 		match addr {
 			0x1F801040 => self.read_rx(),
@@ -544,9 +726,8 @@ impl Sio0 {
 			},
 			TxState::Ready => {
 				if write as usize == CONTROLLER_ADDR {
-					// port 2 is stubbed
-					if self.port_select {
-						//trace!("communication aborted (ps: {} cs: {})", self.port_select, self.cs);
+					if !self.ports[usize::from(self.port_select)].begin_transfer() {
+						trace!("nothing connected to port {}", u8::from(self.port_select) + 1);
 						self.push_rx(scheduler, 0xFF, false);
 						self.ack = false;
 						return;
@@ -567,15 +748,7 @@ impl Sio0 {
 				TxState::Transfering { index: 0 }
 			},
 			TxState::Transfering { index } => {
-				// TODO for now always assuming the device is a controller
-				if index == 0 && !self.controller_state.analog_enabled && write != 0x42 {
-					// invalid command, abort transfer
-					error!("abort transfer 0x{write:X}");
-					self.push_rx(scheduler, 0xFF, false);
-					TxState::Ready
-				} else {
-					let (reply, should_int) = self.controller_state.tx_reply(index, write);
-
+				if let Some((reply, should_int)) = self.ports[usize::from(self.port_select)].tx_reply(index, write) {
 					trace!("write 0x{write:X} controller reply 0x{reply:X} (index: {index}) (int: {should_int})");
 
 					// don't ack bytes past normal communication sequence
@@ -583,6 +756,11 @@ impl Sio0 {
 					self.push_rx(scheduler, reply, should_int);
 					
 					TxState::Transfering { index: index + 1 }
+				} else {
+					// invalid command, abort transfer
+					error!("abort transfer 0x{write:X}");
+					self.push_rx(scheduler, 0xFF, false);
+					TxState::Ready
 				}
 			}
 		};
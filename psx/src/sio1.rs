@@ -0,0 +1,302 @@
+use std::collections::VecDeque;
+
+use log::*;
+
+use crate::{interrupts::{InterruptFlag, Interrupts}, scheduler::{EventType, Scheduler, SchedulerEvent}, serial::SerialBackend};
+
+// serial port on the back of the console, used by link cables and dev tools. one byte is
+// sent or received per character time, at the baud rate set by the game
+
+const RX_FIFO_LEN: usize = 8;
+
+pub struct Sio1 {
+	backend: Option<Box<dyn SerialBackend>>,
+
+	rx_fifo: VecDeque<u8>,
+	tx_data: Option<u8>,
+	transfer_scheduled: bool,
+
+	tx_enable: bool,
+	dtr: bool,
+	rx_enable: bool,
+	rts: bool,
+
+	tx_ie: bool,
+	rx_ie: bool,
+	rx_int_mode: u8, // 0..3 = IRQ when RX FIFO contains 1,2,4,8 bytes
+	dsr_ie: bool,
+
+	// input lines from the other side
+	dsr: bool,
+	cts: bool,
+
+	irq: bool,
+	rx_overrun: bool,
+
+	mode: u16,
+	baudrate: u16,
+	misc: u16,
+}
+
+impl Sio1 {
+	pub fn new() -> Self {
+		Self {
+			backend: None,
+
+			rx_fifo: VecDeque::new(),
+			tx_data: None,
+			transfer_scheduled: false,
+
+			tx_enable: false,
+			dtr: false,
+			rx_enable: false,
+			rts: false,
+
+			tx_ie: false,
+			rx_ie: false,
+			rx_int_mode: 0,
+			dsr_ie: false,
+
+			dsr: false,
+			cts: false,
+
+			irq: false,
+			rx_overrun: false,
+
+			mode: 0,
+			baudrate: 0,
+			misc: 0,
+		}
+	}
+
+	pub fn set_backend(&mut self, backend: Option<Box<dyn SerialBackend>>) {
+		self.backend = backend;
+		self.update_output_lines();
+	}
+
+	pub fn take_backend(&mut self) -> Option<Box<dyn SerialBackend>> {
+		self.backend.take()
+	}
+
+	pub fn read8(&mut self, addr: u32) -> u8 {
+		match addr {
+			0x1F801050 => self.read_rx(),
+			// the rest of the data register
+			0x1F801051..=0x1F801053 => 0,
+			_ => (self.read16(addr & !1) >> ((addr & 1) * 8)) as u8,
+		}
+	}
+
+	pub fn read16(&mut self, addr: u32) -> u16 {
+		match addr {
+			0x1F801050 => self.read_rx().into(),
+			0x1F801052 => 0,
+			0x1F801054 => self.read_stat() as u16,
+			0x1F801056 => (self.read_stat() >> 16) as u16,
+			0x1F801058 => self.mode,
+			0x1F80105A => self.read_ctrl(),
+			0x1F80105C => self.misc,
+			0x1F80105E => self.baudrate,
+			_ => unreachable!("[0x{addr:X}] SIO1 read16"),
+		}
+	}
+
+	pub fn write8(&mut self, addr: u32, write: u8, scheduler: &mut Scheduler) {
+		match addr {
+			0x1F801050 => self.write_tx(write, scheduler),
+			0x1F801051..=0x1F801057 => {},
+			// merge the byte into the rest of the 16 bit register
+			_ => {
+				let shift = (addr & 1) * 8;
+				let current = match addr & !1 {
+					0x1F801058 => self.mode,
+					0x1F80105A => self.read_ctrl(),
+					0x1F80105C => self.misc,
+					_ => self.baudrate,
+				};
+
+				let merged = (current & !(0xFF << shift)) | (u16::from(write) << shift);
+				self.write16(addr & !1, merged, scheduler);
+			},
+		}
+	}
+
+	pub fn write16(&mut self, addr: u32, write: u16, scheduler: &mut Scheduler) {
+		match addr {
+			0x1F801050 => self.write_tx(write as u8, scheduler),
+			0x1F801052 => {},
+			0x1F801054 | 0x1F801056 => {},
+			0x1F801058 => self.mode = write,
+			0x1F80105A => self.write_ctrl(write, scheduler),
+			0x1F80105C => self.misc = write,
+			0x1F80105E => self.baudrate = write,
+			_ => warn!("[0x{addr:X}] SIO1 write16 0x{write:X}"),
+		}
+	}
+
+	fn read_stat(&mut self) -> u32 {
+		u32::from(self.tx_data.is_none())				// TX FIFO Not Full
+			| u32::from(!self.rx_fifo.is_empty()) << 1	// RX FIFO Not Empty
+			| u32::from(self.tx_data.is_none()) << 2	// TX Idle
+			| u32::from(self.rx_overrun) << 4			// RX FIFO Overrun
+			| u32::from(self.dsr) << 7					// DSR input level
+			| u32::from(self.cts) << 8					// CTS input level
+			| u32::from(self.irq) << 9					// IRQ fired
+	}
+
+	fn read_ctrl(&self) -> u16 {
+		u16::from(self.tx_enable)
+			| u16::from(self.dtr) << 1
+			| u16::from(self.rx_enable) << 2
+			| u16::from(self.rts) << 5
+			| u16::from(self.rx_int_mode) << 8
+			| u16::from(self.tx_ie) << 10
+			| u16::from(self.rx_ie) << 11
+			| u16::from(self.dsr_ie) << 12
+	}
+
+	fn write_ctrl(&mut self, write: u16, scheduler: &mut Scheduler) {
+		trace!("SIO1 write ctrl 0x{write:X}");
+
+		if (write >> 6) & 1 != 0 {
+			trace!("SIO1 reset");
+
+			self.rx_fifo.clear();
+			self.tx_data = None;
+			self.irq = false;
+			self.rx_overrun = false;
+			self.mode = 0;
+			self.baudrate = 0;
+			self.write_ctrl(0, scheduler);
+
+			return;
+		}
+
+		self.tx_enable = write & 1 != 0;
+		self.dtr = (write >> 1) & 1 != 0;
+		self.rx_enable = (write >> 2) & 1 != 0;
+		self.rts = (write >> 5) & 1 != 0;
+
+		// acknowledge
+		if (write >> 4) & 1 != 0 {
+			self.irq = false;
+			self.rx_overrun = false;
+		}
+
+		self.rx_int_mode = ((write >> 8) & 3) as u8;
+		self.tx_ie = (write >> 10) & 1 != 0;
+		self.rx_ie = (write >> 11) & 1 != 0;
+		self.dsr_ie = (write >> 12) & 1 != 0;
+
+		if !self.rx_enable {
+			self.rx_fifo.clear();
+		}
+
+		self.update_output_lines();
+		self.schedule_transfer(scheduler);
+	}
+
+	fn write_tx(&mut self, write: u8, scheduler: &mut Scheduler) {
+		trace!("SIO1 write TX 0x{write:X}");
+
+		if self.tx_data.is_some() {
+			warn!("SIO1 TX overrun, 0x{write:X} replaces a byte that wasn't sent");
+		}
+
+		self.tx_data = Some(write & self.data_mask());
+		self.schedule_transfer(scheduler);
+	}
+
+	fn read_rx(&mut self) -> u8 {
+		self.rx_fifo.pop_front().unwrap_or(0)
+	}
+
+	fn update_output_lines(&mut self) {
+		if let Some(backend) = &mut self.backend {
+			backend.set_output_lines(self.dtr, self.rts);
+		}
+	}
+
+	// 5 to 8 bits
+	fn data_mask(&self) -> u8 {
+		0xFF >> (3 - ((self.mode >> 2) & 3))
+	}
+
+	// start bit, data, parity and stop bits at the reload value times the factor per bit
+	fn character_cycles(&self) -> Option<u64> {
+		let factor = match self.mode & 3 {
+			0 => return None,
+			1 => 1,
+			2 => 16,
+			_ => 64,
+		};
+
+		let data_bits = 5 + u64::from((self.mode >> 2) & 3);
+		let parity_bits = u64::from((self.mode >> 4) & 1);
+		let stop_bits = if (self.mode >> 6) & 3 == 3 { 2 } else { 1 };
+
+		Some(u64::from(self.baudrate.max(1)) * factor * (1 + data_bits + parity_bits + stop_bits))
+	}
+
+	fn is_active(&self) -> bool {
+		self.backend.is_some() && (self.tx_enable || self.rx_enable || self.dsr_ie)
+	}
+
+	fn schedule_transfer(&mut self, scheduler: &mut Scheduler) {
+		if self.transfer_scheduled || !self.is_active() {
+			return;
+		}
+
+		if let Some(cycles) = self.character_cycles() {
+			self.transfer_scheduled = true;
+			scheduler.schedule_event(SchedulerEvent::new(EventType::Sio1Transfer), cycles);
+		}
+	}
+
+	// one character time passed, moves a byte each way and checks the handshake lines
+	pub fn transfer_event(&mut self, scheduler: &mut Scheduler, interrupts: &mut Interrupts) {
+		self.transfer_scheduled = false;
+
+		let Some(backend) = &mut self.backend else {
+			return;
+		};
+
+		let old_dsr = self.dsr;
+		(self.dsr, self.cts) = backend.input_lines();
+
+		let mut interrupt = self.dsr_ie && self.dsr && !old_dsr;
+
+		// the other side has to be ready before anything is sent
+		if self.tx_enable && self.cts {
+			if let Some(byte) = self.tx_data.take() {
+				trace!("SIO1 sent 0x{byte:X}");
+
+				backend.send(byte);
+				interrupt |= self.tx_ie;
+			}
+		}
+
+		if self.rx_enable {
+			if let Some(byte) = backend.receive() {
+				trace!("SIO1 received 0x{byte:X}");
+
+				if self.rx_fifo.len() == RX_FIFO_LEN {
+					self.rx_overrun = true;
+					self.rx_fifo.pop_back();
+				}
+
+				self.rx_fifo.push_back(byte & self.data_mask());
+				interrupt |= self.rx_ie && self.rx_fifo.len() >= 1 << self.rx_int_mode;
+			}
+		}
+
+		if interrupt && !self.irq {
+			trace!("IRQ8");
+
+			self.irq = true;
+			interrupts.raise_interrupt(InterruptFlag::Sio);
+		}
+
+		self.schedule_transfer(scheduler);
+	}
+}
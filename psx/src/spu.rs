@@ -3,6 +3,8 @@ use std::{cell::Cell, ops::{Index, IndexMut, Range}};
 use log::*;
 
 use crate::interrupts::Interrupts;
+use crate::sample_export::AdpcmSample;
+use crate::scheduler::{EventType, Scheduler, SchedulerEvent};
 
 // Table for 4-Point Gaussian Interpolation
 const GAUSS_TABLE: &[i16; 512] = &[
@@ -63,6 +65,14 @@ const CDR_BUF_START: usize = 0x400;
 const VOICE1_BUF_START: usize = 0x800;
 const VOICE3_BUF_START: usize = 0xC00;
 
+// the data transfer fifo, filled by the cpu before a manual write
+const TRANSFER_FIFO_LEN: usize = 32;
+// cpu cycles to move a halfword between the fifo and sound ram, manual and dma transfers alike
+pub const TRANSFER_CYCLES_PER_HALFWORD: u64 = 16;
+
+// samples kept per voice for the inspector waveforms
+pub const WAVEFORM_LEN: usize = 512;
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum TransferMode {
 	Stop = 0,
@@ -212,8 +222,8 @@ impl SweepEnvelope {
 	}
 }
 
-#[derive(Default, Clone, Copy, PartialEq, Eq)]
-enum AdsrPhase {
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AdsrPhase {
 	Attack,
 	Decay,
 	Sustain,
@@ -494,38 +504,7 @@ impl Voice {
 	fn decode_next_block(&mut self, sram: &SoundRam) {
 		let block = &sram[self.current_addr..self.current_addr + 16];
 
-		// decode shift/filter from header
-		// shift can be 0-12; >12 = 9
-		let shift = block[0] & 0xF;
-		let shift = if shift > 12 { 9 } else { shift };
-
-		// 0-4 different filter values
-		let filter = ((block[0] >> 4) & 0x7).min(4);
-
-		let filter_0 = ADPCM_POS_FILTER[filter as usize];
-		let filter_1 = ADPCM_NEG_FILTER[filter as usize];
-
-		for sample_i in 0..28 {
-			let sample_byte = block[2 + sample_i / 2];
-			let sample_nibble = (sample_byte >> (4 * (sample_i % 2))) & 0xF;
-
-			// sign-extend to i32
-			let raw_sample = (((sample_nibble as i8) << 4) >> 4) as i32;
-			// apply shift from header (calulated as 12 - shift)
-			let shifted_sample = raw_sample << (12 - shift);
-
-			let old = self.old_sample as i32;
-			let older = self.older_sample as i32;
-
-			let filtered_sample = shifted_sample + (filter_0 * old + filter_1 * older + 32) / 64;
-
-			let clamped_sample = filtered_sample.clamp(-0x8000, 0x7FFF) as i16;
-			self.decode_buf[sample_i] = clamped_sample;
-
-			// update old and older samples
-			self.older_sample = self.old_sample;
-			self.old_sample = clamped_sample;
-		}
+		decode_adpcm_block(block, &mut self.decode_buf, &mut self.old_sample, &mut self.older_sample);
 
 		// handle loop flags
 		let loop_end 		= (block[1] >> 0) & 1 != 0;
@@ -621,19 +600,62 @@ impl SoundRam {
 		}
 	}
 
-	fn read16(&self, addr: usize) -> u16 {
-		let low = self[addr];
-		let high = self[addr + 1];
-
-		u16::from_le_bytes([low, high])
-	}
-
 	fn write16(&mut self, addr: usize, write: u16) {
 		let bytes = u16::to_le_bytes(write);
 
 		self[addr] = bytes[0];
 		self[addr + 1] = bytes[1];
 	}
+
+	// transfers bypass the indexing irq check, they hit the irq address at a known point in the
+	// transfer which gets scheduled instead
+	fn transfer_write16(&mut self, addr: usize, write: u16) -> bool {
+		self.ram[addr..addr + 2].copy_from_slice(&write.to_le_bytes());
+
+		self.irq_enabled && addr == self.irq_addr
+	}
+
+	fn transfer_read16(&self, addr: usize) -> (u16, bool) {
+		(u16::from_le_bytes([self.ram[addr], self.ram[addr + 1]]), self.irq_enabled && addr == self.irq_addr)
+	}
+
+	// block ranges before end that look like adpcm samples: valid headers up to a block with the
+	// loop end flag. reads ram directly so the scan can't trigger the irq
+	fn find_samples(&self, end: usize) -> Vec<Range<usize>> {
+		let mut samples = Vec::new();
+		let mut start = None;
+
+		// the capture buffers take the first 4K
+		for addr in (VOICE3_BUF_START + 0x400..end).step_by(16) {
+			let block = &self.ram[addr..addr + 16];
+
+			let valid_header = block[0] & 0xF <= 12 && block[0] >> 4 <= 4 && block[1] & !7 == 0;
+			let silent = block.iter().all(|&byte| byte == 0);
+
+			if !valid_header {
+				start = None;
+				continue;
+			}
+
+			// runs of zeroes between samples aren't part of either
+			if start.is_none() && silent {
+				continue;
+			}
+
+			let sample_start = *start.get_or_insert(addr);
+
+			if block[1] & 1 != 0 {
+				start = None;
+
+				// skip the silent looping blocks one-shot samples are often followed by
+				if self.ram[sample_start..addr + 16].chunks_exact(16).any(|block| block[2..].iter().any(|&byte| byte != 0)) {
+					samples.push(sample_start..addr + 16);
+				}
+			}
+		}
+
+		samples
+	}
 }
 
 impl Index<usize> for SoundRam {
@@ -726,18 +748,17 @@ impl SpuControlRegister {
 		self.transfer_mode = TransferMode::from_bits((write >> 4) & 3);
 
 		self.irq_enable = (write >> 6) & 1 != 0;
+		self.spu_enable = (write >> 15) & 1 != 0;
 
 		// writing 0 to irq enable acknowledges the irq and disables further irqs
 		if self.irq_enable == false {
 			trace!("ack IRQ9");
 			sram.irq.set(false);
-
-			sram.irq_enabled = false;
-		} else {
-			// writing 1 enables IRQs
-			sram.irq_enabled = true;
 		}
 
+		// the irq address is only checked while the spu is enabled
+		sram.irq_enabled = self.irq_enable && self.spu_enable;
+
 		self.reverb_master_enable = (write >> 7) & 1 != 0;
 
 		self.noise_freq_step = ((write >> 8) & 3) as u8;
@@ -746,11 +767,67 @@ impl SpuControlRegister {
 		noise.write(self.noise_freq_shift, self.noise_freq_step);
 
 		self.unmute_spu = (write >> 14) & 1 != 0;
-		self.spu_enable = (write >> 15) & 1 != 0;
 	}
 
 }
 
+// snapshot of a voice for the spu inspector
+#[derive(Debug, Clone, Copy)]
+pub struct VoiceInfo {
+	pub adsr_phase: AdsrPhase,
+	pub adsr_level: i16,
+	pub sample_rate: u16,
+
+	pub start_addr: usize,
+	pub repeat_addr: usize,
+	pub current_addr: usize,
+
+	pub key_on: bool,
+	pub end_x: bool,
+	pub noise: bool,
+	pub reverb: bool,
+	pub pitch_modulation: bool,
+
+	pub volume: (i16, i16),
+}
+
+// decodes one 16 byte adpcm block into 28 samples, old/older are the last two samples
+// of the previous block
+pub(crate) fn decode_adpcm_block(block: &[u8], out: &mut [i16; 28], old_sample: &mut i16, older_sample: &mut i16) {
+	// decode shift/filter from header
+	// shift can be 0-12; >12 = 9
+	let shift = block[0] & 0xF;
+	let shift = if shift > 12 { 9 } else { shift };
+
+	// 0-4 different filter values
+	let filter = ((block[0] >> 4) & 0x7).min(4);
+
+	let filter_0 = ADPCM_POS_FILTER[filter as usize];
+	let filter_1 = ADPCM_NEG_FILTER[filter as usize];
+
+	for sample_i in 0..28 {
+		let sample_byte = block[2 + sample_i / 2];
+		let sample_nibble = (sample_byte >> (4 * (sample_i % 2))) & 0xF;
+
+		// sign-extend to i32
+		let raw_sample = (((sample_nibble as i8) << 4) >> 4) as i32;
+		// apply shift from header (calulated as 12 - shift)
+		let shifted_sample = raw_sample << (12 - shift);
+
+		let old = *old_sample as i32;
+		let older = *older_sample as i32;
+
+		let filtered_sample = shifted_sample + (filter_0 * old + filter_1 * older + 32) / 64;
+
+		let clamped_sample = filtered_sample.clamp(-0x8000, 0x7FFF) as i16;
+		out[sample_i] = clamped_sample;
+
+		// update old and older samples
+		*older_sample = *old_sample;
+		*old_sample = clamped_sample;
+	}
+}
+
 // stubbed for now
 pub struct Spu {
 	control: SpuControlRegister,
@@ -772,11 +849,26 @@ pub struct Spu {
 
 	capture_buf_index: usize,
 
+	transfer_fifo: Vec<u16>,
+	transfer_busy: bool,
+	// halfwords moved since the transfer timing was last scheduled, and which one hit the irq address
+	transfer_halfwords: u64,
+	transfer_irq_at: Option<u64>,
+
 	volume_l: SweepEnvelope,
 	volume_r: SweepEnvelope,
 	cd_volume: (i16, i16),
 
 	pub emu_mute: bool,
+
+	// debug controls, these only change what gets mixed into the output
+	voice_muted: [bool; 24],
+	voice_solo: [bool; 24],
+	reverb_output_enabled: bool,
+
+	// last WAVEFORM_LEN samples of every voice, only kept while the inspector is open
+	waveforms: Option<Box<[[i16; WAVEFORM_LEN]; 24]>>,
+	waveform_index: usize,
 }
 
 impl Spu {
@@ -800,11 +892,23 @@ impl Spu {
 
 			capture_buf_index: 0,
 
+			transfer_fifo: Vec::with_capacity(TRANSFER_FIFO_LEN),
+			transfer_busy: false,
+			transfer_halfwords: 0,
+			transfer_irq_at: None,
+
 			volume_l: SweepEnvelope::default(),
 			volume_r: SweepEnvelope::default(),
 			cd_volume: (0, 0),
 
 			emu_mute: false,
+
+			voice_muted: [false; 24],
+			voice_solo: [false; 24],
+			reverb_output_enabled: true,
+
+			waveforms: None,
+			waveform_index: 0,
 		}
 	}
 
@@ -842,21 +946,34 @@ impl Spu {
 		let mut mixed_l: i32 = 0;
 		let mut mixed_r: i32 = 0;
 
+		let any_solo = self.voice_solo.contains(&true);
+
 		for (i, voice) in self.voices.iter_mut().enumerate() {
 			let (sample_l, sample_r) = match self.noise_enabled[i] {
 				false => voice.current_sample,
 				true => voice.apply_volume(self.noise.lfsr as i16)
 			};
 
-			mixed_l += i32::from(sample_l);
-			mixed_r += i32::from(sample_r);
+			if let Some(waveforms) = &mut self.waveforms {
+				waveforms[i][self.waveform_index] = voice.mono_sample;
+			}
 
+			// reverb writes to spu ram, so it gets every voice whatever the debug controls say
 			if self.reverb_enabled[i] {
 				reverb_l += i32::from(sample_l);
 				reverb_r += i32::from(sample_r);
 			}
+
+			if self.voice_muted[i] || (any_solo && !self.voice_solo[i]) {
+				continue;
+			}
+
+			mixed_l += i32::from(sample_l);
+			mixed_r += i32::from(sample_r);
 		}
 
+		self.waveform_index = (self.waveform_index + 1) % WAVEFORM_LEN;
+
 		// muting the SPU only affects the voices
 		if !self.control.unmute_spu {
 			mixed_l = 0;
@@ -880,6 +997,10 @@ impl Spu {
 		// mix reverb output
 		if self.even_tick {
 			let (reverb_out_l, reverb_out_r) = self.reverb.tick(reverb_l, reverb_r, &mut self.sram);
+			let (reverb_out_l, reverb_out_r) = match self.reverb_output_enabled {
+				true => (reverb_out_l, reverb_out_r),
+				false => (0, 0),
+			};
 
 			mixed_l = (mixed_l + i32::from(reverb_out_l)).clamp(-0x8000, 0x7FFF);
 			mixed_r = (mixed_r + i32::from(reverb_out_r)).clamp(-0x8000, 0x7FFF);
@@ -904,6 +1025,83 @@ impl Spu {
 		}
 	}
 
+	pub fn get_voice_info(&self, voice: usize) -> VoiceInfo {
+		let v = &self.voices[voice];
+
+		VoiceInfo {
+			adsr_phase: v.adsr.phase,
+			adsr_level: v.adsr.level,
+			sample_rate: v.sample_rate,
+
+			start_addr: v.start_addr,
+			repeat_addr: v.repeat_addr,
+			current_addr: v.current_addr,
+
+			key_on: v.adsr.phase != AdsrPhase::Release,
+			end_x: v.end_x,
+			noise: self.noise_enabled[voice],
+			reverb: self.reverb_enabled[voice],
+			pitch_modulation: v.pitch_modulation_enabled,
+
+			volume: (v.volume_l.level, v.volume_r.level),
+		}
+	}
+
+	pub fn set_voice_muted(&mut self, voice: usize, muted: bool) {
+		self.voice_muted[voice] = muted;
+	}
+
+	pub fn is_voice_muted(&self, voice: usize) -> bool {
+		self.voice_muted[voice]
+	}
+
+	// while any voice is soloed only soloed voices are heard
+	pub fn set_voice_solo(&mut self, voice: usize, solo: bool) {
+		self.voice_solo[voice] = solo;
+	}
+
+	pub fn is_voice_solo(&self, voice: usize) -> bool {
+		self.voice_solo[voice]
+	}
+
+	// reverb keeps running when disabled so turning it back on doesn't replay a stale buffer
+	pub fn set_reverb_output_enabled(&mut self, enabled: bool) {
+		self.reverb_output_enabled = enabled;
+	}
+
+	pub fn is_reverb_output_enabled(&self) -> bool {
+		self.reverb_output_enabled
+	}
+
+	pub fn set_waveform_capture(&mut self, enabled: bool) {
+		match enabled {
+			true if self.waveforms.is_none() => self.waveforms = Some(Box::new([[0; WAVEFORM_LEN]; 24])),
+			false => self.waveforms = None,
+			_ => {},
+		}
+	}
+
+	// oldest sample first
+	pub fn get_voice_waveform(&self, voice: usize) -> Option<Vec<i16>> {
+		let waveform = &self.waveforms.as_ref()?[voice];
+
+		Some(waveform[self.waveform_index..].iter().chain(&waveform[..self.waveform_index]).copied().collect())
+	}
+
+	// every adpcm sample found in sound ram, a voice playing one gives it its sample rate
+	pub fn extract_samples(&self) -> Vec<AdpcmSample> {
+		// the reverb work area holds pcm, not adpcm
+		let end = if self.reverb.enabled { self.reverb.base_addr } else { SRAM_LEN };
+
+		self.sram.find_samples(end).into_iter().map(|range| {
+			let sample_rate = self.voices.iter()
+				.find(|voice| voice.sample_rate != 0 && (range.start.saturating_sub(16)..range.end).contains(&voice.start_addr))
+				.map_or(44100, |voice| u32::from(voice.sample_rate) * 44100 / 0x1000);
+
+			AdpcmSample::decode(format!("sram_{:05X}", range.start), sample_rate, &self.sram.ram[range])
+		}).collect()
+	}
+
 	pub fn read16(&self, addr: u32) -> u16 {
 		match addr {
 			// voice regs
@@ -953,7 +1151,7 @@ impl Spu {
 		(u32::from(self.read16(addr)) << 16) | u32::from(self.read16(addr + 2))
 	}
 
-	pub fn write16(&mut self, addr: u32, write: u16) {
+	pub fn write16(&mut self, addr: u32, write: u16, scheduler: &mut Scheduler) {
 		match addr {
 			// voice regs
 			0x1F801C00 		..= 0x1F801D7F => {
@@ -999,6 +1197,10 @@ impl Spu {
 			0x1F801DAA => {
 				self.control.write(write, &mut self.noise, &mut self.sram);
 
+				if self.control.transfer_mode == TransferMode::ManualWrite {
+					self.flush_transfer_fifo(scheduler);
+				}
+
 				if !self.control.spu_enable {
 					for mut voice in self.voices {
 						voice.adsr.level = 0;
@@ -1014,7 +1216,17 @@ impl Spu {
 				self.reverb.current_addr = self.reverb.base_addr;
 			}
 			// Sound RAM Data Transfer Fifo
-			0x1F801DA8 => self.write_sram(write),
+			0x1F801DA8 => {
+				if self.transfer_fifo.len() < TRANSFER_FIFO_LEN {
+					self.transfer_fifo.push(write);
+				} else {
+					debug!("SPU transfer fifo full, dropped 0x{write:X}");
+				}
+
+				if self.control.transfer_mode == TransferMode::ManualWrite {
+					self.flush_transfer_fifo(scheduler);
+				}
+			},
 			// Sound RAM Data Transfer Control
 			0x1F801DAC => self.transfer_control = write,
 			// Status Register (SPUSTAT)
@@ -1028,25 +1240,85 @@ impl Spu {
 		}
 	}
 
-	pub fn write32(&mut self, addr: u32, write: u32) {
-		self.write16(addr, write as u16);
-		self.write16(addr + 2, (write >> 16) as u16);
+	pub fn write32(&mut self, addr: u32, write: u32, scheduler: &mut Scheduler) {
+		self.write16(addr, write as u16, scheduler);
+		self.write16(addr + 2, (write >> 16) as u16, scheduler);
 	}
 
+	// a halfword from dma or the fifo, the transfer timing is scheduled by finish_transfer
 	pub fn write_sram(&mut self, write: u16) {
-		self.sram.write16(self.current_sram_addr, write);
+		if self.sram.transfer_write16(self.current_sram_addr, write) {
+			self.transfer_irq_at.get_or_insert(self.transfer_halfwords);
+		}
 
+		self.transfer_halfwords += 1;
 		self.current_sram_addr = (self.current_sram_addr + 2) & SRAM_MASK;
 	}
 
 	pub fn read_sram(&mut self) -> u16 {
-		let read = self.sram.read16(self.current_sram_addr);
+		let (read, irq) = self.sram.transfer_read16(self.current_sram_addr);
 
+		if irq {
+			self.transfer_irq_at.get_or_insert(self.transfer_halfwords);
+		}
+
+		self.transfer_halfwords += 1;
 		self.current_sram_addr = (self.current_sram_addr + 2) & SRAM_MASK;
 
 		read
 	}
 
+	// the data has already been moved, this keeps the busy flag set for as long as the transfer
+	// takes and raises the irq at the point it reached the irq address. elapsed is the time the
+	// transfer already took, dma stalls the cpu while it runs
+	pub fn finish_transfer(&mut self, scheduler: &mut Scheduler, elapsed: u64) {
+		if self.transfer_halfwords == 0 {
+			return;
+		}
+
+		// queued behind a transfer that's still running
+		let queued = scheduler.get_event(EventType::SpuTransfer).map_or(0, |ev| scheduler.event_cycles_away(ev));
+		scheduler.remove_event(EventType::SpuTransfer);
+
+		if let Some(irq_at) = self.transfer_irq_at.take() {
+			let irq_cycles = (irq_at * TRANSFER_CYCLES_PER_HALFWORD).saturating_sub(elapsed);
+			scheduler.schedule_event(SchedulerEvent::new(EventType::SpuIrq), queued + irq_cycles);
+		}
+
+		let transfer_cycles = (self.transfer_halfwords * TRANSFER_CYCLES_PER_HALFWORD).saturating_sub(elapsed);
+		scheduler.schedule_event(SchedulerEvent::new(EventType::SpuTransfer), queued + transfer_cycles);
+
+		self.transfer_busy = true;
+		self.transfer_halfwords = 0;
+	}
+
+	pub fn transfer_done(&mut self) {
+		self.transfer_busy = false;
+	}
+
+	// irq from a transfer reaching the irq address
+	pub fn transfer_irq(&mut self, interrupts: &mut Interrupts) {
+		// acknowledged or disabled while the transfer was running
+		if !self.sram.irq_enabled || self.sram.irq.get() {
+			return;
+		}
+
+		trace!("IRQ9 (transfer)");
+
+		self.sram.irq.set(true);
+		self.sram.last_irq = true;
+
+		interrupts.raise_interrupt(crate::interrupts::InterruptFlag::Spu);
+	}
+
+	fn flush_transfer_fifo(&mut self, scheduler: &mut Scheduler) {
+		for write in std::mem::take(&mut self.transfer_fifo) {
+			self.write_sram(write);
+		}
+
+		self.finish_transfer(scheduler, 0);
+	}
+
 	fn read_endx(&self, is_high: bool) -> u16 {
 		let mut result = 0;
 
@@ -1174,10 +1446,10 @@ impl Spu {
 		(self.control.read() & 0x3F)
 			| (u16::from(self.sram.irq.get()) << 6) // IRQ flag
 			// data transfer DMA read/write request
-			| ((self.control.transfer_mode as u16 & 2) << 7)
+			| ((self.control.transfer_mode as u16 >> 1) << 7)
 			| (u16::from(self.control.transfer_mode == TransferMode::DmaWrite) << 8) // data transfer DMA write request
 			| (u16::from(self.control.transfer_mode == TransferMode::DmaRead) << 9) // data transfer dma read request
-			| (0 << 10) // data transfer busy flag
+			| (u16::from(self.transfer_busy) << 10) // data transfer busy flag
 			| (u16::from(self.capture_buf_index >= 0x200) << 11) // writing to first/second half of capture buffers
 	}
 }
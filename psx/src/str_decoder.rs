@@ -0,0 +1,740 @@
+use log::*;
+
+use crate::cdrom::disc::{Disc, Sector, SECTOR_SYNC};
+use crate::cdrom::xa_apdcm::XaAdpcmState;
+use crate::mdec::{Mdec, ZAGZIG};
+use crate::scheduler::{EventType, Scheduler};
+
+// STR movies, video sectors carry chunks of a compressed frame and XA audio sectors are
+// interleaved between them. the frames are turned back into mdec run length codes and decoded by
+// the emulated mdec, the audio goes through the same decoder the cdrom uses
+
+const RAW_SECTOR_LEN: usize = 0x930;
+// subheader onwards, how mode 2 sectors are stored in .STR/.XA files ripped from a disc
+const MODE2_SECTOR_LEN: usize = 0x920;
+const DATA_SECTOR_LEN: usize = 0x800;
+
+const STR_MAGIC: u16 = 0x0160;
+const STR_TYPE_MDEC: u16 = 0x8001;
+const CHUNK_HEADER_LEN: usize = 0x20;
+const CHUNK_DATA_LEN: usize = DATA_SECTOR_LEN - CHUNK_HEADER_LEN;
+
+// movies are played at double speed
+const SECTORS_PER_SECOND: u64 = 150;
+
+const MDEC_DATA: u32 = 0x1F801820;
+const MDEC_STATUS: u32 = 0x1F801824;
+
+const MACROBLOCK_LEN: usize = 16 * 16 * 3;
+
+// the tables the libraries upload, the quant table is the mpeg-1 intra matrix
+pub(crate) const QUANT_MATRIX: [u8; 64] = [
+	2, 16, 19, 22, 26, 27, 29, 34,
+	16, 16, 22, 24, 27, 29, 34, 37,
+	19, 22, 26, 27, 29, 34, 34, 38,
+	22, 22, 26, 27, 29, 34, 37, 40,
+	22, 26, 27, 29, 32, 35, 40, 48,
+	26, 27, 29, 32, 35, 40, 48, 58,
+	26, 27, 29, 34, 38, 46, 56, 69,
+	27, 29, 35, 38, 46, 56, 69, 83,
+];
+
+pub(crate) const SCALE_TABLE: [u16; 64] = [
+	0x5A82, 0x5A82, 0x5A82, 0x5A82, 0x5A82, 0x5A82, 0x5A82, 0x5A82,
+	0x7D8A, 0x6A6D, 0x471C, 0x18F8, 0xE707, 0xB8E3, 0x9592, 0x8275,
+	0x7641, 0x30FB, 0xCF04, 0x89BE, 0x89BE, 0xCF04, 0x30FB, 0x7641,
+	0x6A6D, 0xE707, 0x8275, 0xB8E3, 0x471C, 0x7D8A, 0x18F8, 0x9592,
+	0x5A82, 0xA57D, 0xA57D, 0x5A82, 0x5A82, 0xA57D, 0xA57D, 0x5A82,
+	0x471C, 0x8275, 0x18F8, 0x6A6D, 0x9592, 0xE707, 0x7D8A, 0xB8E3,
+	0x30FB, 0x89BE, 0x7641, 0xCF04, 0xCF04, 0x7641, 0x89BE, 0x30FB,
+	0x18F8, 0xB8E3, 0x6A6D, 0x8275, 0x7D8A, 0x9592, 0x471C, 0xE707,
+];
+
+const EOB: u16 = 0xFE00;
+
+// run/level codes without the sign bit, mpeg-1 table B.14. "10" is the end of block and "000001"
+// is followed by a 16 bit mdec code
+const AC_CODES: [(&str, u16, u16); 111] = [
+	("11", 0, 1), ("011", 1, 1), ("0100", 0, 2), ("0101", 2, 1), ("00101", 0, 3),
+	("00111", 3, 1), ("00110", 4, 1), ("000110", 1, 2), ("000111", 5, 1), ("000101", 6, 1),
+	("000100", 7, 1), ("0000110", 0, 4), ("0000100", 2, 2), ("0000111", 8, 1), ("0000101", 9, 1),
+	("00100110", 0, 5), ("00100001", 0, 6), ("00100101", 1, 3), ("00100100", 3, 2), ("00100111", 10, 1),
+	("00100011", 11, 1), ("00100010", 12, 1), ("00100000", 13, 1), ("0000001010", 0, 7), ("0000001100", 1, 4),
+	("0000001011", 2, 3), ("0000001111", 4, 2), ("0000001001", 5, 2), ("0000001110", 14, 1), ("0000001101", 15, 1),
+	("0000001000", 16, 1), ("000000011101", 0, 8), ("000000011000", 0, 9), ("000000010011", 0, 10), ("000000010000", 0, 11),
+	("000000011011", 1, 5), ("000000010100", 2, 4), ("000000011100", 3, 3), ("000000010010", 4, 3), ("000000011110", 6, 2),
+	("000000010101", 7, 2), ("000000010001", 8, 2), ("000000011111", 17, 1), ("000000011010", 18, 1), ("000000011001", 19, 1),
+	("000000010111", 20, 1), ("000000010110", 21, 1), ("0000000011010", 0, 12), ("0000000011001", 0, 13), ("0000000011000", 0, 14),
+	("0000000010111", 0, 15), ("0000000010110", 1, 6), ("0000000010101", 1, 7), ("0000000010100", 2, 5), ("0000000010011", 3, 4),
+	("0000000010010", 5, 3), ("0000000010001", 9, 2), ("0000000010000", 10, 2), ("0000000011111", 22, 1), ("0000000011110", 23, 1),
+	("0000000011101", 24, 1), ("0000000011100", 25, 1), ("0000000011011", 26, 1), ("00000000011111", 0, 16), ("00000000011110", 0, 17),
+	("00000000011101", 0, 18), ("00000000011100", 0, 19), ("00000000011011", 0, 20), ("00000000011010", 0, 21), ("00000000011001", 0, 22),
+	("00000000011000", 0, 23), ("00000000010111", 0, 24), ("00000000010110", 0, 25), ("00000000010101", 0, 26), ("00000000010100", 0, 27),
+	("00000000010011", 0, 28), ("00000000010010", 0, 29), ("00000000010001", 0, 30), ("00000000010000", 0, 31), ("000000000011000", 0, 32),
+	("000000000010111", 0, 33), ("000000000010110", 0, 34), ("000000000010101", 0, 35), ("000000000010100", 0, 36), ("000000000010011", 0, 37),
+	("000000000010010", 0, 38), ("000000000010001", 0, 39), ("000000000010000", 0, 40), ("000000000011111", 1, 8), ("000000000011110", 1, 9),
+	("000000000011101", 1, 10), ("000000000011100", 1, 11), ("000000000011011", 1, 12), ("000000000011010", 1, 13), ("000000000011001", 1, 14),
+	("0000000000010011", 1, 15), ("0000000000010010", 1, 16), ("0000000000010001", 1, 17), ("0000000000010000", 1, 18), ("0000000000010100", 6, 3),
+	("0000000000011010", 11, 2), ("0000000000011001", 12, 2), ("0000000000011000", 13, 2), ("0000000000010111", 14, 2), ("0000000000010110", 15, 2),
+	("0000000000010101", 16, 2), ("0000000000011111", 27, 1), ("0000000000011110", 28, 1), ("0000000000011101", 29, 1), ("0000000000011100", 30, 1),
+	("0000000000011011", 31, 1),
+];
+
+// v3 dc size codes for sizes 0..=8
+const LUMA_DC_CODES: [&str; 9] = ["100", "00", "01", "101", "110", "1110", "11110", "111110", "1111110"];
+const CHROMA_DC_CODES: [&str; 9] = ["00", "01", "10", "110", "1110", "11110", "111110", "1111110", "11111110"];
+
+#[derive(Debug, Clone, Copy)]
+enum AcCode {
+	Invalid,
+	EndOfBlock,
+	Escape,
+	RunLevel { run: u16, level: u16 },
+}
+
+// what a 16 bit peek at the bitstream decodes to, and the length of the code
+#[derive(Debug, Clone, Copy)]
+struct AcEntry {
+	code: AcCode,
+	len: u32,
+}
+
+pub struct MovieFrame {
+	pub number: u32,
+	pub width: usize,
+	pub height: usize,
+	pub rgb: Vec<u8>,
+	// index of the sector that completed the frame
+	pub sector: usize,
+	// how long the mdec took to decode the frame
+	pub decode_cycles: u64,
+}
+
+// chunks of the frame being demultiplexed
+struct PartialFrame {
+	number: u32,
+	width: usize,
+	height: usize,
+	data: Vec<u8>,
+	received: Vec<bool>,
+}
+
+pub struct StrDecoder {
+	mdec: Mdec,
+	scheduler: Scheduler,
+
+	ac_table: Vec<AcEntry>,
+
+	frame: Option<PartialFrame>,
+	sectors: usize,
+
+	xa_adpcm: XaAdpcmState,
+	// file and channel of the audio to keep, the first one seen if not set
+	audio_filter: Option<(u8, u8)>,
+	audio: Vec<(i16, i16)>,
+}
+
+impl StrDecoder {
+	pub fn new() -> Self {
+		let mut decoder = Self {
+			mdec: Mdec::new(),
+			scheduler: Scheduler::new(Box::new(|_| {})),
+
+			ac_table: build_ac_table(),
+
+			frame: None,
+			sectors: 0,
+
+			xa_adpcm: XaAdpcmState::new(),
+			audio_filter: None,
+			audio: Vec::new(),
+		};
+
+		decoder.upload_tables();
+		decoder
+	}
+
+	pub fn set_audio_channel(&mut self, file: u8, channel: u8) {
+		self.audio_filter = Some((file, channel));
+	}
+
+	// returns a frame once its last chunk comes in
+	pub fn push_sector(&mut self, sector: &Sector) -> Option<MovieFrame> {
+		let index = self.sectors;
+		self.sectors += 1;
+
+		let raw = sector.audio_sector();
+		let subheader = sector.subheader();
+
+		if raw[0xF] == 2 && subheader[2] & 0x04 != 0 {
+			let (file, channel) = (subheader[0], subheader[1]);
+
+			if *self.audio_filter.get_or_insert((file, channel)) == (file, channel) {
+				self.xa_adpcm.decode_xa_sector(sector);
+				self.audio.extend(self.xa_adpcm.take_samples());
+			}
+
+			return None;
+		}
+
+		let data = sector.data_only();
+
+		if read_u16(data, 0) != STR_MAGIC || read_u16(data, 2) != STR_TYPE_MDEC {
+			trace!("sector {index} isn't a video sector");
+			return None;
+		}
+
+		let chunk = usize::from(read_u16(data, 4));
+		let chunks = usize::from(read_u16(data, 6));
+		let number = read_u32(data, 8);
+		let width = usize::from(read_u16(data, 0x10));
+		let height = usize::from(read_u16(data, 0x12));
+
+		if chunk >= chunks {
+			warn!("sector {index}: chunk {chunk} of {chunks}");
+			return None;
+		}
+
+		// a frame that's still missing chunks is dropped when the next one starts
+		if self.frame.as_ref().is_none_or(|frame| frame.number != number) {
+			if let Some(frame) = self.frame.take() {
+				warn!("frame {} is missing chunks", frame.number);
+			}
+
+			self.frame = Some(PartialFrame {
+				number,
+				width,
+				height,
+				data: vec![0; chunks * CHUNK_DATA_LEN],
+				received: vec![false; chunks],
+			});
+		}
+
+		let frame = self.frame.as_mut().unwrap();
+
+		let Some(received) = frame.received.get_mut(chunk) else {
+			warn!("frame {number} chunk {chunk} is past the chunk count");
+			return None;
+		};
+
+		*received = true;
+		frame.data[chunk * CHUNK_DATA_LEN..(chunk + 1) * CHUNK_DATA_LEN].copy_from_slice(&data[CHUNK_HEADER_LEN..]);
+
+		if !frame.received.iter().all(|&received| received) {
+			return None;
+		}
+
+		let frame = self.frame.take().unwrap();
+		let codes = self.decode_bitstream(&frame.data, frame.width, frame.height)?;
+
+		let start_cycles = self.scheduler.cpu_cycle_counter;
+		let macroblocks = self.run_mdec(&codes);
+
+		trace!("frame {} {}x{}, {} mdec codes", frame.number, frame.width, frame.height, codes.len());
+
+		Some(MovieFrame {
+			number: frame.number,
+			width: frame.width,
+			height: frame.height,
+			rgb: assemble_frame(&macroblocks, frame.width, frame.height),
+			sector: index,
+			decode_cycles: self.scheduler.cpu_cycle_counter - start_cycles,
+		})
+	}
+
+	// 44.1khz stereo decoded since the last call
+	pub fn take_audio(&mut self) -> Vec<(i16, i16)> {
+		std::mem::take(&mut self.audio)
+	}
+
+	fn upload_tables(&mut self) {
+		// the mdec takes the quant table in zigzag order
+		let quant: Vec<u8> = (0..64).map(|k| QUANT_MATRIX[ZAGZIG[k]]).collect();
+
+		self.mdec.write32(MDEC_DATA, 0x4000_0001, &mut self.scheduler);
+
+		for _ in 0..2 {
+			for word in quant.chunks_exact(4) {
+				self.mdec.write32(MDEC_DATA, u32::from_le_bytes(word.try_into().unwrap()), &mut self.scheduler);
+			}
+		}
+
+		self.mdec.write32(MDEC_DATA, 0x6000_0000, &mut self.scheduler);
+
+		for pair in SCALE_TABLE.chunks_exact(2) {
+			self.mdec.write32(MDEC_DATA, u32::from(pair[0]) | u32::from(pair[1]) << 16, &mut self.scheduler);
+		}
+	}
+
+	// frame header is the number of mdec codes, 0x3800, the quant scale and the version, the
+	// bitstream follows as little endian halfwords read from the top bit down
+	fn decode_bitstream(&self, data: &[u8], width: usize, height: usize) -> Option<Vec<u16>> {
+		let magic = read_u16(data, 2);
+		let qscale = read_u16(data, 4);
+		let version = read_u16(data, 6);
+
+		if magic != 0x3800 || !(1..=3).contains(&version) {
+			warn!("unsupported frame header, magic 0x{magic:X} version {version}");
+			return None;
+		}
+
+		let macroblocks = width.div_ceil(16) * height.div_ceil(16);
+
+		let mut bits = BitReader::new(&data[8..]);
+		let mut codes = Vec::with_capacity(usize::from(read_u16(data, 0)) * 2);
+
+		// v3 dc values are differences from the last block of the same kind: Cr, Cb, Y
+		let mut prev_dc = [0i32; 3];
+
+		for macroblock in 0..macroblocks {
+			for block in 0..6 {
+				let dc = match version {
+					3 => {
+						let kind = block.min(2);
+						let size_codes = if kind == 2 { &LUMA_DC_CODES } else { &CHROMA_DC_CODES };
+
+						let size = size_codes.iter().position(|code| bits.matches(code))?;
+						bits.skip(size_codes[size].len() as u32);
+
+						let diff = match size {
+							0 => 0,
+							size => {
+								let value = bits.read(size as u32) as i32;
+
+								// the top bit clear means negative
+								match value >> (size - 1) {
+									0 => value - (1 << size) + 1,
+									_ => value,
+								}
+							},
+						};
+
+						prev_dc[kind] += diff * 4;
+
+						if !(-0x200..0x200).contains(&prev_dc[kind]) {
+							warn!("dc out of range in macroblock {macroblock}");
+							return None;
+						}
+
+						prev_dc[kind] as u16 & 0x3FF
+					},
+					_ => bits.read(10) as u16,
+				};
+
+				codes.push(qscale << 10 | dc);
+
+				loop {
+					let entry = self.ac_table[bits.peek(16) as usize];
+					bits.skip(entry.len);
+
+					match entry.code {
+						AcCode::EndOfBlock => break,
+						AcCode::Escape => codes.push(bits.read(16) as u16),
+						AcCode::RunLevel { run, level } => {
+							let level = match bits.read(1) {
+								0 => level,
+								_ => level.wrapping_neg(),
+							};
+
+							codes.push(run << 10 | (level & 0x3FF));
+						},
+						AcCode::Invalid => {
+							warn!("invalid ac code in macroblock {macroblock} block {block}");
+							return None;
+						},
+					}
+				}
+
+				codes.push(EOB);
+			}
+		}
+
+		// whole words, the padding is skipped between blocks
+		if codes.len() % 2 != 0 {
+			codes.push(EOB);
+		}
+
+		Some(codes)
+	}
+
+	// runs a 24 bit decode command, the output fifo is drained whenever it has something so the
+	// decoder never has to wait on it
+	fn run_mdec(&mut self, codes: &[u16]) -> Vec<u8> {
+		let mut out = Vec::new();
+
+		self.mdec.write32(MDEC_DATA, 0x3000_0000 | 2 << 27 | (codes.len() / 2) as u32, &mut self.scheduler);
+
+		for pair in codes.chunks_exact(2) {
+			// wait for room in the input fifo
+			while self.mdec.read32(MDEC_STATUS, &mut self.scheduler) & (1 << 30) != 0 && self.step_mdec(&mut out) {}
+
+			self.mdec.write32(MDEC_DATA, u32::from(pair[0]) | u32::from(pair[1]) << 16, &mut self.scheduler);
+		}
+
+		// until the command is done and the output is empty
+		while self.step_mdec(&mut out) || self.mdec.read32(MDEC_STATUS, &mut self.scheduler) & (1 << 31) == 0 {}
+
+		out
+	}
+
+	// reads out the output fifo and runs the next decode event, false when nothing is pending
+	fn step_mdec(&mut self, out: &mut Vec<u8>) -> bool {
+		while self.mdec.read32(MDEC_STATUS, &mut self.scheduler) & (1 << 31) == 0 {
+			out.extend_from_slice(&self.mdec.read32(MDEC_DATA, &mut self.scheduler).to_le_bytes());
+		}
+
+		let Some(event) = self.scheduler.get_event(EventType::MdecDecode).cloned() else {
+			return false;
+		};
+
+		self.scheduler.cpu_cycle_counter = self.scheduler.cpu_cycle_counter.max(event.cpu_timestamp);
+		self.scheduler.remove_event(EventType::MdecDecode);
+		self.mdec.decode_event(&mut self.scheduler);
+
+		true
+	}
+}
+
+impl Default for StrDecoder {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+// 2352 byte sectors, 2336 byte mode 2 sectors without the sync and header, or 2048 byte data
+// sectors holding only video. told apart by what the first sector starts with, the length only
+// decides when that's inconclusive
+pub fn str_file_sectors(data: &[u8]) -> Vec<Sector> {
+	let is_str_chunk = |offset: usize| {
+		data.len() >= offset + 4 && read_u16(data, offset) == STR_MAGIC && read_u16(data, offset + 2) == STR_TYPE_MDEC
+	};
+
+	// the subheader is stored twice
+	let is_subheader = data.len() >= 8 && data[0..4] == data[4..8];
+
+	let (sector_len, prefix_len) = if data.starts_with(&SECTOR_SYNC) {
+		(RAW_SECTOR_LEN, 0)
+	} else if is_str_chunk(0) {
+		(DATA_SECTOR_LEN, 0x18)
+	} else if is_str_chunk(8) || is_subheader
+		|| (data.len().is_multiple_of(MODE2_SECTOR_LEN) && !data.len().is_multiple_of(DATA_SECTOR_LEN)) {
+		(MODE2_SECTOR_LEN, RAW_SECTOR_LEN - MODE2_SECTOR_LEN)
+	} else {
+		(DATA_SECTOR_LEN, 0x18)
+	};
+
+	debug!("{sector_len} byte sectors");
+
+	data.chunks_exact(sector_len).map(|chunk| {
+		let mut sector = Vec::with_capacity(RAW_SECTOR_LEN);
+
+		sector.extend_from_slice(&SECTOR_SYNC);
+		sector.extend_from_slice(&[0, 0, 0, 2]);
+
+		// data only sectors get a form 1 data subheader
+		if prefix_len == 0x18 {
+			sector.extend_from_slice(&[0, 0, 0x08, 0, 0, 0, 0x08, 0]);
+		}
+
+		sector.truncate(prefix_len);
+		sector.extend_from_slice(chunk);
+		sector.resize(RAW_SECTOR_LEN, 0);
+
+		Sector::new(sector)
+	}).collect()
+}
+
+// an STR file in the disc filesystem
+pub fn disc_str_sectors(disc: &Disc, path: &str) -> Option<Vec<Sector>> {
+	let files = disc.iso_files();
+	let file = files.iter().find(|file| !file.is_dir && file.path.eq_ignore_ascii_case(path))?;
+
+	disc.read_iso_sectors(file.lba, file.len)
+}
+
+// frames per second as a fraction, from the number of sectors between frames
+pub fn frame_rate(sectors: &[Sector]) -> (u64, u64) {
+	let frame_starts: Vec<usize> = sectors.iter().enumerate()
+		.filter(|(_, sector)| {
+			let data = sector.data_only();
+
+			sector.subheader()[2] & 0x04 == 0
+				&& read_u16(data, 0) == STR_MAGIC
+				&& read_u16(data, 2) == STR_TYPE_MDEC
+				&& read_u16(data, 4) == 0
+		})
+		.map(|(index, _)| index)
+		.collect();
+
+	let (Some(first), Some(last)) = (frame_starts.first(), frame_starts.last()) else {
+		return (15, 1);
+	};
+
+	if first == last {
+		return (15, 1);
+	}
+
+	let (num, den) = (SECTORS_PER_SECOND * (frame_starts.len() as u64 - 1), (last - first) as u64);
+
+	let (mut a, mut b) = (num, den);
+
+	while b != 0 {
+		(a, b) = (b, a % b);
+	}
+
+	(num / a, den / a)
+}
+
+// macroblocks are stored column by column
+fn assemble_frame(macroblocks: &[u8], width: usize, height: usize) -> Vec<u8> {
+	let mut rgb = vec![0; width * height * 3];
+	let blocks_high = height.div_ceil(16);
+
+	for (i, macroblock) in macroblocks.chunks_exact(MACROBLOCK_LEN).enumerate() {
+		let (mx, my) = (i / blocks_high * 16, i % blocks_high * 16);
+
+		for y in 0..16 {
+			if my + y >= height {
+				break;
+			}
+
+			let copy_width = 16.min(width.saturating_sub(mx));
+
+			let src = y * 16 * 3;
+			let dst = ((my + y) * width + mx) * 3;
+
+			rgb[dst..dst + copy_width * 3].copy_from_slice(&macroblock[src..src + copy_width * 3]);
+		}
+	}
+
+	rgb
+}
+
+fn build_ac_table() -> Vec<AcEntry> {
+	let mut table = vec![AcEntry { code: AcCode::Invalid, len: 16 }; 0x10000];
+
+	let codes = AC_CODES.iter()
+		.map(|&(bits, run, level)| (bits, AcCode::RunLevel { run, level }))
+		.chain([("10", AcCode::EndOfBlock), ("000001", AcCode::Escape)]);
+
+	for (bits, code) in codes {
+		let len = bits.len() as u32;
+		let prefix = u32::from_str_radix(bits, 2).unwrap() << (16 - len);
+
+		for suffix in 0..1 << (16 - len) {
+			table[(prefix | suffix) as usize] = AcEntry { code, len };
+		}
+	}
+
+	table
+}
+
+// msb first over little endian halfwords, reads past the end give zeroes
+struct BitReader<'a> {
+	data: &'a [u8],
+	pos: usize,
+	// the next bits, from the top down
+	cache: u64,
+	bits: u32,
+}
+
+impl<'a> BitReader<'a> {
+	fn new(data: &'a [u8]) -> Self {
+		let mut reader = Self {
+			data,
+			pos: 0,
+			cache: 0,
+			bits: 0,
+		};
+
+		reader.refill();
+		reader
+	}
+
+	fn refill(&mut self) {
+		while self.bits <= 48 {
+			let halfword = match self.data.get(self.pos..self.pos + 2) {
+				Some(bytes) => u16::from_le_bytes([bytes[0], bytes[1]]),
+				None => 0,
+			};
+
+			self.cache |= u64::from(halfword) << (48 - self.bits);
+			self.bits += 16;
+			self.pos += 2;
+		}
+	}
+
+	fn peek(&self, len: u32) -> u32 {
+		(self.cache >> (64 - len)) as u32
+	}
+
+	fn skip(&mut self, len: u32) {
+		self.cache <<= len;
+		self.bits -= len;
+		self.refill();
+	}
+
+	fn read(&mut self, len: u32) -> u32 {
+		let value = self.peek(len);
+		self.skip(len);
+		value
+	}
+
+	// whether the next bits are the given code
+	fn matches(&self, code: &str) -> bool {
+		self.peek(code.len() as u32) == u32::from_str_radix(code, 2).unwrap()
+	}
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+	u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+	u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// packs a string of bits msb first into little endian halfwords, like the frame bitstream
+	fn pack_bits(bits: &str) -> Vec<u8> {
+		let bits: Vec<u16> = bits.chars().filter(|c| !c.is_whitespace()).map(|c| u16::from(c == '1')).collect();
+
+		bits.chunks(16)
+			.flat_map(|chunk| chunk.iter().enumerate().fold(0u16, |word, (i, &bit)| word | bit << (15 - i)).to_le_bytes())
+			.collect()
+	}
+
+	fn frame_data(qscale: u16, version: u16, bits: &str) -> Vec<u8> {
+		let mut data = Vec::new();
+
+		data.extend_from_slice(&0u16.to_le_bytes());
+		data.extend_from_slice(&0x3800u16.to_le_bytes());
+		data.extend_from_slice(&qscale.to_le_bytes());
+		data.extend_from_slice(&version.to_le_bytes());
+		data.extend(pack_bits(bits));
+
+		data
+	}
+
+	// 2048 byte video sector holding chunk of frame
+	fn video_chunk(chunk: u16, chunks: u16, frame: u32) -> Vec<u8> {
+		let mut data = vec![0; DATA_SECTOR_LEN];
+
+		data[0..2].copy_from_slice(&STR_MAGIC.to_le_bytes());
+		data[2..4].copy_from_slice(&STR_TYPE_MDEC.to_le_bytes());
+		data[4..6].copy_from_slice(&chunk.to_le_bytes());
+		data[6..8].copy_from_slice(&chunks.to_le_bytes());
+		data[8..12].copy_from_slice(&frame.to_le_bytes());
+		data[0x10..0x12].copy_from_slice(&16u16.to_le_bytes());
+		data[0x12..0x14].copy_from_slice(&16u16.to_le_bytes());
+
+		data
+	}
+
+	#[test]
+	fn v2_bitstream_to_rle() {
+		// dc, (0, 1), (1, -1), an escaped code and the end of block, then 5 blocks with only a dc
+		let bits = format!(
+			"0101010101 11 0 011 1 000001 0001001000110100 10 {}",
+			"0000000000 10 ".repeat(5)
+		);
+
+		let codes = StrDecoder::new().decode_bitstream(&frame_data(2, 2, &bits), 16, 16).unwrap();
+
+		let mut expected = vec![0x0800 | 0x155, 0x0001, 0x0400 | 0x3FF, 0x1234, EOB];
+		expected.extend([0x0800, EOB].repeat(5));
+		// padded to whole words
+		expected.push(EOB);
+
+		assert_eq!(codes, expected);
+	}
+
+	#[test]
+	fn v3_bitstream_to_rle() {
+		// dc differences are per kind, Cr and Cb use the chroma codes and the 4 Y blocks the luma
+		// ones. Cr +3, Cb 0, Y -1, Y 0 with an ac of (0, 2), Y -2, Y +4
+		let bits = "10 11 10  00 10  00 0 10  100 0100 0 10  01 01 10  101 100 10";
+
+		let codes = StrDecoder::new().decode_bitstream(&frame_data(1, 3, bits), 16, 16).unwrap();
+
+		let dc = |value: i32| 0x0400 | (value as u16 & 0x3FF);
+
+		assert_eq!(codes, vec![
+			dc(12), EOB,
+			dc(0), EOB,
+			dc(-4), EOB,
+			dc(-4), 0x0002, EOB,
+			dc(-12), EOB,
+			dc(4), EOB,
+			EOB,
+		]);
+	}
+
+	#[test]
+	fn invalid_frame_header() {
+		assert!(StrDecoder::new().decode_bitstream(&frame_data(1, 4, "10"), 16, 16).is_none());
+	}
+
+	#[test]
+	fn frame_rate_from_sectors() {
+		// a new frame every 8 sectors at 150 sectors per second
+		let data: Vec<u8> = (0..32).flat_map(|i| video_chunk(i % 8, 8, u32::from(i / 8))).collect();
+
+		assert_eq!(frame_rate(&str_file_sectors(&data)), (75, 4));
+
+		// a single frame falls back to 15fps
+		assert_eq!(frame_rate(&str_file_sectors(&video_chunk(0, 1, 0))), (15, 1));
+	}
+
+	#[test]
+	fn mode2_sectors_with_ambiguous_length() {
+		// 64 sectors of 2336 bytes are also a whole number of 2048 byte sectors
+		let data: Vec<u8> = (0..64).flat_map(|i| {
+			let mut sector = vec![1, 1, 0x48, 0, 1, 1, 0x48, 0];
+			sector.extend(video_chunk(i % 8, 8, u32::from(i / 8)));
+			sector.resize(MODE2_SECTOR_LEN, 0);
+			sector
+		}).collect();
+
+		assert!(data.len().is_multiple_of(DATA_SECTOR_LEN));
+
+		let sectors = str_file_sectors(&data);
+
+		assert_eq!(sectors.len(), 64);
+		assert_eq!(sectors[9].subheader(), [1, 1, 0x48, 0]);
+		assert_eq!(read_u16(sectors[9].data_only(), 4), 1);
+	}
+
+	#[test]
+	fn xa_block_decode() {
+		let mut sector = vec![0; RAW_SECTOR_LEN];
+
+		sector[..12].copy_from_slice(&SECTOR_SYNC);
+		sector[0xF] = 2;
+		// audio and realtime, 4 bit stereo at 37.8khz
+		sector[0x10..0x18].copy_from_slice(&[1, 0, 0x64, 0x01, 1, 0, 0x64, 0x01]);
+
+		// filter 0 and shift 0 for every unit, left nibbles are 1 and right nibbles -1
+		for group in sector[0x18..].chunks_exact_mut(128).take(18) {
+			group[..16].fill(0);
+			group[16..].fill(0xF1);
+		}
+
+		let mut decoder = StrDecoder::new();
+
+		assert!(decoder.push_sector(&Sector::new(sector)).is_none());
+
+		let audio = decoder.take_audio();
+
+		// 18 groups of 4 units of 28 samples per channel, 6 samples at 37.8khz are 7 at 44.1khz
+		assert_eq!(audio.len(), 18 * 4 * 28 / 6 * 7);
+
+		// the interpolation doesn't have unity gain, the channels only have to stay apart
+		for &(left, right) in &audio[64..] {
+			assert!(left > 0xE00 && right < -0xE00, "{left} {right}");
+		}
+	}
+}